@@ -1,6 +1,6 @@
 import { Router } from 'express';
 import { z } from 'zod';
-import { Connection, PublicKey } from '@solana/web3.js';
+import { Connection, PublicKey, Keypair } from '@solana/web3.js';
 import { buildSettleTransaction, getSettleAccounts } from '../services/transaction-builder';
 import { getMerchantConfigById } from '../services/database';
 import BNjs from 'bn.js';
@@ -10,6 +10,7 @@ const router = Router();
 const buildTxRequestSchema = z.object({
   quoteId: z.string().uuid(),
   merchantId: z.string(),
+  merchantHandle: z.string().optional(), // Set when checkout was opened by handle
   payer: z.string(),
   amount: z.string(),
   payToken: z.enum(['SOL', 'USDC']),
@@ -18,6 +19,18 @@ const buildTxRequestSchema = z.object({
   buybackBps: z.number().min(0).max(10000),
   burnBps: z.number().min(0).max(10000),
   swapTransaction: z.string().optional(), // Base64 encoded Jupiter swap transaction
+  payoutSwapTransaction: z.string().optional(), // Base64 Jupiter swap converting the payout
+  payoutMinOut: z.string().optional(),
+  inputSwapTransaction: z.string().optional(), // Base64 Jupiter swap from the payer's token into USDC
+  inputMint: z.string().optional(),
+  maxAmountIn: z.string().optional(),
+  reference: z.string().optional(), // Solana Pay reference public key (order reference)
+  memo: z.string().max(64).optional(),
+  createReceipt: z.boolean().optional(), // Write an on-chain Receipt keyed by reference
+  quoteSignature: z.string().optional(), // Base64 ed25519 signature from the merchant's quote signer
+  quoteExpiresAt: z.number().int().optional(),
+  sponsored: z.boolean().optional(), // Gasless USDC: the relayer pays fees and rent
+  tip: z.string().optional(), // Gratuity in base units, paid to the merchant's tip wallet
   priorityFee: z.number().optional(),
 });
 
@@ -47,6 +60,18 @@ router.post('/', async (req, res) => {
       });
     }
 
+    // Relayer for gasless checkout (co-signs as fee payer and rent sponsor)
+    let relayer: Keypair | null = null;
+    if (body.sponsored) {
+      if (!process.env.RELAYER_PRIVATE_KEY) {
+        return res.status(503).json({ error: 'Sponsored payments are not enabled' });
+      }
+      if (body.payToken !== 'USDC' || body.inputSwapTransaction) {
+        return res.status(400).json({ error: 'Sponsored payments must be paid directly in USDC' });
+      }
+      relayer = Keypair.fromSecretKey(Buffer.from(process.env.RELAYER_PRIVATE_KEY, 'base64'));
+    }
+
     // Get all required accounts
     const accounts = await getSettleAccounts(
       connection,
@@ -73,6 +98,13 @@ router.post('/', async (req, res) => {
         payoutWallet: accounts.merchant.payoutWallet,
         buybackMint: accounts.merchant.buybackMint,
         vaultBuybackToken: accounts.merchant.vaultBuybackToken,
+        buybackPriceFeed: accounts.merchant.buybackPriceFeed,
+        settlementMint: accounts.merchant.settlementMint,
+        limitsEnabled: accounts.merchant.limitsEnabled,
+        payerListEnabled: accounts.merchant.payerListEnabled,
+        quoteSigner: accounts.merchant.quoteSigner,
+        tipWallet: accounts.merchant.tipWallet,
+        handle: body.merchantHandle,
       },
       protocol: {
         protocolConfigPDA: accounts.protocol.protocolConfigPDA,
@@ -80,14 +112,40 @@ router.post('/', async (req, res) => {
         vaultUsdcPDA: accounts.protocol.vaultUsdcPDA,
         protocolWallet: accounts.protocol.protocolWallet,
         jupiterRouter: accounts.protocol.jupiterRouter,
+        solPriceFeed: accounts.protocol.solPriceFeed,
+        usdcPriceFeed: accounts.protocol.usdcPriceFeed,
+        denylistEnabled: accounts.protocol.denylistEnabled,
       },
       jupiterQuote: body.swapTransaction ? {
         quote: {}, // Quote data not needed for account extraction
         swapTransaction: body.swapTransaction,
       } : undefined,
+      payoutSwap: body.payoutSwapTransaction && body.payoutMinOut ? {
+        swapTransaction: body.payoutSwapTransaction,
+        minOut: body.payoutMinOut,
+      } : undefined,
+      inputSwap: body.inputSwapTransaction && body.inputMint && body.maxAmountIn ? {
+        swapTransaction: body.inputSwapTransaction,
+        inputMint: new PublicKey(body.inputMint),
+        maxAmountIn: body.maxAmountIn,
+      } : undefined,
+      orderReference: body.reference ? new PublicKey(body.reference) : undefined,
+      memo: body.memo,
+      createReceipt: body.createReceipt,
+      signedQuote: body.quoteSignature && body.quoteExpiresAt !== undefined ? {
+        signature: Buffer.from(body.quoteSignature, 'base64'),
+        expiresAt: body.quoteExpiresAt,
+      } : undefined,
+      sponsor: relayer?.publicKey,
+      beneficiary: relayer ? new PublicKey(body.payer) : undefined,
+      tip: body.tip,
       priorityFee: body.priorityFee,
     });
 
+    if (relayer) {
+      transaction.sign([relayer]);
+    }
+
     // Serialize transaction
     const serialized = transaction.serialize({
       requireAllSignatures: false,
@@ -10,11 +10,13 @@ import {
   ASSOCIATED_TOKEN_PROGRAM_ID,
 } from '@solana/spl-token';
 import { AnchorProvider, Wallet } from '@coral-xyz/anchor';
-import { createHash } from 'crypto';
-import BNjs from 'bn.js';
 import { getProgram, createProgramWithProvider } from '../services/program';
 import { 
+  deriveMerchantId,
   deriveMerchantRegistryPDA, 
+  deriveMerchantHandlePDA,
+  deriveMerchantMetadataPDA,
+  normalizeHandle,
   deriveProtocolConfigPDA,
   getUsdcMint,
 } from '../services/pda';
@@ -35,25 +37,6 @@ const registerRequestSchema = z.object({
   webhookUrl: z.string().url().optional(),
 });
 
-/**
- * Generate merchant ID (hash-based)
- */
-function generateMerchantId(merchantOwner: string): string {
-  const hash = createHash('sha256')
-    .update(
-      Buffer.from(
-        merchantOwner +
-        Date.now().toString() +
-        Math.random().toString()
-      )
-    )
-    .digest();
-  
-  // Take first 8 bytes and convert to u64
-  const merchantIdBN = new BNjs(hash.slice(0, 8), 'le');
-  return merchantIdBN.toString();
-}
-
 router.post('/register', async (req, res) => {
   try {
     const body = registerRequestSchema.parse(req.body);
@@ -86,8 +69,9 @@ router.post('/register', async (req, res) => {
     const payoutWalletPubkey = new PublicKey(body.payoutWallet);
     const buybackMintPubkey = new PublicKey(body.buybackMint);
 
-    // Generate merchant ID
-    const merchantId = generateMerchantId(body.merchantOwner);
+    // Derive merchant ID from owner + nonce (register_merchant verifies it on-chain)
+    const merchantNonce = Date.now().toString();
+    const merchantId = deriveMerchantId(merchantOwnerPubkey, merchantNonce);
 
     // Derive merchant registry PDA
     const [merchantRegistryPDA] = deriveMerchantRegistryPDA(merchantId);
@@ -164,6 +148,7 @@ router.post('/register', async (req, res) => {
 
     res.json({
       merchantId: merchant.merchant_id,
+      merchantNonce, // Pass to register_merchant alongside merchantId
       merchantRegistryPDA: merchant.merchant_registry_pda,
       vaultBuybackToken: merchant.vault_buyback_token,
       status: 'registered',
@@ -181,15 +166,64 @@ router.post('/register', async (req, res) => {
 
 router.get('/:merchantId', async (req, res) => {
   try {
-    const { merchantId } = req.params;
+    // Accept either a numeric merchant_id or a handle (e.g. /v2/merchants/acme-coffee)
+    let merchantId = req.params.merchantId;
+    let handle: string | null = null;
+
+    const connection = new Connection(
+      process.env.RPC_URL || 'https://api.devnet.solana.com',
+      'confirmed'
+    );
+    const program = getProgram(connection);
+
+    if (!/^\d+$/.test(merchantId)) {
+      handle = normalizeHandle(merchantId);
+      if (!handle) {
+        return res.status(400).json({ error: 'Invalid merchant handle' });
+      }
+
+      // The on-chain handle registry is the source of truth for handle -> merchant_id
+      const [handlePDA] = deriveMerchantHandlePDA(handle);
+      const merchantHandle = await program.account.merchantHandle.fetchNullable(handlePDA);
+      if (!merchantHandle) {
+        return res.status(404).json({ error: 'Merchant handle not found' });
+      }
+      merchantId = merchantHandle.merchantId.toString();
+    }
 
     const merchant = await getMerchantConfigById(merchantId);
     if (!merchant) {
       return res.status(404).json({ error: 'Merchant not found' });
     }
 
+    // Display metadata is published on-chain by the merchant owner (optional)
+    const [metadataPDA] = deriveMerchantMetadataPDA(merchant.merchant_id);
+    const metadata = await program.account.merchantMetadata.fetchNullable(metadataPDA);
+
+    // Settlement totals are kept on the registry by settle
+    const [merchantRegistryPDA] = deriveMerchantRegistryPDA(merchant.merchant_id);
+    const registry = await program.account.merchantRegistry.fetchNullable(merchantRegistryPDA);
+
     res.json({
       merchantId: merchant.merchant_id,
+      handle,
+      metadata: metadata ? {
+        name: metadata.name,
+        uri: metadata.uri,
+        website: metadata.website,
+        supportContact: metadata.supportContact,
+        categoryCode: metadata.categoryCode,
+        termsHash: Buffer.from(metadata.termsHash as number[]).toString('hex'),
+      } : null,
+      stats: registry ? {
+        paymentCount: registry.paymentCount.toString(),
+        volumeSol: registry.volumeSol.toString(),
+        volumeUsdc: registry.volumeUsdc.toString(),
+        feesSol: registry.feesSol.toString(),
+        feesUsdc: registry.feesUsdc.toString(),
+        totalBoughtBack: registry.totalBoughtBack.toString(),
+        totalBurned: registry.totalBurned.toString(),
+      } : null,
       merchantOwner: merchant.owner_pubkey,
       merchantRegistryPDA: merchant.merchant_registry_pda,
       payoutWallet: merchant.payout_wallet,
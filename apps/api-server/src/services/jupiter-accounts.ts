@@ -75,6 +75,29 @@ export function extractJupiterSwapAccounts(
   }
 }
 
+/**
+ * Extract the instruction data of the Jupiter swap instruction
+ *
+ * The settle instruction forwards this data to the router CPI; its first
+ * 8 bytes must be an entrypoint allowlisted in the on-chain router registry.
+ */
+export function extractJupiterSwapData(
+  swapTransactionBase64: string,
+  jupiterRouterProgramId: PublicKey
+): Buffer {
+  const swapTx = VersionedTransaction.deserialize(Buffer.from(swapTransactionBase64, 'base64'));
+  const message = TransactionMessage.decompile(swapTx.message);
+
+  const swapInstruction = message.instructions.find(
+    (instruction) => instruction.programId.toBase58() === jupiterRouterProgramId.toBase58()
+  );
+  if (!swapInstruction) {
+    throw new Error('Jupiter swap instruction not found in swap transaction');
+  }
+
+  return Buffer.from(swapInstruction.data);
+}
+
 /**
  * Extract account metas from multiple Jupiter swap transactions
  * (For multi-hop swaps like USDC → SOL → buyback_token)
@@ -0,0 +1,60 @@
+import { Ed25519Program, Keypair, PublicKey, TransactionInstruction } from '@solana/web3.js';
+import BN from 'bn.js';
+
+/**
+ * Domain prefix of a signed payment quote (must match QUOTE_DOMAIN on-chain)
+ */
+export const QUOTE_DOMAIN = Buffer.from('qantara-quote-v1');
+
+/**
+ * Native mint, used as the quote mint for SOL payments
+ */
+export const NATIVE_MINT = new PublicKey('So11111111111111111111111111111111111111112');
+
+export interface PaymentQuote {
+  merchantId: BN | string | number;
+  amount: BN | string | number; // Base units, or micro-USD for settle_usd
+  mint: PublicKey; // NATIVE_MINT for SOL, the USDC mint for USDC, PublicKey.default for USD
+  orderReference: PublicKey;
+  expiresAt: number; // Unix timestamp (seconds)
+}
+
+/**
+ * Message a merchant's quote signer signs: QUOTE_DOMAIN || borsh(PaymentQuote)
+ */
+export function encodePaymentQuote(quote: PaymentQuote): Buffer {
+  return Buffer.concat([
+    QUOTE_DOMAIN,
+    new BN(quote.merchantId.toString()).toArrayLike(Buffer, 'le', 8),
+    new BN(quote.amount.toString()).toArrayLike(Buffer, 'le', 8),
+    quote.mint.toBuffer(),
+    quote.orderReference.toBuffer(),
+    new BN(quote.expiresAt).toTwos(64).toArrayLike(Buffer, 'le', 8),
+  ]);
+}
+
+/**
+ * Sign a quote (for merchant backends holding the quote signer key)
+ */
+export function signPaymentQuote(signer: Keypair, quote: PaymentQuote): TransactionInstruction {
+  return Ed25519Program.createInstructionWithPrivateKey({
+    privateKey: signer.secretKey,
+    message: encodePaymentQuote(quote),
+  });
+}
+
+/**
+ * ed25519 verify instruction for a quote signed elsewhere
+ * (must be placed directly before the settle instruction)
+ */
+export function buildQuoteInstruction(
+  signer: PublicKey,
+  signature: Uint8Array,
+  quote: PaymentQuote
+): TransactionInstruction {
+  return Ed25519Program.createInstructionWithPublicKey({
+    publicKey: signer.toBytes(),
+    message: encodePaymentQuote(quote),
+    signature,
+  });
+}
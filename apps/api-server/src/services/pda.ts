@@ -1,5 +1,6 @@
 import { PublicKey } from '@solana/web3.js';
 import BN from 'bn.js';
+import { createHash } from 'crypto';
 
 /**
  * V2 Program ID
@@ -28,6 +29,26 @@ export function deriveProtocolConfigPDA(programId: PublicKey = QANTARA_V2_PROGRA
   );
 }
 
+/**
+ * Number of protocol stats shards (must match PROTOCOL_STATS_SHARDS on-chain)
+ */
+export const PROTOCOL_STATS_SHARDS = 16;
+
+/**
+ * Derive the protocol stats shard PDA (settlement totals) that records a merchant's settlements
+ * Protocol-wide totals are the sum of all shards
+ */
+export function deriveProtocolStatsPDA(
+  merchantId: BN | string | number,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const shard = new BN(merchantId.toString()).modn(PROTOCOL_STATS_SHARDS);
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('stats'), Buffer.from([shard])],
+    programId
+  );
+}
+
 /**
  * Derive vault SOL PDA
  */
@@ -51,6 +72,22 @@ export function deriveVaultUsdcPDA(
   );
 }
 
+/**
+ * Derive merchant_id from owner and nonce (must match the on-chain derivation)
+ * merchant_id = sha256("qantara:merchant_id" || owner || nonce_le)[0..8] as u64 LE
+ */
+export function deriveMerchantId(owner: PublicKey, nonce: BN | string | number): string {
+  const nonceBN = new BN(nonce.toString());
+  const hash = createHash('sha256')
+    .update(Buffer.concat([
+      Buffer.from('qantara:merchant_id'),
+      owner.toBuffer(),
+      nonceBN.toArrayLike(Buffer, 'le', 8),
+    ]))
+    .digest();
+  return new BN(hash.subarray(0, 8), 'le').toString();
+}
+
 /**
  * Derive merchant registry PDA
  */
@@ -70,6 +107,251 @@ export function deriveMerchantRegistryPDA(
   );
 }
 
+/**
+ * Derive merchant metadata PDA
+ */
+export function deriveMerchantMetadataPDA(
+  merchantId: BN | string | number,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('metadata'), merchantIdBN.toArrayLike(Buffer, 'le', 8)],
+    programId
+  );
+}
+
+/**
+ * Derive merchant limits PDA
+ */
+export function deriveMerchantLimitsPDA(
+  merchantId: BN | string | number,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('limits'), merchantIdBN.toArrayLike(Buffer, 'le', 8)],
+    programId
+  );
+}
+
+/**
+ * Derive a payer's protocol denylist entry PDA
+ */
+export function deriveProtocolPayerEntryPDA(
+  payer: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('payer_list'), payer.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Derive a payer's entry PDA on a merchant's denylist/allowlist
+ */
+export function deriveMerchantPayerEntryPDA(
+  merchantId: BN | string | number,
+  payer: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('payer_list'), merchantIdBN.toArrayLike(Buffer, 'le', 8), payer.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Derive an authorization PDA (held funds awaiting capture or void)
+ */
+export function deriveAuthorizationPDA(
+  merchantId: BN | string | number,
+  authorizationId: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('authorization'), merchantIdBN.toArrayLike(Buffer, 'le', 8), authorizationId.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Derive the USDC escrow token account of an authorization
+ */
+export function deriveAuthorizationEscrowPDA(
+  authorization: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('escrow'), authorization.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Derive an installment plan PDA
+ */
+export function deriveInstallmentPlanPDA(
+  merchantId: BN | string | number,
+  planId: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('installments'), merchantIdBN.toArrayLike(Buffer, 'le', 8), planId.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Order reference of installment `index` of a plan (mirrors installment_reference on-chain):
+ * sha256("qantara:installment" || plan_id || index_le16)
+ */
+export function deriveInstallmentReference(planId: PublicKey, index: number): PublicKey {
+  const indexBytes = Buffer.alloc(2);
+  indexBytes.writeUInt16LE(index);
+  return new PublicKey(
+    createHash('sha256')
+      .update(Buffer.from('qantara:installment'))
+      .update(planId.toBuffer())
+      .update(indexBytes)
+      .digest()
+  );
+}
+
+/**
+ * Derive a merchant's tip policy PDA (tip wallet and fee policy)
+ */
+export function deriveTipPolicyPDA(
+  merchantId: BN | string | number,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('tips'), merchantIdBN.toArrayLike(Buffer, 'le', 8)],
+    programId
+  );
+}
+
+/**
+ * Derive a merchant's donation settings PDA (minimums and suggested tiers)
+ */
+export function deriveDonationSettingsPDA(
+  merchantId: BN | string | number,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('donations'), merchantIdBN.toArrayLike(Buffer, 'le', 8)],
+    programId
+  );
+}
+
+/**
+ * Derive a group invoice PDA (one order funded by several payers)
+ */
+export function deriveGroupInvoicePDA(
+  merchantId: BN | string | number,
+  invoiceId: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('group_invoice'), merchantIdBN.toArrayLike(Buffer, 'le', 8), invoiceId.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Derive a payer's contribution record for a group invoice
+ * (the invoice's USDC escrow derives like an authorization's: deriveAuthorizationEscrowPDA(groupInvoice))
+ */
+export function deriveContributionPDA(
+  groupInvoice: PublicKey,
+  contributor: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('contribution'), groupInvoice.toBuffer(), contributor.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * UTC day index used by MerchantDayStats buckets (days since the unix epoch)
+ */
+export function currentDayIndex(now: number = Date.now()): number {
+  return Math.floor(now / 1000 / 86400);
+}
+
+/**
+ * Derive a merchant's day stats PDA (defaults to the current UTC day)
+ */
+export function deriveMerchantDayStatsPDA(
+  merchantId: BN | string | number,
+  day: number = currentDayIndex(),
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('day_stats'), merchantIdBN.toArrayLike(Buffer, 'le', 8), new BN(day).toArrayLike(Buffer, 'le', 4)],
+    programId
+  );
+}
+
+/**
+ * Derive receipt PDA for a merchant's order reference
+ */
+export function deriveReceiptPDA(
+  merchantId: BN | string | number,
+  orderReference: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  const merchantIdBN = new BN(merchantId.toString());
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('receipt'), merchantIdBN.toArrayLike(Buffer, 'le', 8), orderReference.toBuffer()],
+    programId
+  );
+}
+
+/**
+ * Normalize a merchant handle the way the program expects it ("Acme-Coffee " -> "acme-coffee")
+ * Returns null if the result is not a valid handle (3-32 chars of a-z, 0-9, '-', no edge '-')
+ */
+export function normalizeHandle(handle: string): string | null {
+  const normalized = handle.trim().toLowerCase();
+  return /^[a-z0-9](?:[a-z0-9-]{1,30})[a-z0-9]$/.test(normalized) ? normalized : null;
+}
+
+/**
+ * Derive merchant handle PDA (handle must already be normalized)
+ */
+export function deriveMerchantHandlePDA(
+  handle: string,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('handle'), Buffer.from(handle)],
+    programId
+  );
+}
+
+/**
+ * Derive router registry PDA for a swap router program
+ */
+export function deriveSwapRouterPDA(
+  routerProgram: PublicKey,
+  programId: PublicKey = QANTARA_V2_PROGRAM_ID
+): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('router'), routerProgram.toBuffer()],
+    programId
+  );
+}
+
 /**
  * Get USDC mint based on cluster
  */
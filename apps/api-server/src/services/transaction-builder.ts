@@ -4,19 +4,31 @@ import {
   VersionedTransaction,
   ComputeBudgetProgram,
   TransactionMessage,
+  AccountMeta,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
 } from '@solana/web3.js';
 import { Program } from '@coral-xyz/anchor';
 import { TOKEN_PROGRAM_ID, getAssociatedTokenAddressSync, ASSOCIATED_TOKEN_PROGRAM_ID } from '@solana/spl-token';
 import { SystemProgram } from '@solana/web3.js';
 import { getProgram } from './program';
+import { buildQuoteInstruction, NATIVE_MINT } from './payment-quote';
 import {
   deriveProtocolAccounts,
   deriveMerchantAccounts,
   getUsdcMint,
+  deriveSwapRouterPDA,
+  deriveMerchantHandlePDA,
+  deriveReceiptPDA,
+  deriveMerchantDayStatsPDA,
+  deriveProtocolStatsPDA,
+  deriveMerchantLimitsPDA,
+  deriveProtocolPayerEntryPDA,
+  deriveMerchantPayerEntryPDA,
+  deriveTipPolicyPDA,
   QANTARA_V2_PROGRAM_ID,
 } from './pda';
 import { getJupiterSwapTransaction, SOL_MINT } from './jupiter';
-import { extractJupiterSwapAccounts, extractMultiHopSwapAccounts, filterDuplicateAccounts } from './jupiter-accounts';
+import { extractJupiterSwapAccounts, extractJupiterSwapData, extractMultiHopSwapAccounts, filterDuplicateAccounts } from './jupiter-accounts';
 import BNjs from 'bn.js';
 import { BN } from '@coral-xyz/anchor';
 
@@ -35,6 +47,13 @@ export interface BuildSettleTransactionParams {
     payoutWallet: PublicKey;
     buybackMint: PublicKey;
     vaultBuybackToken: PublicKey;
+    buybackPriceFeed?: PublicKey;
+    settlementMint?: PublicKey;
+    handle?: string; // Handle the payer resolved the merchant from (checked on-chain)
+    limitsEnabled?: boolean; // Registry requires its MerchantLimits account in settle
+    payerListEnabled?: boolean; // Merchant has denylist entries or an allowlist
+    quoteSigner?: PublicKey; // Set when settle must carry a quote signed by this key
+    tipWallet?: PublicKey; // Tip recipient from the merchant's TipPolicy (unset = no tips)
   };
   protocol: {
     protocolConfigPDA: PublicKey;
@@ -42,11 +61,33 @@ export interface BuildSettleTransactionParams {
     vaultUsdcPDA: PublicKey;
     protocolWallet: PublicKey;
     jupiterRouter: PublicKey;
+    solPriceFeed?: PublicKey;
+    usdcPriceFeed?: PublicKey;
+    denylistEnabled?: boolean; // Protocol denylist is non-empty
   };
   jupiterQuote?: {
     quote: any;
     swapTransaction?: string;
   };
+  payoutSwap?: {
+    swapTransaction: string; // Jupiter swap converting the payout into the settlement currency
+    minOut: BNjs | BN | string | number;
+  };
+  inputSwap?: {
+    swapTransaction: string; // Jupiter swap from the payer's token into vault_usdc
+    inputMint: PublicKey;
+    maxAmountIn: BNjs | BN | string | number;
+  };
+  orderReference?: PublicKey; // Solana Pay reference (stored in PaymentSettled, added read-only)
+  memo?: string; // Up to 64 bytes, stored in PaymentSettled
+  createReceipt?: boolean; // Write a Receipt PDA for orderReference (payer funds the rent)
+  signedQuote?: {
+    signature: Uint8Array; // quoteSigner's ed25519 signature over the encoded quote
+    expiresAt: number;
+  };
+  sponsor?: PublicKey; // Relayer paying fees and rent (settle_sponsored, USDC only)
+  beneficiary?: PublicKey; // Customer credited with a sponsored payment
+  tip?: BNjs | BN | string | number; // Gratuity on top of amount, paid to the merchant's tip wallet
   priorityFee?: number;
 }
 
@@ -69,6 +110,15 @@ export async function buildSettleTransaction(
     merchant,
     protocol,
     jupiterQuote,
+    payoutSwap,
+    inputSwap,
+    orderReference,
+    memo,
+    createReceipt,
+    signedQuote,
+    sponsor,
+    beneficiary,
+    tip,
     priorityFee,
   } = params;
 
@@ -100,6 +150,10 @@ export async function buildSettleTransaction(
     connection.rpcEndpoint.includes('mainnet') ? 'mainnet' : 'devnet'
   );
 
+  if (inputSwap && payToken !== 'USDC') {
+    throw new Error('Swap-in payments settle in USDC');
+  }
+
   // Get payer's USDC account (if USDC payment)
   let payerUsdcAccount: PublicKey | null = null;
   if (payToken === 'USDC') {
@@ -130,22 +184,170 @@ export async function buildSettleTransaction(
     ASSOCIATED_TOKEN_PROGRAM_ID
   );
 
+  // Router registry entry for the router used by this settlement
+  const [swapRouterPDA] = deriveSwapRouterPDA(protocol.jupiterRouter);
+
+  // Swap instruction data forwarded to the router CPI (empty when there is no buyback swap)
+  const hasBuybackSwap = buybackBps > 0 && !!jupiterQuote?.swapTransaction;
+  const swapData = hasBuybackSwap
+    ? extractJupiterSwapData(jupiterQuote!.swapTransaction!, protocol.jupiterRouter)
+    : Buffer.alloc(0);
+
+  // Oracle feeds bound min_out on-chain; only required when a swap runs
+  const buybackPriceFeed = hasBuybackSwap ? merchant.buybackPriceFeed ?? null : null;
+
+  // Payout conversion into the merchant's settlement currency (route accounts go last)
+  const settlementToken = resolveSettlementToken(merchant.settlementMint, usdcMint);
+  const needsPayoutSwap = payoutBps > 0 && settlementToken !== null && settlementToken !== payToken;
+  if (needsPayoutSwap && !payoutSwap) {
+    throw new Error(`Merchant settles in ${settlementToken}; a payout swap transaction is required`);
+  }
+  const payoutSwapAccounts = needsPayoutSwap
+    ? extractJupiterSwapAccounts(payoutSwap!.swapTransaction, protocol.jupiterRouter)
+    : [];
+  const settlementPriceFeed = needsPayoutSwap
+    ? (settlementToken === 'SOL' ? protocol.solPriceFeed : protocol.usdcPriceFeed) ?? null
+    : null;
+  const payPriceFeed = (hasBuybackSwap || needsPayoutSwap)
+    ? (payToken === 'SOL' ? protocol.solPriceFeed : protocol.usdcPriceFeed) ?? null
+    : null;
+
+  const payoutSwapArg = needsPayoutSwap
+    ? {
+        swapData: extractJupiterSwapData(payoutSwap!.swapTransaction, protocol.jupiterRouter),
+        minOut: new BN(payoutSwap!.minOut.toString()),
+        accountCount: payoutSwapAccounts.length,
+      }
+    : null;
+
+  // Pay-with-any-token: the payer's swap into vault_usdc runs first (route accounts go first)
+  const inputSwapAccounts = inputSwap
+    ? extractJupiterSwapAccounts(inputSwap.swapTransaction, protocol.jupiterRouter)
+    : [];
+  const payerInputToken = inputSwap
+    ? getAssociatedTokenAddressSync(inputSwap.inputMint, payer, false, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID)
+    : null;
+
+  if (createReceipt && !orderReference) {
+    throw new Error('A receipt requires an order reference');
+  }
+  const receipt = createReceipt && orderReference
+    ? deriveReceiptPDA(merchantIdBN, orderReference)[0]
+    : null;
+
+  // Today's volume bucket, recorded only if a crank opened it (settle never pays its rent)
+  const [dayStatsPDA] = deriveMerchantDayStatsPDA(merchantIdBN);
+  const merchantDayStats = (await connection.getAccountInfo(dayStatsPDA)) ? dayStatsPDA : null;
+
+  // Protocol totals shard this merchant's settlements are recorded in
+  const [protocolStats] = deriveProtocolStatsPDA(merchantIdBN);
+
+  // Payment limits are enforced on-chain once the merchant enables them
+  const merchantLimits = merchant.limitsEnabled
+    ? deriveMerchantLimitsPDA(merchantIdBN)[0]
+    : null;
+
+  // Payer list entries are looked up on-chain only while the lists are in use
+  const protocolPayerEntry = protocol.denylistEnabled
+    ? deriveProtocolPayerEntryPDA(payer)[0]
+    : null;
+  const merchantPayerEntry = merchant.payerListEnabled
+    ? deriveMerchantPayerEntryPDA(merchantIdBN, payer)[0]
+    : null;
+
+  // Merchant-signed quote covering this exact payment, verified on-chain by the
+  // ed25519 instruction placed directly before settle
+  let quoteIx = null;
+  if (merchant.quoteSigner && !merchant.quoteSigner.equals(PublicKey.default)) {
+    if (!signedQuote || !orderReference) {
+      throw new Error('Merchant requires a signed quote and an order reference');
+    }
+    quoteIx = buildQuoteInstruction(merchant.quoteSigner, signedQuote.signature, {
+      merchantId: merchantIdBN,
+      amount: amountBN,
+      mint: inputSwap || payToken === 'USDC' ? usdcMint : NATIVE_MINT,
+      orderReference,
+      expiresAt: signedQuote.expiresAt,
+    });
+  }
+
+  if (sponsor && (payToken !== 'USDC' || inputSwap)) {
+    throw new Error('Sponsored payments must be paid directly in USDC');
+  }
+
+  // Tips bypass the vault and go straight to the merchant's tip wallet (plain settle only)
+  const tipBN = tip !== undefined ? new BN(tip.toString()) : null;
+  const tipping = tipBN !== null && !tipBN.isZero();
+  if (tipping && (inputSwap || sponsor)) {
+    throw new Error('Tips are only supported on direct payments');
+  }
+  if (tipping && !merchant.tipWallet) {
+    throw new Error('Merchant does not accept tips');
+  }
+  const tipPolicy = tipping ? deriveTipPolicyPDA(merchantIdBN)[0] : null;
+  const tipWallet = tipping && payToken === 'SOL' ? merchant.tipWallet! : null;
+  const tipWalletUsdc = tipping && payToken === 'USDC'
+    ? getAssociatedTokenAddressSync(usdcMint, merchant.tipWallet!, true)
+    : null;
+
   // Build settle instruction
   // If buyback > 0, we need to add Jupiter swap accounts as remaining accounts
-  let settleBuilder = program.methods
-    .settle(
-      merchantIdBN,
-      amountBN,
-      payToken === 'SOL' ? { sol: {} } : { usdc: {} },
-      minOutBN,
-      payoutBps,
-      buybackBps,
-      burnBps
-    )
+  const settleArgs = {
+    minOut: minOutBN,
+    payoutBps,
+    buybackBps,
+    burnOfBuybackBps: burnBps,
+    swapData,
+    payoutSwap: payoutSwapArg,
+    orderReference: orderReference ? Array.from(orderReference.toBytes()) : null,
+    memo: memo ?? null,
+    tip: tipping ? tipBN : null,
+  };
+  const settleMethod = inputSwap
+    ? program.methods.settleWithSwap(
+        merchantIdBN,
+        amountBN,
+        {
+          swapData: extractJupiterSwapData(inputSwap.swapTransaction, protocol.jupiterRouter),
+          maxAmountIn: new BN(inputSwap.maxAmountIn.toString()),
+          accountCount: inputSwapAccounts.length,
+        },
+        settleArgs
+      )
+    : sponsor
+    ? program.methods.settleSponsored(
+        merchantIdBN,
+        amountBN,
+        beneficiary ?? null,
+        settleArgs
+      )
+    : program.methods.settle(
+        merchantIdBN,
+        amountBN,
+        payToken === 'SOL' ? { sol: {} } : { usdc: {} },
+        settleArgs
+      );
+
+  let settleBuilder = settleMethod
     .accounts({
       protocolConfig: protocol.protocolConfigPDA,
       merchantRegistry: merchant.merchantRegistryPDA,
+      protocolStats,
       payer: payer,
+      merchantDayStats,
+      merchantLimits,
+      protocolPayerEntry,
+      merchantPayerEntry,
+      instructionsSysvar: quoteIx ? SYSVAR_INSTRUCTIONS_PUBKEY : null,
+      sponsor: sponsor ?? null,
+      authorization: null,
+      escrowUsdc: null,
+      installmentPlan: null,
+      groupInvoice: null,
+      donationSettings: null,
+      tipPolicy,
+      tipWallet,
+      tipWalletUsdc,
       vaultSol: protocol.vaultSolPDA,
       vaultUsdc: protocol.vaultUsdcPDA,
       usdcMint: usdcMint,
@@ -156,12 +358,21 @@ export async function buildSettleTransaction(
       merchantPayoutWallet: merchant.payoutWallet,
       merchantPayoutUsdc: merchantPayoutUsdc,
       payerUsdcAccount: payerUsdcAccount || payer, // Fallback if SOL payment
+      merchantHandle: merchant.handle ? deriveMerchantHandlePDA(merchant.handle)[0] : null,
+      reference: orderReference ?? null,
+      receipt,
+      payerInputToken,
       jupiterRouter: protocol.jupiterRouter,
+      swapRouter: swapRouterPDA,
+      payPriceFeed,
+      buybackPriceFeed,
+      settlementPriceFeed,
       tokenProgram: TOKEN_PROGRAM_ID,
       systemProgram: SystemProgram.programId,
     });
 
   // Add Jupiter swap accounts as remaining accounts if buyback > 0
+  let buybackAccounts: AccountMeta[] = [];
   if (buybackBps > 0 && jupiterQuote?.swapTransaction) {
     try {
       // Extract account metas from Jupiter swap transaction
@@ -175,6 +386,12 @@ export async function buildSettleTransaction(
       const existingAccounts = [
         protocol.protocolConfigPDA,
         merchant.merchantRegistryPDA,
+        protocolStats,
+        ...(merchantDayStats ? [merchantDayStats] : []),
+        ...(merchantLimits ? [merchantLimits] : []),
+        ...(protocolPayerEntry ? [protocolPayerEntry] : []),
+        ...(merchantPayerEntry ? [merchantPayerEntry] : []),
+        ...(sponsor ? [sponsor] : []),
         payer,
         protocol.vaultSolPDA,
         protocol.vaultUsdcPDA,
@@ -187,16 +404,14 @@ export async function buildSettleTransaction(
         merchantPayoutUsdc,
         payerUsdcAccount || payer,
         protocol.jupiterRouter,
+        swapRouterPDA,
         TOKEN_PROGRAM_ID,
         SystemProgram.programId,
       ];
       
       const filteredAccounts = filterDuplicateAccounts(jupiterAccounts, existingAccounts);
       
-      // Add as remaining accounts
-      if (filteredAccounts.length > 0) {
-        settleBuilder = settleBuilder.remainingAccounts(filteredAccounts);
-      }
+      buybackAccounts = filteredAccounts;
     } catch (error: any) {
       console.error('Error adding Jupiter swap accounts:', error.message);
       // Continue without remaining accounts - the swap will fail but transaction can still be built
@@ -204,6 +419,13 @@ export async function buildSettleTransaction(
     }
   }
 
+  // Input route first, buyback route next, payout conversion route last
+  // (input and payout route lengths are passed on-chain)
+  const remainingAccounts = [...inputSwapAccounts, ...buybackAccounts, ...payoutSwapAccounts];
+  if (remainingAccounts.length > 0) {
+    settleBuilder = settleBuilder.remainingAccounts(remainingAccounts);
+  }
+
   const settleIx = await settleBuilder.instruction();
 
   // Get recent blockhash
@@ -221,12 +443,15 @@ export async function buildSettleTransaction(
     );
   }
 
-  // Add settle instruction
+  // Add quote verification and settle instructions
+  if (quoteIx) {
+    instructions.push(quoteIx);
+  }
   instructions.push(settleIx);
 
   // Create versioned transaction
   const messageV0 = new TransactionMessage({
-    payerKey: payer,
+    payerKey: sponsor ?? payer, // The relayer pays fees for sponsored payments
     recentBlockhash: blockhash,
     instructions,
   }).compileToV0Message();
@@ -234,6 +459,26 @@ export async function buildSettleTransaction(
   return new VersionedTransaction(messageV0);
 }
 
+/**
+ * Map a merchant's settlement mint preference to the token it is paid out in
+ * (null = paid in whatever token the payer used)
+ */
+function resolveSettlementToken(
+  settlementMint: PublicKey | undefined,
+  usdcMint: PublicKey
+): 'SOL' | 'USDC' | null {
+  if (!settlementMint || settlementMint.equals(PublicKey.default)) {
+    return null;
+  }
+  if (settlementMint.toBase58() === SOL_MINT) {
+    return 'SOL';
+  }
+  if (settlementMint.equals(usdcMint)) {
+    return 'USDC';
+  }
+  throw new Error(`Unsupported settlement mint: ${settlementMint.toBase58()}`);
+}
+
 /**
  * Helper to get all required accounts for settle instruction
  */
@@ -266,17 +511,32 @@ export async function getSettleAccounts(
     merchantAccounts.merchantRegistryPDA
   );
 
+  // Tip policy, if the merchant accepts tips
+  const tipPolicy = await program.account.tipPolicy.fetchNullable(
+    deriveTipPolicyPDA(merchantId)[0]
+  );
+
   return {
     protocol: {
       ...protocolAccounts,
       protocolWallet: protocolConfig.protocolWallet as PublicKey,
       jupiterRouter: protocolConfig.jupiterRouter as PublicKey,
+      solPriceFeed: protocolConfig.solPriceFeed as PublicKey,
+      usdcPriceFeed: protocolConfig.usdcPriceFeed as PublicKey,
+      denylistEnabled: (protocolConfig.deniedPayers as number) > 0,
     },
     merchant: {
       ...merchantAccounts,
       payoutWallet,
       buybackMint,
       vaultBuybackToken,
+      buybackPriceFeed: merchantRegistry.buybackPriceFeed as PublicKey,
+      settlementMint: merchantRegistry.settlementMint as PublicKey,
+      limitsEnabled: merchantRegistry.limitsEnabled as boolean,
+      quoteSigner: merchantRegistry.quoteSigner as PublicKey,
+      tipWallet: tipPolicy ? (tipPolicy.tipWallet as PublicKey) : undefined,
+      payerListEnabled:
+        (merchantRegistry.deniedPayers as number) > 0 || (merchantRegistry.allowlistEnabled as boolean),
     },
     usdcMint,
   };
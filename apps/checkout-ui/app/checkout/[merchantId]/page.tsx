@@ -22,8 +22,19 @@ interface QuoteResponse {
   expiresAt: number
 }
 
+interface MerchantMetadata {
+  name: string
+  uri: string
+  website: string
+  supportContact: string
+  categoryCode: number
+  termsHash: string
+}
+
 interface MerchantInfo {
   merchantId: string
+  handle: string | null
+  metadata: MerchantMetadata | null
   merchantOwner: string
   buybackMint: string
   defaultPayoutBps: number
@@ -35,6 +46,7 @@ interface MerchantInfo {
 
 export default function CheckoutPage() {
   const params = useParams()
+  // Numeric merchant_id or a handle (/checkout/acme-coffee); the API resolves either
   const merchantId = params.merchantId as string
   const { publicKey, signTransaction, connected } = useWallet()
   const { connection } = useConnection()
@@ -61,6 +73,8 @@ export default function CheckoutPage() {
         } else {
           setMerchant({
             merchantId: data.merchantId,
+            handle: data.handle ?? null,
+            metadata: data.metadata ?? null,
             merchantOwner: data.merchantOwner,
             buybackMint: data.buybackMint,
             defaultPayoutBps: data.defaultPayoutBps,
@@ -138,6 +152,7 @@ export default function CheckoutPage() {
         body: JSON.stringify({
           quoteId: quote.quoteId,
           merchantId: quote.merchantId,
+          merchantHandle: merchant?.handle ?? undefined,
           payer: publicKey.toBase58(),
           amount: quote.amount,
           payToken: quote.payToken,
@@ -197,8 +212,20 @@ export default function CheckoutPage() {
       <div className="max-w-2xl mx-auto">
         <div className="bg-white rounded-lg shadow-lg p-8">
           <div className="mb-6">
-            <h1 className="text-3xl font-bold text-gray-900 mb-2">Checkout</h1>
+            <h1 className="text-3xl font-bold text-gray-900 mb-2">
+              {merchant.metadata?.name ?? 'Checkout'}
+            </h1>
             <p className="text-gray-600">Pay with SOL or USDC</p>
+            {merchant.metadata?.website && (
+              <a
+                href={merchant.metadata.website}
+                target="_blank"
+                rel="noopener noreferrer"
+                className="text-sm text-primary-600 hover:underline"
+              >
+                {merchant.metadata.website}
+              </a>
+            )}
           </div>
 
           {/* Wallet Connection */}
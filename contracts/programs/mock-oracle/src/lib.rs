@@ -0,0 +1,71 @@
+use anchor_lang::prelude::*;
+
+declare_id!("AGYA1ewTyTPJ1ekV1gN8bmtTigk6r3SgFRHXr4WkcT3H");
+
+/// Size of a Pyth price account up to the end of the aggregate price
+pub const PRICE_ACCOUNT_LEN: usize = 240;
+
+/// Size of a mock price account: the Pyth layout followed by the key allowed to write it
+pub const MOCK_PRICE_ACCOUNT_LEN: usize = PRICE_ACCOUNT_LEN + 32;
+
+/// Mock price oracle (tests only)
+/// Writes the subset of the Pyth v2 price account layout read by qantara-v2
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    /// Write an aggregate price into a price account owned by this program
+    /// The account is created by the client (system create_account, owner = this program);
+    /// the first write records the signer as its authority, so create and set in one transaction
+    pub fn set_price(
+        ctx: Context<SetPrice>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Result<()> {
+        let mut data = ctx.accounts.price_account.try_borrow_mut_data()?;
+        require!(data.len() >= MOCK_PRICE_ACCOUNT_LEN, MockOracleError::AccountTooSmall);
+
+        let authority = ctx.accounts.authority.key();
+        let stored = Pubkey::new_from_array(
+            data[PRICE_ACCOUNT_LEN..MOCK_PRICE_ACCOUNT_LEN].try_into().unwrap(),
+        );
+        if stored == Pubkey::default() {
+            data[PRICE_ACCOUNT_LEN..MOCK_PRICE_ACCOUNT_LEN].copy_from_slice(authority.as_ref());
+        } else {
+            require_keys_eq!(stored, authority, MockOracleError::Unauthorized);
+        }
+
+        data[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes()); // magic
+        data[4..8].copy_from_slice(&2u32.to_le_bytes()); // version
+        data[8..12].copy_from_slice(&3u32.to_le_bytes()); // account type: price
+        data[12..16].copy_from_slice(&(PRICE_ACCOUNT_LEN as u32).to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[96..104].copy_from_slice(&publish_time.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes()); // agg.price
+        data[216..224].copy_from_slice(&conf.to_le_bytes()); // agg.conf
+        data[224..228].copy_from_slice(&1u32.to_le_bytes()); // agg.status: trading
+        data[232..240].copy_from_slice(&Clock::get()?.slot.to_le_bytes()); // agg.pub_slot
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetPrice<'info> {
+    /// CHECK: Raw Pyth-layout price account owned by this program
+    #[account(mut, owner = crate::ID)]
+    pub price_account: UncheckedAccount<'info>,
+
+    /// Price account authority (recorded by the first write)
+    pub authority: Signer<'info>,
+}
+
+#[error_code]
+pub enum MockOracleError {
+    #[msg("Price account is smaller than the mock price layout")]
+    AccountTooSmall,
+    #[msg("Signer is not the price account authority")]
+    Unauthorized,
+}
@@ -28,5 +28,169 @@ pub enum QantaraError {
     InvalidMinOut,
     #[msg("Unauthorized: Only owner can perform this action")]
     Unauthorized,
+    #[msg("Swap moved vault funds beyond its allowance (conservation check failed)")]
+    VaultConservationViolated,
+    #[msg("Router is disabled in the router registry")]
+    RouterDisabled,
+    #[msg("Swap instruction is not an allowlisted router entrypoint")]
+    SwapInstructionNotAllowed,
+    #[msg("Router discriminator list is empty or exceeds the maximum")]
+    InvalidRouterDiscriminators,
+    #[msg("Oracle price feeds are not configured")]
+    OracleNotConfigured,
+    #[msg("Price feed does not match configuration or is malformed")]
+    InvalidPriceFeed,
+    #[msg("Oracle price is stale")]
+    OraclePriceStale,
+    #[msg("min_out implies more slippage than the merchant allows")]
+    MinOutBelowOracleBound,
+    #[msg("Oracle staleness limit must be greater than zero")]
+    InvalidPriceAge,
+    #[msg("USD amount converts to zero or more lamports than the payer allowed")]
+    UsdConversionExceedsMax,
+    #[msg("Settlement mint must be SOL (native mint) or the protocol USDC mint")]
+    UnsupportedSettlementMint,
+    #[msg("Payout swap must be supplied exactly when the payout needs conversion")]
+    PayoutSwapMismatch,
+    #[msg("Swap route account counts exceed the remaining accounts")]
+    InvalidSwapRouteAccounts,
+    #[msg("Input swap is missing accounts or its token accounts are not the payer's")]
+    InvalidInputSwap,
+    #[msg("Input swap spent more than the payer's max_amount_in")]
+    InputSwapExceedsMax,
+    #[msg("Input swap produced less USDC than the invoice amount")]
+    InputSwapShortfall,
+    #[msg("Buyback vault must be empty before the merchant can be closed")]
+    BuybackVaultNotEmpty,
+    #[msg("Account is not in a layout this instruction can migrate")]
+    UnsupportedAccountVersion,
+    #[msg("Handle must be 3-32 characters of a-z, 0-9 or '-', not starting or ending with '-'")]
+    InvalidHandle,
+    #[msg("Merchant handle does not resolve to this merchant")]
+    MerchantHandleMismatch,
+    #[msg("Metadata name must be 1-64 bytes without surrounding whitespace or control characters")]
+    InvalidMetadataName,
+    #[msg("Metadata field exceeds its maximum length or contains control characters")]
+    InvalidMetadataField,
+    #[msg("Metadata URI must use https://, ipfs:// or ar:// (website: https://)")]
+    InvalidMetadataUri,
+    #[msg("Category code must be a 4-digit ISO 18245 merchant category code")]
+    InvalidCategoryCode,
+    #[msg("Reference account does not match the order reference")]
+    OrderReferenceMismatch,
+    #[msg("Memo exceeds 64 bytes")]
+    MemoTooLong,
+    #[msg("A receipt requires an order reference and the receipt PDA for it")]
+    InvalidReceiptAccount,
+    #[msg("Receipt is still within its retention period")]
+    ReceiptRetentionActive,
+    #[msg("Day stats account is not the merchant's bucket for the current UTC day")]
+    InvalidDayStatsAccount,
+    #[msg("Day stats bucket is for a past day or still within its retention period")]
+    InvalidDayStatsDay,
+    #[msg("Minimum amount exceeds maximum amount")]
+    InvalidLimits,
+    #[msg("Merchant has payment limits; the limits account is required")]
+    MerchantLimitsRequired,
+    #[msg("Payment is below the merchant's minimum amount")]
+    PaymentBelowMinimum,
+    #[msg("Payment is above the merchant's maximum amount")]
+    PaymentAboveMaximum,
+    #[msg("Payment would exceed the merchant's rolling 24h volume cap")]
+    DailyCapExceeded,
+    #[msg("Payer list entry account is required to check this payer")]
+    PayerListEntryRequired,
+    #[msg("Payer is on a denylist")]
+    PayerDenied,
+    #[msg("Payer is not on the merchant's allowlist")]
+    PayerNotAllowlisted,
+    #[msg("Merchant requires a signed quote for this payment")]
+    QuoteRequired,
+    #[msg("Quote must be signed by the merchant's quote signer in the preceding ed25519 instruction")]
+    InvalidQuoteSignature,
+    #[msg("Payment does not match the signed quote")]
+    QuoteMismatch,
+    #[msg("Signed quote has expired")]
+    QuoteExpired,
+    #[msg("Sponsored settlement requires a sponsor to fund rent")]
+    SponsorRequired,
+    #[msg("Authorization amount must be greater than zero")]
+    InvalidAuthorizationAmount,
+    #[msg("Authorization expiry must be in the future and within 30 days")]
+    InvalidAuthorizationExpiry,
+    #[msg("USDC authorizations require the escrow, mint and payer token accounts")]
+    AuthorizationEscrowRequired,
+    #[msg("Capture requires the authorization account")]
+    AuthorizationRequired,
+    #[msg("Authorization has already been captured")]
+    AuthorizationAlreadyCaptured,
+    #[msg("Authorization has expired")]
+    AuthorizationExpired,
+    #[msg("Capture amount must be between 1 and the authorized amount")]
+    CaptureExceedsAuthorization,
+    #[msg("Only the merchant may void an authorization before it is captured or expires")]
+    AuthorizationActive,
+    #[msg("Merchant has open authorizations")]
+    AuthorizationsOutstanding,
+    #[msg("Installment plans need 2-60 installments of a non-zero amount and a positive interval")]
+    InvalidInstallmentTerms,
+    #[msg("Installment payment requires the installment plan account")]
+    InstallmentPlanRequired,
+    #[msg("Only the plan's payer may pay its installments")]
+    InstallmentPayerMismatch,
+    #[msg("Installment plan is already fully paid")]
+    InstallmentPlanCompleted,
+    #[msg("Installment plan has already defaulted")]
+    InstallmentPlanDefaulted,
+    #[msg("No installment is past its grace period")]
+    InstallmentNotOverdue,
+    #[msg("Group invoices need a non-zero target and an expiry within 30 days")]
+    InvalidGroupInvoiceTerms,
+    #[msg("Group invoice settlement requires the group invoice account")]
+    GroupInvoiceRequired,
+    #[msg("USDC group invoices require the escrow and token accounts")]
+    GroupInvoiceEscrowRequired,
+    #[msg("Contribution must be between 1 and the invoice's unfunded remainder")]
+    InvalidContributionAmount,
+    #[msg("Group invoice has expired")]
+    GroupInvoiceExpired,
+    #[msg("Group invoice has already been settled")]
+    GroupInvoiceSettled,
+    #[msg("Group invoice is not fully funded")]
+    GroupInvoiceUnderfunded,
+    #[msg("Contributions can only be reclaimed once the invoice is settled or expired")]
+    GroupInvoiceOpen,
+    #[msg("Group invoice still has contribution records")]
+    ContributionsOutstanding,
+    #[msg("Merchant has open group invoices")]
+    GroupInvoicesOutstanding,
+    #[msg("Suggested donation tiers must be at least the minimum")]
+    InvalidDonationSettings,
+    #[msg("Donations require the merchant's donation settings account")]
+    DonationSettingsRequired,
+    #[msg("Merchant is not accepting donations")]
+    DonationsDisabled,
+    #[msg("Donation is below the merchant's minimum")]
+    DonationBelowMinimum,
+    #[msg("Donation message too long (max 280 bytes)")]
+    DonationMessageTooLong,
+    #[msg("Tips require the merchant's tip policy account")]
+    TipPolicyRequired,
+    #[msg("Tip wallet does not match the merchant's tip policy")]
+    InvalidTipWallet,
+    #[msg("Oracle price confidence interval is too wide")]
+    OraclePriceUncertain,
+    #[msg("USDC mint does not match the protocol configuration")]
+    InvalidUsdcMint,
+    #[msg("Protocol stats shard out of range")]
+    InvalidStatsShard,
+    #[msg("Merchant has open installment plans")]
+    InstallmentPlansOutstanding,
+    #[msg("Merchant still has claimed handles")]
+    HandlesOutstanding,
+    #[msg("Settle argument not accepted by this instruction")]
+    UnsupportedSettleArg,
+    #[msg("Payer lists require the payer to own the paying token account")]
+    PayerNotTokenOwner,
 }
 
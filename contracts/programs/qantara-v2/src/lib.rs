@@ -1,14 +1,21 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::instruction::AccountMeta;
 use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::system_program;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Burn, Transfer};
+use anchor_spl::token::spl_token::native_mint;
 
 pub mod state;
 pub mod errors;
 pub mod utils;
+pub mod oracle;
+pub mod migration;
 
 use state::*;
 use errors::*;
+use utils::*;
+use oracle::*;
+use migration::*;
 
 declare_id!("JCjXHcUy7LzJsLBoafjem9wRffRyuyGYsiTz35Yyr9AH");
 
@@ -29,6 +36,7 @@ pub mod qantara_v2 {
         );
 
         let config = &mut ctx.accounts.protocol_config;
+        config.version = PROTOCOL_CONFIG_VERSION;
         config.authority = ctx.accounts.authority.key();
         config.protocol_fee_bps = protocol_fee_bps;
         config.protocol_wallet = protocol_wallet;
@@ -45,6 +53,18 @@ pub mod qantara_v2 {
         Ok(())
     }
 
+    /// Create one shard of the protocol-wide settlement totals (one-time per shard, admin only)
+    /// Every shard must exist before merchants mapped to it can settle
+    pub fn init_protocol_stats(ctx: Context<InitProtocolStats>, shard: u8) -> Result<()> {
+        require!(shard < PROTOCOL_STATS_SHARDS, QantaraError::InvalidStatsShard);
+
+        let stats = &mut ctx.accounts.protocol_stats;
+        stats.version = PROTOCOL_STATS_VERSION;
+        stats.shard = shard;
+        stats.bump = ctx.bumps.protocol_stats;
+        Ok(())
+    }
+
     /// Update protocol configuration (admin only)
     pub fn update_protocol(
         ctx: Context<UpdateProtocol>,
@@ -81,28 +101,188 @@ pub mod qantara_v2 {
         Ok(())
     }
 
+    /// Upgrade a pre-versioning ProtocolConfig to the current layout (admin only)
+    pub fn migrate_protocol(ctx: Context<MigrateProtocol>) -> Result<()> {
+        let account = ctx.accounts.protocol_config.to_account_info();
+        let legacy = LegacyProtocolConfig::decode(&account.try_borrow_data()?)?;
+        require_keys_eq!(
+            legacy.authority,
+            ctx.accounts.authority.key(),
+            QantaraError::Unauthorized
+        );
+
+        write_upgraded(
+            &account,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &legacy.upgrade(),
+        )?;
+
+        emit!(AccountMigrated {
+            account: account.key(),
+            version: PROTOCOL_CONFIG_VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Upgrade a pre-versioning MerchantRegistry to the current layout
+    /// Permissionless: the upgrade only adds defaulted fields, the payer funds the extra rent
+    pub fn migrate_merchant(ctx: Context<MigrateMerchant>, merchant_id: u64) -> Result<()> {
+        let account = ctx.accounts.merchant_registry.to_account_info();
+        let legacy = LegacyMerchantRegistry::decode(&account.try_borrow_data()?)?;
+        require!(
+            legacy.merchant_id == merchant_id,
+            QantaraError::InvalidMerchantId
+        );
+
+        write_upgraded(
+            &account,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &legacy.upgrade(),
+        )?;
+
+        emit!(AccountMigrated {
+            account: account.key(),
+            version: MERCHANT_REGISTRY_VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Configure price oracle used to bound buyback slippage (admin only)
+    pub fn configure_oracle(
+        ctx: Context<UpdateProtocol>,
+        oracle_program: Pubkey,
+        sol_price_feed: Pubkey,
+        usdc_price_feed: Pubkey,
+        max_price_age_secs: u32,
+    ) -> Result<()> {
+        require!(max_price_age_secs > 0, QantaraError::InvalidPriceAge);
+
+        let config = &mut ctx.accounts.protocol_config;
+        config.oracle_program = oracle_program;
+        config.sol_price_feed = sol_price_feed;
+        config.usdc_price_feed = usdc_price_feed;
+        config.max_price_age_secs = max_price_age_secs;
+
+        emit!(OracleConfigured {
+            oracle_program,
+            sol_price_feed,
+            usdc_price_feed,
+            max_price_age_secs,
+        });
+
+        Ok(())
+    }
+
+    /// Set the USDC mint the protocol accepts (admin only)
+    /// Settle, USDC escrows and merchant settlement mints are checked against it
+    pub fn set_usdc_mint(ctx: Context<UpdateProtocol>, usdc_mint: Pubkey) -> Result<()> {
+        ctx.accounts.protocol_config.usdc_mint = usdc_mint;
+
+        emit!(UsdcMintUpdated { usdc_mint });
+
+        Ok(())
+    }
+
+    /// Add a swap router to the registry (admin only)
+    /// Only allowlisted entrypoints of registered routers can be invoked by settle
+    pub fn add_router(
+        ctx: Context<AddRouter>,
+        program_id: Pubkey,
+        discriminators: Vec<[u8; 8]>,
+    ) -> Result<()> {
+        require!(
+            !discriminators.is_empty() && discriminators.len() <= MAX_ROUTER_DISCRIMINATORS,
+            QantaraError::InvalidRouterDiscriminators
+        );
+
+        let router = &mut ctx.accounts.swap_router;
+        router.version = SWAP_ROUTER_VERSION;
+        router.program_id = program_id;
+        router.discriminators = discriminators.clone();
+        router.enabled = true;
+        router.bump = ctx.bumps.swap_router;
+
+        emit!(RouterRegistered {
+            program_id,
+            discriminators,
+        });
+
+        Ok(())
+    }
+
+    /// Update a registered router (admin only)
+    pub fn update_router(
+        ctx: Context<UpdateRouter>,
+        discriminators: Option<Vec<[u8; 8]>>,
+        enabled: Option<bool>,
+    ) -> Result<()> {
+        let router = &mut ctx.accounts.swap_router;
+
+        if let Some(discriminators) = discriminators {
+            require!(
+                !discriminators.is_empty() && discriminators.len() <= MAX_ROUTER_DISCRIMINATORS,
+                QantaraError::InvalidRouterDiscriminators
+            );
+            router.discriminators = discriminators;
+        }
+
+        if let Some(enable) = enabled {
+            router.enabled = enable;
+        }
+
+        emit!(RouterUpdated {
+            program_id: router.program_id,
+            discriminators: router.discriminators.clone(),
+            enabled: router.enabled,
+        });
+
+        Ok(())
+    }
+
+    /// Remove a router from the registry (admin only)
+    pub fn remove_router(ctx: Context<RemoveRouter>) -> Result<()> {
+        emit!(RouterRemoved {
+            program_id: ctx.accounts.swap_router.program_id,
+        });
+
+        Ok(())
+    }
+
     /// Register a merchant (creates on-chain registry entry)
     /// This prevents rerouting attacks by storing payout_wallet on-chain
+    /// merchant_id must equal derive_merchant_id(owner, nonce), so IDs cannot be squatted
     pub fn register_merchant(
         ctx: Context<RegisterMerchant>,
         merchant_id: u64,
+        nonce: u64,
         payout_wallet: Pubkey,
         buyback_mint: Pubkey,
     ) -> Result<()> {
-        let merchant = &mut ctx.accounts.merchant_registry;
-        
-        // Verify merchant_id matches PDA derivation
+        // Verify merchant_id is derived from the signing owner
         require!(
-            merchant.merchant_id == merchant_id || merchant.merchant_id == 0,
+            merchant_id == derive_merchant_id(&ctx.accounts.owner.key(), nonce),
             QantaraError::InvalidMerchantId
         );
 
+        let merchant = &mut ctx.accounts.merchant_registry;
+
+        merchant.version = MERCHANT_REGISTRY_VERSION;
         merchant.merchant_id = merchant_id;
         merchant.owner = ctx.accounts.owner.key();
         merchant.payout_wallet = payout_wallet;
         merchant.buyback_mint = buyback_mint;
         merchant.frozen = false;
+        merchant.buyback_price_feed = Pubkey::default();
+        merchant.slippage_bps_max = DEFAULT_SLIPPAGE_BPS;
+        merchant.settlement_mint = Pubkey::default();
         merchant.bump = ctx.bumps.merchant_registry;
+        merchant.payout_bps = 10000;
+        merchant.buyback_bps = 0;
+        merchant.burn_of_buyback_bps = 0;
 
         emit!(MerchantRegistered {
             merchant_id,
@@ -111,212 +291,2029 @@ pub mod qantara_v2 {
             buyback_mint,
         });
 
-        Ok(())
+        Ok(())
+    }
+
+    /// Create a v2 registry from the owner's v1 MerchantConfig
+    /// Carries over payout wallet, buyback mint, splits, slippage and frozen state, then marks the
+    /// v1 merchant as migrated so it stops accepting payments. The v1 split becomes the merchant's
+    /// default split; settle still takes the split per payment
+    pub fn migrate_from_v1(
+        ctx: Context<MigrateFromV1>,
+        merchant_id: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        require!(
+            merchant_id == derive_merchant_id(&ctx.accounts.owner.key(), nonce),
+            QantaraError::InvalidMerchantId
+        );
+
+        let v1 = &ctx.accounts.v1_merchant_config;
+        let merchant = &mut ctx.accounts.merchant_registry;
+
+        merchant.version = MERCHANT_REGISTRY_VERSION;
+        merchant.merchant_id = merchant_id;
+        merchant.owner = v1.owner;
+        merchant.payout_wallet = v1.payout_wallet;
+        merchant.buyback_mint = v1.buyback_mint;
+        merchant.frozen = v1.frozen;
+        merchant.buyback_price_feed = Pubkey::default();
+        merchant.slippage_bps_max = v1.slippage_bps_max.min(MAX_SLIPPAGE_BPS);
+        merchant.settlement_mint = Pubkey::default();
+        merchant.bump = ctx.bumps.merchant_registry;
+        merchant.payout_bps = v1.payout_bps;
+        merchant.buyback_bps = v1.buyback_bps;
+        merchant.burn_of_buyback_bps = v1.burn_of_buyback_bps;
+
+        // Freeze the v1 merchant and record the move (owner signature is forwarded)
+        qantara::cpi::mark_migrated(
+            CpiContext::new(
+                ctx.accounts.qantara_program.to_account_info(),
+                qantara::cpi::accounts::MarkMigrated {
+                    merchant_config: ctx.accounts.v1_merchant_config.to_account_info(),
+                    migration_record: ctx.accounts.v1_migration_record.to_account_info(),
+                    owner: ctx.accounts.owner.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                },
+            ),
+            merchant_id,
+        )?;
+
+        emit!(MerchantRegistered {
+            merchant_id,
+            owner: merchant.owner,
+            payout_wallet: merchant.payout_wallet,
+            buyback_mint: merchant.buyback_mint,
+        });
+        emit!(MerchantMigratedFromV1 {
+            merchant_id,
+            owner: merchant.owner,
+            v1_merchant_config: ctx.accounts.v1_merchant_config.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Create a merchant's limits account (merchant owner or protocol authority)
+    /// Starts with no limits; from then on every settlement is checked against it
+    pub fn init_merchant_limits(ctx: Context<InitMerchantLimits>) -> Result<()> {
+        let authority = ctx.accounts.authority.key();
+        require!(
+            authority == ctx.accounts.merchant_registry.owner
+                || authority == ctx.accounts.protocol_config.authority,
+            QantaraError::Unauthorized
+        );
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.limits_enabled = true;
+
+        let limits = &mut ctx.accounts.merchant_limits;
+        limits.version = MERCHANT_LIMITS_VERSION;
+        limits.merchant_id = merchant.merchant_id;
+        limits.bump = ctx.bumps.merchant_limits;
+        Ok(())
+    }
+
+    /// Set the merchant's own min/max amounts and rolling 24h caps (owner only)
+    /// Protocol ceilings still apply on top of these
+    pub fn update_merchant_limits(
+        ctx: Context<UpdateMerchantLimits>,
+        sol: TokenLimits,
+        usdc: TokenLimits,
+    ) -> Result<()> {
+        sol.validate()?;
+        usdc.validate()?;
+
+        let limits = &mut ctx.accounts.merchant_limits;
+        limits.sol = sol;
+        limits.usdc = usdc;
+
+        emit!(MerchantLimitsUpdated {
+            merchant_id: limits.merchant_id,
+            sol,
+            usdc,
+            by_protocol: false,
+        });
+
+        Ok(())
+    }
+
+    /// Create a merchant's donation settings (owner only); donations stay off until enabled
+    pub fn init_donation_settings(ctx: Context<InitDonationSettings>) -> Result<()> {
+        let settings = &mut ctx.accounts.donation_settings;
+        settings.version = DONATION_SETTINGS_VERSION;
+        settings.merchant_id = ctx.accounts.merchant_registry.merchant_id;
+        settings.bump = ctx.bumps.donation_settings;
+        Ok(())
+    }
+
+    /// Turn donations on or off and set per-token minimums and suggested tiers (owner only)
+    pub fn update_donation_settings(
+        ctx: Context<UpdateDonationSettings>,
+        enabled: bool,
+        sol: DonationTerms,
+        usdc: DonationTerms,
+    ) -> Result<()> {
+        sol.validate()?;
+        usdc.validate()?;
+
+        let settings = &mut ctx.accounts.donation_settings;
+        settings.enabled = enabled;
+        settings.sol = sol;
+        settings.usdc = usdc;
+
+        emit!(DonationSettingsUpdated {
+            merchant_id: settings.merchant_id,
+            enabled,
+            sol,
+            usdc,
+        });
+
+        Ok(())
+    }
+
+    /// Create the merchant's tip policy (owner only); settle then accepts tips
+    pub fn init_tip_policy(
+        ctx: Context<InitTipPolicy>,
+        tip_wallet: Pubkey,
+        fee_on_tips: bool,
+    ) -> Result<()> {
+        let policy = &mut ctx.accounts.tip_policy;
+        policy.version = TIP_POLICY_VERSION;
+        policy.merchant_id = ctx.accounts.merchant_registry.merchant_id;
+        policy.tip_wallet = tip_wallet;
+        policy.fee_on_tips = fee_on_tips;
+        policy.bump = ctx.bumps.tip_policy;
+
+        emit!(TipPolicyUpdated {
+            merchant_id: policy.merchant_id,
+            tip_wallet,
+            fee_on_tips,
+        });
+
+        Ok(())
+    }
+
+    /// Change the tip wallet and whether the protocol fee applies to tips (owner only)
+    pub fn update_tip_policy(
+        ctx: Context<UpdateTipPolicy>,
+        tip_wallet: Pubkey,
+        fee_on_tips: bool,
+    ) -> Result<()> {
+        let policy = &mut ctx.accounts.tip_policy;
+        policy.tip_wallet = tip_wallet;
+        policy.fee_on_tips = fee_on_tips;
+
+        emit!(TipPolicyUpdated {
+            merchant_id: policy.merchant_id,
+            tip_wallet,
+            fee_on_tips,
+        });
+
+        Ok(())
+    }
+
+    /// Mark or unmark a merchant as a verified nonprofit (admin only)
+    /// Donations to verified nonprofits pay no protocol fee
+    pub fn set_verified_nonprofit(ctx: Context<SetVerifiedNonprofit>, verified: bool) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.verified_nonprofit = verified;
+
+        emit!(NonprofitVerificationUpdated {
+            merchant_id: merchant.merchant_id,
+            verified,
+        });
+
+        Ok(())
+    }
+
+    /// Set protocol ceilings on a merchant's limits (admin only)
+    /// The strictest of merchant and protocol limits is enforced
+    pub fn set_protocol_limits(
+        ctx: Context<SetProtocolLimits>,
+        sol: TokenLimits,
+        usdc: TokenLimits,
+    ) -> Result<()> {
+        sol.validate()?;
+        usdc.validate()?;
+
+        let limits = &mut ctx.accounts.merchant_limits;
+        limits.protocol_sol = sol;
+        limits.protocol_usdc = usdc;
+
+        emit!(MerchantLimitsUpdated {
+            merchant_id: limits.merchant_id,
+            sol,
+            usdc,
+            by_protocol: true,
+        });
+
+        Ok(())
+    }
+
+    /// Add a wallet to the protocol denylist (admin only), blocking it from paying any merchant
+    pub fn add_protocol_denylist_entry(
+        ctx: Context<AddProtocolDenylistEntry>,
+        wallet: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.denied_payers = config.denied_payers.saturating_add(1);
+
+        let entry = &mut ctx.accounts.payer_list_entry;
+        entry.version = PAYER_LIST_ENTRY_VERSION;
+        entry.merchant_id = None;
+        entry.payer = wallet;
+        entry.kind = PayerListKind::Deny;
+        entry.created_at = Clock::get()?.unix_timestamp;
+        entry.bump = ctx.bumps.payer_list_entry;
+
+        emit!(PayerListUpdated {
+            merchant_id: None,
+            payer: wallet,
+            kind: PayerListKind::Deny,
+            added: true,
+        });
+
+        Ok(())
+    }
+
+    /// Remove a wallet from the protocol denylist (admin only)
+    pub fn remove_protocol_denylist_entry(ctx: Context<RemoveProtocolDenylistEntry>) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.denied_payers = config.denied_payers.saturating_sub(1);
+
+        emit!(PayerListUpdated {
+            merchant_id: None,
+            payer: ctx.accounts.payer_list_entry.payer,
+            kind: PayerListKind::Deny,
+            added: false,
+        });
+
+        Ok(())
+    }
+
+    /// Add a wallet to the merchant's denylist or allowlist (owner only)
+    /// A wallet has at most one entry per merchant; remove it to change its kind
+    pub fn add_payer_list_entry(
+        ctx: Context<AddPayerListEntry>,
+        wallet: Pubkey,
+        kind: PayerListKind,
+    ) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        if kind == PayerListKind::Deny {
+            merchant.denied_payers = merchant.denied_payers.saturating_add(1);
+        }
+
+        let entry = &mut ctx.accounts.payer_list_entry;
+        entry.version = PAYER_LIST_ENTRY_VERSION;
+        entry.merchant_id = Some(merchant.merchant_id);
+        entry.payer = wallet;
+        entry.kind = kind;
+        entry.created_at = Clock::get()?.unix_timestamp;
+        entry.bump = ctx.bumps.payer_list_entry;
+
+        emit!(PayerListUpdated {
+            merchant_id: Some(merchant.merchant_id),
+            payer: wallet,
+            kind,
+            added: true,
+        });
+
+        Ok(())
+    }
+
+    /// Remove a wallet from the merchant's list (owner only)
+    pub fn remove_payer_list_entry(ctx: Context<RemovePayerListEntry>) -> Result<()> {
+        let entry = &ctx.accounts.payer_list_entry;
+        let merchant = &mut ctx.accounts.merchant_registry;
+        if entry.kind == PayerListKind::Deny {
+            merchant.denied_payers = merchant.denied_payers.saturating_sub(1);
+        }
+
+        emit!(PayerListUpdated {
+            merchant_id: Some(merchant.merchant_id),
+            payer: entry.payer,
+            kind: entry.kind,
+            added: false,
+        });
+
+        Ok(())
+    }
+
+    /// Accept payments only from allowlisted wallets (owner only)
+    pub fn set_payer_allowlist(ctx: Context<SetPayerAllowlist>, enabled: bool) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.allowlist_enabled = enabled;
+
+        emit!(PayerAllowlistUpdated {
+            merchant_id: merchant.merchant_id,
+            enabled,
+        });
+
+        Ok(())
+    }
+
+    /// Update merchant registry (owner only)
+    pub fn update_merchant(
+        ctx: Context<UpdateMerchant>,
+        payout_wallet: Option<Pubkey>,
+        buyback_mint: Option<Pubkey>,
+        frozen: Option<bool>,
+    ) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+
+        if let Some(wallet) = payout_wallet {
+            merchant.payout_wallet = wallet;
+        }
+
+        if let Some(mint) = buyback_mint {
+            merchant.buyback_mint = mint;
+        }
+
+        if let Some(freeze) = frozen {
+            merchant.frozen = freeze;
+            emit!(MerchantFrozen {
+                merchant_id: merchant.merchant_id,
+                frozen: freeze,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Update buyback oracle settings (owner only)
+    /// min_out supplied at settlement is checked against this feed and tolerance
+    pub fn update_buyback_oracle(
+        ctx: Context<UpdateMerchant>,
+        buyback_price_feed: Option<Pubkey>,
+        slippage_bps_max: Option<u16>,
+    ) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+
+        if let Some(feed) = buyback_price_feed {
+            merchant.buyback_price_feed = feed;
+        }
+
+        if let Some(slippage_bps) = slippage_bps_max {
+            require!(slippage_bps <= MAX_SLIPPAGE_BPS, QantaraError::SlippageTooHigh);
+            merchant.slippage_bps_max = slippage_bps;
+        }
+
+        emit!(BuybackOracleUpdated {
+            merchant_id: merchant.merchant_id,
+            buyback_price_feed: merchant.buyback_price_feed,
+            slippage_bps_max: merchant.slippage_bps_max,
+        });
+
+        Ok(())
+    }
+
+    /// Set the currency the merchant is paid out in (owner only)
+    /// Pubkey::default() pays out in the incoming token, the native mint converts to SOL,
+    /// the protocol USDC mint converts to USDC
+    pub fn update_settlement_mint(
+        ctx: Context<UpdateSettlementMint>,
+        settlement_mint: Pubkey,
+    ) -> Result<()> {
+        resolve_settlement_token(&settlement_mint, &ctx.accounts.protocol_config.usdc_mint)?;
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.settlement_mint = settlement_mint;
+
+        emit!(SettlementMintUpdated {
+            merchant_id: merchant.merchant_id,
+            settlement_mint,
+        });
+
+        Ok(())
+    }
+
+    /// Set the key whose signed quotes settle must carry (owner only)
+    /// None stops requiring quotes; amounts are then chosen by the client again
+    pub fn set_quote_signer(
+        ctx: Context<UpdateMerchant>,
+        quote_signer: Option<Pubkey>,
+    ) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.quote_signer = quote_signer.unwrap_or_default();
+
+        emit!(QuoteSignerUpdated {
+            merchant_id: merchant.merchant_id,
+            quote_signer,
+        });
+
+        Ok(())
+    }
+
+    /// Publish display metadata for a merchant (owner only)
+    pub fn init_merchant_metadata(
+        ctx: Context<InitMerchantMetadata>,
+        args: MerchantMetadataArgs,
+    ) -> Result<()> {
+        validate_metadata(&args)?;
+
+        let metadata = &mut ctx.accounts.merchant_metadata;
+        metadata.version = MERCHANT_METADATA_VERSION;
+        metadata.merchant_id = ctx.accounts.merchant_registry.merchant_id;
+        metadata.bump = ctx.bumps.merchant_metadata;
+        write_merchant_metadata(metadata, args);
+
+        Ok(())
+    }
+
+    /// Replace a merchant's display metadata (owner only)
+    /// The account is resized to fit; the owner pays for growth and is refunded on shrink
+    pub fn update_merchant_metadata(
+        ctx: Context<UpdateMerchantMetadata>,
+        args: MerchantMetadataArgs,
+    ) -> Result<()> {
+        validate_metadata(&args)?;
+        write_merchant_metadata(&mut ctx.accounts.merchant_metadata, args);
+
+        Ok(())
+    }
+
+    /// Close a merchant registry, its buyback vault and its metadata, limits, tip policy and
+    /// donation settings accounts (owner only). Refuses while the buyback vault still holds
+    /// tokens, a handle still resolves to the merchant, or authorizations, installment plans
+    /// or group invoices are open; rent goes to the owner
+    pub fn close_merchant(ctx: Context<CloseMerchant>) -> Result<()> {
+        let merchant = &ctx.accounts.merchant_registry;
+        require!(
+            ctx.accounts.vault_buyback_token.amount == 0,
+            QantaraError::BuybackVaultNotEmpty
+        );
+        require!(
+            merchant.open_authorizations == 0,
+            QantaraError::AuthorizationsOutstanding
+        );
+        require!(
+            merchant.open_installment_plans == 0,
+            QantaraError::InstallmentPlansOutstanding
+        );
+        require!(
+            merchant.open_group_invoices == 0,
+            QantaraError::GroupInvoicesOutstanding
+        );
+        require!(
+            merchant.claimed_handles == 0,
+            QantaraError::HandlesOutstanding
+        );
+
+        // Per-merchant accounts would be orphaned once the registry is gone
+        let owner = ctx.accounts.owner.to_account_info();
+        close_if_initialized(&ctx.accounts.merchant_metadata, &owner)?;
+        close_if_initialized(&ctx.accounts.merchant_limits, &owner)?;
+        close_if_initialized(&ctx.accounts.tip_policy, &owner)?;
+        close_if_initialized(&ctx.accounts.donation_settings, &owner)?;
+
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.vault_buyback_token.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: merchant.to_account_info(),
+            },
+            &[&[
+                b"merchant",
+                &merchant.merchant_id.to_le_bytes(),
+                &[merchant.bump],
+            ]],
+        ))?;
+
+        emit!(MerchantClosed {
+            merchant_id: merchant.merchant_id,
+            owner: merchant.owner,
+        });
+
+        // Registry lamports are returned to the owner by the `close` constraint
+        Ok(())
+    }
+
+    /// Claim a human-readable handle for a merchant (owner only)
+    /// Handles are unique: the PDA is keyed by the normalized handle itself
+    pub fn claim_handle(ctx: Context<ClaimHandle>, handle: String) -> Result<()> {
+        validate_handle(&handle)?;
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.claimed_handles = merchant.claimed_handles.saturating_add(1);
+
+        let merchant_handle = &mut ctx.accounts.merchant_handle;
+        merchant_handle.version = MERCHANT_HANDLE_VERSION;
+        merchant_handle.handle = handle.clone();
+        merchant_handle.merchant_id = ctx.accounts.merchant_registry.merchant_id;
+        merchant_handle.owner = ctx.accounts.owner.key();
+        merchant_handle.bump = ctx.bumps.merchant_handle;
+
+        emit!(HandleClaimed {
+            handle,
+            merchant_id: merchant_handle.merchant_id,
+            owner: merchant_handle.owner,
+        });
+
+        Ok(())
+    }
+
+    /// Point a handle at another merchant and hand it to that merchant's owner
+    /// Both the current and the new owner sign, so nobody receives a handle unasked
+    pub fn transfer_handle(ctx: Context<TransferHandle>) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.claimed_handles = merchant.claimed_handles.saturating_sub(1);
+        let new_merchant = &mut ctx.accounts.new_merchant_registry;
+        new_merchant.claimed_handles = new_merchant.claimed_handles.saturating_add(1);
+
+        let merchant_handle = &mut ctx.accounts.merchant_handle;
+        let from_merchant_id = merchant_handle.merchant_id;
+
+        merchant_handle.merchant_id = ctx.accounts.new_merchant_registry.merchant_id;
+        merchant_handle.owner = ctx.accounts.new_owner.key();
+
+        emit!(HandleTransferred {
+            handle: merchant_handle.handle.clone(),
+            from_merchant_id,
+            to_merchant_id: merchant_handle.merchant_id,
+            new_owner: merchant_handle.owner,
+        });
+
+        Ok(())
+    }
+
+    /// Release a handle (owner only); it can be claimed again afterwards
+    pub fn release_handle(ctx: Context<ReleaseHandle>) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.claimed_handles = merchant.claimed_handles.saturating_sub(1);
+
+        let merchant_handle = &ctx.accounts.merchant_handle;
+
+        emit!(HandleReleased {
+            handle: merchant_handle.handle.clone(),
+            merchant_id: merchant_handle.merchant_id,
+            owner: merchant_handle.owner,
+        });
+
+        // Handle lamports are returned to the owner by the `close` constraint
+        Ok(())
+    }
+
+    /// Open a merchant's day bucket before its first settlement (permissionless)
+    /// Lets a crank fund buckets so the first payer of the day does not pay the rent
+    pub fn open_day_stats(ctx: Context<OpenDayStats>, merchant_id: u64, day: u32) -> Result<()> {
+        require!(
+            day >= day_index(Clock::get()?.unix_timestamp),
+            QantaraError::InvalidDayStatsDay
+        );
+
+        let stats = &mut ctx.accounts.merchant_day_stats;
+        stats.version = MERCHANT_DAY_STATS_VERSION;
+        stats.merchant_id = merchant_id;
+        stats.day = day;
+        stats.rent_payer = ctx.accounts.payer.key();
+        stats.bump = ctx.bumps.merchant_day_stats;
+        Ok(())
+    }
+
+    /// Close a day bucket older than the retention period (permissionless crank)
+    pub fn close_day_stats(ctx: Context<CloseDayStats>) -> Result<()> {
+        let stats = &ctx.accounts.merchant_day_stats;
+        require!(
+            stats.day.saturating_add(DAY_STATS_RETENTION_DAYS)
+                < day_index(Clock::get()?.unix_timestamp),
+            QantaraError::InvalidDayStatsDay
+        );
+
+        emit!(DayStatsClosed {
+            merchant_id: stats.merchant_id,
+            day: stats.day,
+        });
+
+        // Bucket lamports are returned to the rent payer by the `close` constraint
+        Ok(())
+    }
+
+    /// Close a receipt after the retention period (payer, rent payer or merchant owner)
+    /// Rent returns to whoever funded the receipt (`rent_payer`: the payer, or its sponsor)
+    pub fn close_receipt(ctx: Context<CloseReceipt>) -> Result<()> {
+        let receipt = &ctx.accounts.receipt;
+        let authority = ctx.accounts.authority.key();
+
+        let is_merchant_owner = ctx
+            .accounts
+            .merchant_registry
+            .as_ref()
+            .is_some_and(|merchant| merchant.owner == authority);
+        require!(
+            authority == receipt.payer || authority == receipt.rent_payer || is_merchant_owner,
+            QantaraError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= receipt.timestamp.saturating_add(RECEIPT_RETENTION_SECS),
+            QantaraError::ReceiptRetentionActive
+        );
+
+        emit!(ReceiptClosed {
+            merchant_id: receipt.merchant_id,
+            order_reference: receipt.order_reference,
+            closed_by: authority,
+        });
+
+        // Receipt lamports are returned to the rent payer by the `close` constraint
+        Ok(())
+    }
+
+    /// Initialize vault USDC token account (one-time setup)
+    pub fn init_vault_usdc(ctx: Context<InitVaultUsdc>) -> Result<()> {
+        // Account is initialized by Anchor constraints
+        // This instruction just ensures the vault_usdc PDA token account exists
+        msg!("Vault USDC token account initialized at: {}", ctx.accounts.vault_usdc.key());
+        Ok(())
+    }
+
+    /// Execute payment settlement with full security validations
+    /// An optional `args.tip` goes from the payer straight to the merchant's tip wallet, outside the
+    /// buyback split (protocol fee per the merchant's tip policy)
+    pub fn settle(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        amount: u64,
+        pay_token: PayToken,
+        args: SettleArgs,
+    ) -> Result<()> {
+        process_settlement(&mut ctx, SettlementRequest::new(merchant_id, amount, pay_token, args))
+    }
+
+    /// Settle a USD-priced payment in SOL
+    /// `usd_amount` (6 decimals) is converted to lamports at the SOL/USD oracle price,
+    /// bounded by the payer-supplied `max_lamports`, then split like a regular settlement
+    pub fn settle_usd(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        usd_amount: u64,
+        max_lamports: u64,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require!(args.tip.is_none(), QantaraError::UnsupportedSettleArg);
+        let (amount, usd_conversion) = convert_usd_to_lamports(&ctx, usd_amount)?;
+        require!(
+            amount > 0 && amount <= max_lamports,
+            QantaraError::UsdConversionExceedsMax
+        );
+
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                usd_conversion: Some(usd_conversion),
+                ..SettlementRequest::new(merchant_id, amount, PayToken::Sol, args)
+            },
+        )
+    }
+
+    /// Settle a USDC invoice paid with any token the router can swap
+    /// The payer's token is swapped into vault_usdc (spending at most `input_swap.max_amount_in`),
+    /// exactly `amount` USDC is settled and any excess USDC is refunded to the payer
+    pub fn settle_with_swap(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        amount: u64,
+        input_swap: InputSwap,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require!(args.tip.is_none(), QantaraError::UnsupportedSettleArg);
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                input_swap: Some(input_swap),
+                ..SettlementRequest::new(merchant_id, amount, PayToken::Usdc, args)
+            },
+        )
+    }
+
+    /// Settle a USDC payment whose transaction fees and rent are covered by a relayer
+    /// (gasless checkout). `payer` authorizes the transfer out of `payer_usdc_account` as its
+    /// owner or approved delegate and needs no SOL; `sponsor` funds the receipt rent, and the
+    /// relayer signs as the transaction fee payer. `beneficiary` is the customer
+    /// credited with the payment, recorded in `PaymentSettled`
+    pub fn settle_sponsored(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        amount: u64,
+        beneficiary: Option<Pubkey>,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require!(ctx.accounts.sponsor.is_some(), QantaraError::SponsorRequired);
+        require!(args.tip.is_none(), QantaraError::UnsupportedSettleArg);
+
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                beneficiary,
+                ..SettlementRequest::new(merchant_id, amount, PayToken::Usdc, args)
+            },
+        )
+    }
+
+    /// Hold a payer's funds for a merchant until `capture` or `void`
+    /// SOL is held in the authorization PDA, USDC in its escrow token account
+    pub fn authorize(
+        ctx: Context<Authorize>,
+        authorization_id: [u8; 32],
+        amount: u64,
+        pay_token: PayToken,
+        expires_at: i64,
+    ) -> Result<()> {
+        let protocol_config = &ctx.accounts.protocol_config;
+        let merchant = &ctx.accounts.merchant_registry;
+        require!(!protocol_config.paused, QantaraError::ProtocolPaused);
+        require!(!merchant.frozen, QantaraError::MerchantFrozen);
+        require!(amount > 0, QantaraError::InvalidAuthorizationAmount);
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            expires_at > now && expires_at <= now.saturating_add(MAX_AUTHORIZATION_SECS),
+            QantaraError::InvalidAuthorizationExpiry
+        );
+
+        check_payer_lists(
+            protocol_config,
+            merchant,
+            ctx.accounts.payer.key(),
+            ctx.accounts.payer_usdc_account.as_ref().map(|account| account.owner),
+            ctx.accounts.protocol_payer_entry.as_ref(),
+            ctx.accounts.merchant_payer_entry.as_ref(),
+        )?;
+
+        match pay_token {
+            PayToken::Sol => {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.payer.to_account_info(),
+                            to: ctx.accounts.authorization.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+            }
+            PayToken::Usdc => {
+                let (Some(escrow), Some(_), Some(payer_usdc)) = (
+                    &ctx.accounts.escrow_usdc,
+                    &ctx.accounts.usdc_mint,
+                    &ctx.accounts.payer_usdc_account,
+                ) else {
+                    return err!(QantaraError::AuthorizationEscrowRequired);
+                };
+                token::transfer(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: payer_usdc.to_account_info(),
+                            to: escrow.to_account_info(),
+                            authority: ctx.accounts.payer.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+            }
+        }
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.open_authorizations = merchant.open_authorizations.saturating_add(1);
+
+        let authorization = &mut ctx.accounts.authorization;
+        authorization.version = AUTHORIZATION_VERSION;
+        authorization.merchant_id = merchant.merchant_id;
+        authorization.authorization_id = authorization_id;
+        authorization.payer = ctx.accounts.payer.key();
+        authorization.pay_token = pay_token;
+        authorization.amount = amount;
+        authorization.expires_at = expires_at;
+        authorization.created_at = now;
+        authorization.bump = ctx.bumps.authorization;
+
+        emit!(PaymentAuthorized {
+            merchant_id: merchant.merchant_id,
+            authorization_id,
+            payer: authorization.payer,
+            pay_token,
+            amount,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Capture part or all of an authorization (merchant owner signs as `payer`, before expiry)
+    /// The captured amount runs the normal settlement split; the authorization id is the
+    /// order reference. The remainder stays in escrow until `void_authorization`
+    pub fn capture(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        amount: u64,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.payer.key(),
+            ctx.accounts.merchant_registry.owner,
+            QantaraError::Unauthorized
+        );
+        require!(
+            args.order_reference.is_none() && args.tip.is_none(),
+            QantaraError::UnsupportedSettleArg
+        );
+        let authorization = ctx
+            .accounts
+            .authorization
+            .as_ref()
+            .ok_or(QantaraError::AuthorizationRequired)?;
+        authorization.check_capture(amount, Clock::get()?.unix_timestamp)?;
+        let pay_token = authorization.pay_token;
+        let authorization_id = authorization.authorization_id;
+        let customer = authorization.payer;
+        if pay_token == PayToken::Usdc {
+            let escrow = ctx
+                .accounts
+                .escrow_usdc
+                .as_ref()
+                .ok_or(QantaraError::AuthorizationEscrowRequired)?;
+            require_keys_eq!(
+                escrow.owner,
+                authorization.key(),
+                QantaraError::AuthorizationEscrowRequired
+            );
+        }
+
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                order_reference: Some(authorization_id),
+                beneficiary: Some(customer),
+                escrowed: true,
+                quote_exempt: true,
+                ..SettlementRequest::new(merchant_id, amount, pay_token, args)
+            },
+        )?;
+
+        let authorization = ctx
+            .accounts
+            .authorization
+            .as_mut()
+            .ok_or(QantaraError::AuthorizationRequired)?;
+        let remainder = authorization.record_capture(amount);
+
+        emit!(AuthorizationCaptured {
+            merchant_id,
+            authorization_id,
+            amount,
+            remainder,
+        });
+
+        Ok(())
+    }
+
+    /// Return an authorization's remaining funds to the payer and close it
+    /// The merchant owner may void at any time; anyone may once it is captured or expired
+    pub fn void_authorization(ctx: Context<VoidAuthorization>) -> Result<()> {
+        let authorization = &ctx.accounts.authorization;
+        let authority = ctx.accounts.authority.key();
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            authority == ctx.accounts.merchant_registry.owner
+                || authorization.voidable_by_anyone(now),
+            QantaraError::AuthorizationActive
+        );
+
+        let refunded = authorization.remainder();
+        if authorization.pay_token == PayToken::Usdc {
+            let (Some(escrow), Some(payer_usdc)) =
+                (&ctx.accounts.escrow_usdc, &ctx.accounts.payer_usdc_account)
+            else {
+                return err!(QantaraError::AuthorizationEscrowRequired);
+            };
+            let signer_seeds: &[&[u8]] = &[
+                b"authorization",
+                &authorization.merchant_id.to_le_bytes(),
+                &authorization.authorization_id,
+                &[authorization.bump],
+            ];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: escrow.to_account_info(),
+                        to: payer_usdc.to_account_info(),
+                        authority: authorization.to_account_info(),
+                    },
+                    &[signer_seeds],
+                ),
+                escrow.amount,
+            )?;
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: escrow.to_account_info(),
+                    destination: ctx.accounts.payer.to_account_info(),
+                    authority: authorization.to_account_info(),
+                },
+                &[signer_seeds],
+            ))?;
+        }
+        // SOL remainder leaves with the authorization's lamports via the `close` constraint
+
+        emit!(AuthorizationVoided {
+            merchant_id: authorization.merchant_id,
+            authorization_id: authorization.authorization_id,
+            refunded,
+            voided_by: authority,
+        });
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.open_authorizations = merchant.open_authorizations.saturating_sub(1);
+
+        Ok(())
+    }
+
+    /// Create a plan splitting a purchase into installments for one payer
+    pub fn create_installment_plan(
+        ctx: Context<CreateInstallmentPlan>,
+        plan_id: [u8; 32],
+        payer: Pubkey,
+        terms: InstallmentTerms,
+    ) -> Result<()> {
+        terms.validate()?;
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.open_installment_plans = merchant.open_installment_plans.saturating_add(1);
+
+        let merchant_id = merchant.merchant_id;
+        let plan = &mut ctx.accounts.installment_plan;
+        plan.version = INSTALLMENT_PLAN_VERSION;
+        plan.merchant_id = merchant_id;
+        plan.plan_id = plan_id;
+        plan.payer = payer;
+        plan.terms = terms;
+        plan.status = InstallmentStatus::Active;
+        plan.created_at = Clock::get()?.unix_timestamp;
+        plan.bump = ctx.bumps.installment_plan;
+
+        emit!(InstallmentPlanCreated {
+            merchant_id,
+            plan_id,
+            payer,
+            terms,
+        });
+
+        Ok(())
+    }
+
+    /// Pay the next installment of a plan (the plan's payer signs as `payer`)
+    /// Runs the normal settlement split; the order reference is derived from the plan id and
+    /// installment index. A defaulted plan may still be paid and becomes active once caught up
+    pub fn settle_installment(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require!(
+            args.order_reference.is_none() && args.tip.is_none(),
+            QantaraError::UnsupportedSettleArg
+        );
+        let plan = ctx
+            .accounts
+            .installment_plan
+            .as_ref()
+            .ok_or(QantaraError::InstallmentPlanRequired)?;
+        require_keys_eq!(
+            ctx.accounts.payer.key(),
+            plan.payer,
+            QantaraError::InstallmentPayerMismatch
+        );
+        require!(
+            plan.status != InstallmentStatus::Completed,
+            QantaraError::InstallmentPlanCompleted
+        );
+        let plan_id = plan.plan_id;
+        let installment_index = plan.paid_count;
+        let terms = plan.terms;
+        let order_reference = installment_reference(&plan_id, installment_index);
+
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                order_reference: Some(order_reference),
+                quote_exempt: true,
+                ..SettlementRequest::new(merchant_id, terms.installment_amount, terms.pay_token, args)
+            },
+        )?;
+
+        let plan = ctx
+            .accounts
+            .installment_plan
+            .as_mut()
+            .ok_or(QantaraError::InstallmentPlanRequired)?;
+        plan.record_payment(Clock::get()?.unix_timestamp);
+
+        emit!(InstallmentPaid {
+            merchant_id,
+            plan_id,
+            installment_index,
+            order_reference,
+            paid_count: plan.paid_count,
+            status: plan.status,
+        });
+
+        Ok(())
+    }
+
+    /// Flag a plan whose next installment is unpaid past its grace period (permissionless crank)
+    /// Emits `InstallmentDefaulted` for the merchant to act on
+    pub fn mark_installment_default(ctx: Context<MarkInstallmentDefault>) -> Result<()> {
+        let plan = &mut ctx.accounts.installment_plan;
+        require!(
+            plan.status != InstallmentStatus::Completed,
+            QantaraError::InstallmentPlanCompleted
+        );
+        require!(
+            plan.status != InstallmentStatus::Defaulted,
+            QantaraError::InstallmentPlanDefaulted
+        );
+        require!(
+            plan.is_overdue(Clock::get()?.unix_timestamp),
+            QantaraError::InstallmentNotOverdue
+        );
+
+        plan.status = InstallmentStatus::Defaulted;
+        let unpaid = plan.terms.installment_count - plan.paid_count;
+
+        emit!(InstallmentDefaulted {
+            merchant_id: plan.merchant_id,
+            plan_id: plan.plan_id,
+            payer: plan.payer,
+            installment_index: plan.paid_count,
+            due_at: plan.due_at(plan.paid_count),
+            outstanding: plan.terms.installment_amount.saturating_mul(unpaid as u64),
+        });
+
+        Ok(())
+    }
+
+    /// Close an installment plan (completed, defaulted or cancelled by the merchant)
+    pub fn close_installment_plan(ctx: Context<CloseInstallmentPlan>) -> Result<()> {
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.open_installment_plans = merchant.open_installment_plans.saturating_sub(1);
+
+        let plan = &ctx.accounts.installment_plan;
+
+        emit!(InstallmentPlanClosed {
+            merchant_id: plan.merchant_id,
+            plan_id: plan.plan_id,
+            status: plan.status,
+            paid_count: plan.paid_count,
+        });
+
+        Ok(())
+    }
+
+    /// Open an invoice several payers fund together; settled once `target_amount` is reached
+    /// SOL contributions are held in the invoice PDA, USDC in its escrow token account
+    pub fn create_group_invoice(
+        ctx: Context<CreateGroupInvoice>,
+        invoice_id: [u8; 32],
+        pay_token: PayToken,
+        target_amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            target_amount > 0
+                && expires_at > now
+                && expires_at <= now.saturating_add(MAX_GROUP_INVOICE_SECS),
+            QantaraError::InvalidGroupInvoiceTerms
+        );
+        if pay_token == PayToken::Usdc {
+            require!(
+                ctx.accounts.escrow_usdc.is_some() && ctx.accounts.usdc_mint.is_some(),
+                QantaraError::GroupInvoiceEscrowRequired
+            );
+        }
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.open_group_invoices = merchant.open_group_invoices.saturating_add(1);
+
+        let invoice = &mut ctx.accounts.group_invoice;
+        invoice.version = GROUP_INVOICE_VERSION;
+        invoice.merchant_id = merchant.merchant_id;
+        invoice.invoice_id = invoice_id;
+        invoice.pay_token = pay_token;
+        invoice.target_amount = target_amount;
+        invoice.expires_at = expires_at;
+        invoice.created_at = now;
+        invoice.bump = ctx.bumps.group_invoice;
+
+        emit!(GroupInvoiceCreated {
+            merchant_id: merchant.merchant_id,
+            invoice_id,
+            pay_token,
+            target_amount,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Contribute part of a group invoice; repeat contributions add to the payer's record
+    pub fn contribute(ctx: Context<Contribute>, amount: u64) -> Result<()> {
+        let protocol_config = &ctx.accounts.protocol_config;
+        let merchant = &ctx.accounts.merchant_registry;
+        require!(!protocol_config.paused, QantaraError::ProtocolPaused);
+        require!(!merchant.frozen, QantaraError::MerchantFrozen);
+
+        let invoice = &ctx.accounts.group_invoice;
+        let now = Clock::get()?.unix_timestamp;
+        invoice.check_contribution(amount, now)?;
+
+        check_payer_lists(
+            protocol_config,
+            merchant,
+            ctx.accounts.contributor.key(),
+            ctx.accounts
+                .contributor_usdc_account
+                .as_ref()
+                .map(|account| account.owner),
+            ctx.accounts.protocol_payer_entry.as_ref(),
+            ctx.accounts.merchant_payer_entry.as_ref(),
+        )?;
+
+        match invoice.pay_token {
+            PayToken::Sol => {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.contributor.to_account_info(),
+                            to: invoice.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+            }
+            PayToken::Usdc => {
+                let (Some(escrow), Some(contributor_usdc)) = (
+                    &ctx.accounts.escrow_usdc,
+                    &ctx.accounts.contributor_usdc_account,
+                ) else {
+                    return err!(QantaraError::GroupInvoiceEscrowRequired);
+                };
+                token::transfer(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: contributor_usdc.to_account_info(),
+                            to: escrow.to_account_info(),
+                            authority: ctx.accounts.contributor.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+            }
+        }
+
+        // Contribution record: created by the payer's first contribution
+        let invoice_key = invoice.key();
+        let contributor = ctx.accounts.contributor.key();
+        let account = ctx.accounts.contribution.to_account_info();
+        let first_contribution = account.data_is_empty();
+        let mut record = if first_contribution {
+            let bump = ctx.bumps.contribution;
+            create_pda_account(
+                &account,
+                &ctx.accounts.contributor.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                8 + Contribution::INIT_SPACE,
+                &[b"contribution", invoice_key.as_ref(), contributor.as_ref(), &[bump]],
+            )?;
+            Contribution {
+                version: CONTRIBUTION_VERSION,
+                group_invoice: invoice_key,
+                contributor,
+                amount: 0,
+                last_contributed_at: 0,
+                bump,
+                reserved: [0; CONTRIBUTION_RESERVED],
+            }
+        } else {
+            Contribution::try_deserialize(&mut &account.try_borrow_data()?[..])?
+        };
+        record.amount = record.amount.saturating_add(amount);
+        record.last_contributed_at = now;
+        record.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+
+        let invoice = &mut ctx.accounts.group_invoice;
+        invoice.record_contribution(amount, first_contribution)?;
+
+        emit!(GroupContribution {
+            merchant_id: invoice.merchant_id,
+            invoice_id: invoice.invoice_id,
+            contributor,
+            amount,
+            funded_amount: invoice.funded_amount,
+            target_amount: invoice.target_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Settle a fully funded group invoice (merchant owner signs as `payer`, before expiry)
+    /// The escrowed total runs the normal settlement split; the invoice id is the order reference
+    pub fn settle_group_invoice(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require_keys_eq!(
+            ctx.accounts.payer.key(),
+            ctx.accounts.merchant_registry.owner,
+            QantaraError::Unauthorized
+        );
+        require!(
+            args.order_reference.is_none() && args.tip.is_none(),
+            QantaraError::UnsupportedSettleArg
+        );
+        require!(
+            ctx.accounts.authorization.is_none(),
+            QantaraError::GroupInvoiceRequired
+        );
+        let invoice = ctx
+            .accounts
+            .group_invoice
+            .as_ref()
+            .ok_or(QantaraError::GroupInvoiceRequired)?;
+        require!(!invoice.settled, QantaraError::GroupInvoiceSettled);
+        require!(
+            Clock::get()?.unix_timestamp < invoice.expires_at,
+            QantaraError::GroupInvoiceExpired
+        );
+        require!(
+            invoice.funded_amount == invoice.target_amount,
+            QantaraError::GroupInvoiceUnderfunded
+        );
+        let amount = invoice.target_amount;
+        let pay_token = invoice.pay_token;
+        let invoice_id = invoice.invoice_id;
+        let contributor_count = invoice.contributor_count;
+        if pay_token == PayToken::Usdc {
+            let escrow = ctx
+                .accounts
+                .escrow_usdc
+                .as_ref()
+                .ok_or(QantaraError::GroupInvoiceEscrowRequired)?;
+            require_keys_eq!(
+                escrow.owner,
+                invoice.key(),
+                QantaraError::GroupInvoiceEscrowRequired
+            );
+        }
+
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                order_reference: Some(invoice_id),
+                escrowed: true,
+                quote_exempt: true,
+                ..SettlementRequest::new(merchant_id, amount, pay_token, args)
+            },
+        )?;
+
+        let invoice = ctx
+            .accounts
+            .group_invoice
+            .as_mut()
+            .ok_or(QantaraError::GroupInvoiceRequired)?;
+        invoice.settled = true;
+        invoice.funded_amount = 0;
+
+        emit!(GroupInvoiceSettled {
+            merchant_id,
+            invoice_id,
+            amount,
+            contributor_count,
+        });
+
+        Ok(())
+    }
+
+    /// Close a contribution record once its invoice is settled or expired (permissionless)
+    /// Contributions to an expired, unsettled invoice are refunded; rent returns to the contributor
+    pub fn reclaim_contribution(ctx: Context<ReclaimContribution>) -> Result<()> {
+        let invoice = &ctx.accounts.group_invoice;
+        let refunded =
+            invoice.reclaim_refund(ctx.accounts.contribution.amount, Clock::get()?.unix_timestamp)?;
+        if refunded > 0 {
+            match invoice.pay_token {
+                PayToken::Sol => {
+                    // The invoice is program-owned, so its escrowed lamports move directly
+                    let from = invoice.to_account_info();
+                    let to = ctx.accounts.contributor.to_account_info();
+                    **from.try_borrow_mut_lamports()? = from
+                        .lamports()
+                        .checked_sub(refunded)
+                        .ok_or(QantaraError::InvalidContributionAmount)?;
+                    **to.try_borrow_mut_lamports()? = to
+                        .lamports()
+                        .checked_add(refunded)
+                        .ok_or(QantaraError::InvalidContributionAmount)?;
+                }
+                PayToken::Usdc => {
+                    let (Some(escrow), Some(contributor_usdc)) = (
+                        &ctx.accounts.escrow_usdc,
+                        &ctx.accounts.contributor_usdc_account,
+                    ) else {
+                        return err!(QantaraError::GroupInvoiceEscrowRequired);
+                    };
+                    token::transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.token_program.to_account_info(),
+                            token::Transfer {
+                                from: escrow.to_account_info(),
+                                to: contributor_usdc.to_account_info(),
+                                authority: invoice.to_account_info(),
+                            },
+                            &[&[
+                                b"group_invoice",
+                                &invoice.merchant_id.to_le_bytes(),
+                                &invoice.invoice_id,
+                                &[invoice.bump],
+                            ]],
+                        ),
+                        refunded,
+                    )?;
+                }
+            }
+        }
+        // Contribution rent returns to the contributor via the `close` constraint
+
+        let invoice = &mut ctx.accounts.group_invoice;
+        invoice.record_reclaim(refunded);
+
+        emit!(ContributionReclaimed {
+            merchant_id: invoice.merchant_id,
+            invoice_id: invoice.invoice_id,
+            contributor: ctx.accounts.contributor.key(),
+            refunded,
+        });
+
+        Ok(())
+    }
+
+    /// Close a group invoice and its escrow once every contribution record is reclaimed
+    /// (merchant owner; rent returns to the owner)
+    pub fn close_group_invoice(ctx: Context<CloseGroupInvoice>) -> Result<()> {
+        let invoice = &ctx.accounts.group_invoice;
+        require!(
+            invoice.contributor_count == 0,
+            QantaraError::ContributionsOutstanding
+        );
+
+        if let Some(escrow) = &ctx.accounts.escrow_usdc {
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: invoice.to_account_info(),
+                },
+                &[&[
+                    b"group_invoice",
+                    &invoice.merchant_id.to_le_bytes(),
+                    &invoice.invoice_id,
+                    &[invoice.bump],
+                ]],
+            ))?;
+        }
+        // Invoice lamports are returned to the owner by the `close` constraint
+
+        emit!(GroupInvoiceClosed {
+            merchant_id: invoice.merchant_id,
+            invoice_id: invoice.invoice_id,
+            settled: invoice.settled,
+        });
+
+        let merchant = &mut ctx.accounts.merchant_registry;
+        merchant.open_group_invoices = merchant.open_group_invoices.saturating_sub(1);
+
+        Ok(())
+    }
+
+    /// Donate any amount at or above the merchant's minimum, with an optional donor message
+    /// Requires donation mode; no quote is needed and verified nonprofits pay no protocol fee
+    pub fn donate(
+        mut ctx: Context<Settle>,
+        merchant_id: u64,
+        amount: u64,
+        pay_token: PayToken,
+        message: Option<String>,
+        args: SettleArgs,
+    ) -> Result<()> {
+        require!(args.tip.is_none(), QantaraError::UnsupportedSettleArg);
+        let settings = ctx
+            .accounts
+            .donation_settings
+            .as_ref()
+            .ok_or(QantaraError::DonationSettingsRequired)?;
+        require!(settings.enabled, QantaraError::DonationsDisabled);
+        require!(
+            settings.terms(pay_token).accepts(amount),
+            QantaraError::DonationBelowMinimum
+        );
+        if let Some(message) = &message {
+            require!(
+                message.len() <= MAX_DONATION_MESSAGE_LEN,
+                QantaraError::DonationMessageTooLong
+            );
+        }
+        let fee_waived = ctx.accounts.merchant_registry.verified_nonprofit;
+
+        process_settlement(
+            &mut ctx,
+            SettlementRequest {
+                quote_exempt: true,
+                fee_waived,
+                ..SettlementRequest::new(merchant_id, amount, pay_token, args)
+            },
+        )?;
+
+        emit!(DonationReceived {
+            merchant_id,
+            donor: ctx.accounts.payer.key(),
+            pay_token,
+            amount,
+            fee_waived,
+            message,
+        });
+
+        Ok(())
+    }
+}
+
+// Helper functions
+
+/// Parameters of a single settlement, shared by every settle entrypoint
+struct SettlementRequest {
+    merchant_id: u64,
+    amount: u64,
+    pay_token: PayToken,
+    min_out: u64,
+    payout_bps: u16,
+    buyback_bps: u16,
+    burn_of_buyback_bps: u16,
+    swap_data: Vec<u8>,
+    payout_swap: Option<PayoutSwap>,
+    input_swap: Option<InputSwap>,
+    usd_conversion: Option<UsdConversion>,
+    order_reference: Option<[u8; 32]>,
+    memo: Option<String>,
+    beneficiary: Option<Pubkey>,
+    /// Funds come from an escrow (authorization or group invoice) whose payers were checked
+    /// when the funds entered it
+    escrowed: bool,
+    /// Amount is not quoted per payment (fixed on-chain by an authorization, installment plan
+    /// or group invoice, or chosen by a donor), so no signed quote is needed
+    quote_exempt: bool,
+    /// No protocol fee (donations to verified nonprofits)
+    fee_waived: bool,
+    /// Payer-added gratuity paid on top of `amount` to the merchant's tip wallet
+    tip: u64,
+}
+
+impl SettlementRequest {
+    /// Plain settlement of `amount` in `pay_token` with the caller's split and optional inputs
+    fn new(merchant_id: u64, amount: u64, pay_token: PayToken, args: SettleArgs) -> Self {
+        Self {
+            merchant_id,
+            amount,
+            pay_token,
+            min_out: args.min_out,
+            payout_bps: args.payout_bps,
+            buyback_bps: args.buyback_bps,
+            burn_of_buyback_bps: args.burn_of_buyback_bps,
+            swap_data: args.swap_data,
+            payout_swap: args.payout_swap,
+            input_swap: None,
+            usd_conversion: None,
+            order_reference: args.order_reference,
+            memo: args.memo,
+            beneficiary: None,
+            escrowed: false,
+            quote_exempt: false,
+            fee_waived: false,
+            tip: args.tip.unwrap_or(0),
+        }
+    }
+}
+
+/// Protocol denylist, then the merchant's denylist or allowlist
+fn check_payer_lists(
+    protocol_config: &ProtocolConfig,
+    merchant: &MerchantRegistry,
+    payer: Pubkey,
+    token_owner: Option<Pubkey>,
+    protocol_entry: Option<&UncheckedAccount>,
+    merchant_entry: Option<&UncheckedAccount>,
+) -> Result<()> {
+    // Entries are keyed by the signer, so a delegate must not pay out of someone else's account
+    let lists_in_use = protocol_config.denied_payers > 0
+        || merchant.denied_payers > 0
+        || merchant.allowlist_enabled;
+    if let (true, Some(owner)) = (lists_in_use, token_owner) {
+        require_keys_eq!(owner, payer, QantaraError::PayerNotTokenOwner);
+    }
+    if protocol_config.denied_payers > 0 {
+        let entry = protocol_entry.ok_or(QantaraError::PayerListEntryRequired)?;
+        require!(
+            payer_list_kind(entry)? != Some(PayerListKind::Deny),
+            QantaraError::PayerDenied
+        );
+    }
+    if merchant.denied_payers > 0 || merchant.allowlist_enabled {
+        let entry = merchant_entry.ok_or(QantaraError::PayerListEntryRequired)?;
+        let kind = payer_list_kind(entry)?;
+        require!(kind != Some(PayerListKind::Deny), QantaraError::PayerDenied);
+        require!(
+            !merchant.allowlist_enabled || kind == Some(PayerListKind::Allow),
+            QantaraError::PayerNotAllowlisted
+        );
     }
+    Ok(())
+}
 
-    /// Update merchant registry (owner only)
-    pub fn update_merchant(
-        ctx: Context<UpdateMerchant>,
-        payout_wallet: Option<Pubkey>,
-        buyback_mint: Option<Pubkey>,
-        frozen: Option<bool>,
-    ) -> Result<()> {
-        let merchant = &mut ctx.accounts.merchant_registry;
+/// Store validated metadata and announce the change
+fn write_merchant_metadata(metadata: &mut MerchantMetadata, args: MerchantMetadataArgs) {
+    metadata.name = args.name;
+    metadata.uri = args.uri;
+    metadata.website = args.website;
+    metadata.support_contact = args.support_contact;
+    metadata.category_code = args.category_code;
+    metadata.terms_hash = args.terms_hash;
 
-        if let Some(wallet) = payout_wallet {
-            merchant.payout_wallet = wallet;
-        }
+    emit!(MerchantMetadataUpdated {
+        merchant_id: metadata.merchant_id,
+        name: metadata.name.clone(),
+        uri: metadata.uri.clone(),
+        website: metadata.website.clone(),
+        support_contact: metadata.support_contact.clone(),
+        category_code: metadata.category_code,
+        terms_hash: metadata.terms_hash,
+    });
+}
 
-        if let Some(mint) = buyback_mint {
-            merchant.buyback_mint = mint;
-        }
+/// Receive payment, enforce protocol fee, run buyback/burn and pay the merchant
+fn process_settlement(ctx: &mut Context<Settle>, request: SettlementRequest) -> Result<()> {
+    let SettlementRequest {
+        merchant_id,
+        amount,
+        pay_token,
+        min_out,
+        payout_bps,
+        buyback_bps,
+        burn_of_buyback_bps,
+        swap_data,
+        payout_swap,
+        input_swap,
+        usd_conversion,
+        order_reference,
+        memo,
+        beneficiary,
+        escrowed,
+        quote_exempt,
+        fee_waived,
+        tip,
+    } = request;
 
-        if let Some(freeze) = frozen {
-            merchant.frozen = freeze;
-            emit!(MerchantFrozen {
-                merchant_id: merchant.merchant_id,
-                frozen: freeze,
-            });
-        }
+    let protocol_config = &ctx.accounts.protocol_config;
+    let merchant = &ctx.accounts.merchant_registry;
 
-        Ok(())
+    // SECURITY CHECK 1: Protocol not paused
+    require!(!protocol_config.paused, QantaraError::ProtocolPaused);
+
+    // SECURITY CHECK 2: Merchant exists and matches ID
+    require!(
+        merchant.merchant_id == merchant_id,
+        QantaraError::InvalidMerchantId
+    );
+
+    // SECURITY CHECK 2a: A handle, if the payer resolved the merchant from one, points here
+    if let Some(merchant_handle) = &ctx.accounts.merchant_handle {
+        require!(
+            merchant_handle.merchant_id == merchant_id,
+            QantaraError::MerchantHandleMismatch
+        );
     }
 
-    /// Initialize vault USDC token account (one-time setup)
-    pub fn init_vault_usdc(ctx: Context<InitVaultUsdc>) -> Result<()> {
-        // Account is initialized by Anchor constraints
-        // This instruction just ensures the vault_usdc PDA token account exists
-        msg!("Vault USDC token account initialized at: {}", ctx.accounts.vault_usdc.key());
-        Ok(())
+    // SECURITY CHECK 3: Merchant not frozen
+    require!(!merchant.frozen, QantaraError::MerchantFrozen);
+
+    // SECURITY CHECK 3a: Payer lists (escrowed funds were checked when they entered escrow)
+    if !escrowed {
+        check_payer_lists(
+            protocol_config,
+            merchant,
+            ctx.accounts.payer.key(),
+            (pay_token == PayToken::Usdc).then(|| ctx.accounts.payer_usdc_account.owner),
+            ctx.accounts.protocol_payer_entry.as_ref(),
+            ctx.accounts.merchant_payer_entry.as_ref(),
+        )?;
     }
 
-    /// Execute payment settlement with full security validations
-    pub fn settle(
-        ctx: Context<Settle>,
-        merchant_id: u64,
-        amount: u64,
-        pay_token: PayToken,
-        min_out: u64,
-        payout_bps: u16,
-        buyback_bps: u16,
-        burn_of_buyback_bps: u16,
-    ) -> Result<()> {
-        let protocol_config = &ctx.accounts.protocol_config;
-        let merchant = &ctx.accounts.merchant_registry;
+    // SECURITY CHECK 4: Validate payout wallet matches registry (prevents rerouting)
+    require_keys_eq!(
+        ctx.accounts.merchant_payout_wallet.key(),
+        merchant.payout_wallet,
+        QantaraError::InvalidPayoutWallet
+    );
 
-        // SECURITY CHECK 1: Protocol not paused
-        require!(!protocol_config.paused, QantaraError::ProtocolPaused);
+    // SECURITY CHECK 5: Validate buyback mint matches registry
+    require_keys_eq!(
+        ctx.accounts.buyback_mint.key(),
+        merchant.buyback_mint,
+        QantaraError::InvalidBuybackMint
+    );
 
-        // SECURITY CHECK 2: Merchant exists and matches ID
+    // SECURITY CHECK 6: Validate BPS bounds
+    require!(
+        payout_bps.checked_add(buyback_bps).unwrap_or(10001) <= 10000,
+        QantaraError::InvalidBasisPoints
+    );
+    require!(
+        burn_of_buyback_bps <= 10000,
+        QantaraError::InvalidBasisPoints
+    );
+
+    // Order reference: a Solana Pay reference account, if passed, must be the reference itself
+    if let Some(reference) = &ctx.accounts.reference {
         require!(
-            merchant.merchant_id == merchant_id,
-            QantaraError::InvalidMerchantId
+            order_reference.map(Pubkey::new_from_array) == Some(reference.key()),
+            QantaraError::OrderReferenceMismatch
         );
+    }
+    if let Some(memo) = &memo {
+        require!(memo.len() <= MAX_MEMO_LEN, QantaraError::MemoTooLong);
+    }
 
-        // SECURITY CHECK 3: Merchant not frozen
-        require!(!merchant.frozen, QantaraError::MerchantFrozen);
-
-        // SECURITY CHECK 4: Validate payout wallet matches registry (prevents rerouting)
-        require_keys_eq!(
-            ctx.accounts.merchant_payout_wallet.key(),
-            merchant.payout_wallet,
-            QantaraError::InvalidPayoutWallet
+    // Day bucket, if passed: must be this merchant's bucket for the current UTC day
+    let timestamp = Clock::get()?.unix_timestamp;
+    if let Some(day_stats) = &ctx.accounts.merchant_day_stats {
+        require!(
+            day_stats.day == day_index(timestamp),
+            QantaraError::InvalidDayStatsAccount
         );
+    }
 
-        // SECURITY CHECK 5: Validate buyback mint matches registry
-        require_keys_eq!(
-            ctx.accounts.buyback_mint.key(),
-            merchant.buyback_mint,
-            QantaraError::InvalidBuybackMint
-        );
+    // Receipt: keyed by (merchant, order reference), so an order can only be receipted once
+    let receipt_bump = match &ctx.accounts.receipt {
+        Some(receipt) => {
+            let order_reference =
+                order_reference.ok_or(QantaraError::InvalidReceiptAccount)?;
+            let (expected, bump) = Pubkey::find_program_address(
+                &[b"receipt", &merchant_id.to_le_bytes(), &order_reference],
+                ctx.program_id,
+            );
+            require_keys_eq!(receipt.key(), expected, QantaraError::InvalidReceiptAccount);
+            Some(bump)
+        }
+        None => None,
+    };
 
-        // SECURITY CHECK 6: Validate BPS bounds
+    // SECURITY CHECK 6a: Merchant payment limits (min/max amount, rolling 24h cap)
+    if merchant.limits_enabled {
+        let limits = ctx
+            .accounts
+            .merchant_limits
+            .as_mut()
+            .ok_or(QantaraError::MerchantLimitsRequired)?;
+        limits.enforce(pay_token, amount, timestamp)?;
+    }
+
+    // SECURITY CHECK 6b: Merchant-signed quote (server-side pricing is binding; captures and
+    // installments were priced on-chain by the merchant itself; donors choose their amount)
+    if merchant.quote_signer != Pubkey::default() && !quote_exempt {
+        let instructions = ctx
+            .accounts
+            .instructions_sysvar
+            .as_ref()
+            .ok_or(QantaraError::QuoteRequired)?;
+        let quote = load_signed_quote(instructions, &merchant.quote_signer)?;
+        let (quoted_amount, quoted_mint) = match &usd_conversion {
+            Some(conversion) => (conversion.usd_amount, Pubkey::default()),
+            None => match pay_token {
+                PayToken::Sol => (amount, native_mint::ID),
+                PayToken::Usdc => (amount, ctx.accounts.usdc_mint.key()),
+            },
+        };
         require!(
-            payout_bps.checked_add(buyback_bps).unwrap_or(10001) <= 10000,
-            QantaraError::InvalidBasisPoints
+            quote.merchant_id == merchant_id
+                && quote.amount == quoted_amount
+                && quote.mint == quoted_mint
+                && Some(quote.order_reference) == order_reference,
+            QantaraError::QuoteMismatch
         );
+        require!(timestamp <= quote.expires_at, QantaraError::QuoteExpired);
+    }
+
+    // SECURITY CHECK 6c: Tips go only to the wallet in the merchant's tip policy
+    let fee_on_tip = if tip > 0 {
+        verify_tip_destination(ctx, pay_token)?.fee_applies(fee_waived)
+    } else {
+        false
+    };
+
+    // SECURITY CHECK 7: Validate router against the registry (allowlist)
+    let swap_router = &ctx.accounts.swap_router;
+    require_keys_eq!(
+        ctx.accounts.jupiter_router.key(),
+        swap_router.program_id,
+        QantaraError::InvalidRouterProgram
+    );
+    require!(swap_router.enabled, QantaraError::RouterDisabled);
+
+    // SECURITY CHECK 7a: Payout conversion is required exactly when the merchant's
+    // settlement currency differs from the pay token; its route uses the trailing accounts
+    let settlement_token = resolve_settlement_token(
+        &merchant.settlement_mint,
+        &protocol_config.usdc_mint,
+    )?
+    .filter(|token| *token != pay_token && payout_bps > 0);
+    require!(
+        settlement_token.is_some() == payout_swap.is_some(),
+        QantaraError::PayoutSwapMismatch
+    );
+    let payout_account_count = payout_swap
+        .as_ref()
+        .map_or(0, |swap| swap.account_count as usize);
+    let input_account_count = input_swap
+        .as_ref()
+        .map_or(0, |swap| swap.account_count as usize);
+    require!(
+        payout_account_count + input_account_count <= ctx.remaining_accounts.len(),
+        QantaraError::InvalidSwapRouteAccounts
+    );
+    // Remaining accounts: [input route | buyback route | payout route]
+    let (input_accounts, route_accounts) = ctx.remaining_accounts.split_at(input_account_count);
+    let (buyback_accounts, payout_accounts) =
+        route_accounts.split_at(route_accounts.len() - payout_account_count);
+    if let Some(payout_swap) = &payout_swap {
         require!(
-            burn_of_buyback_bps <= 10000,
-            QantaraError::InvalidBasisPoints
+            swap_router.allows(&payout_swap.swap_data),
+            QantaraError::SwapInstructionNotAllowed
         );
+        require!(payout_swap.min_out > 0, QantaraError::InvalidMinOut);
+    }
 
-        // SECURITY CHECK 7: Validate Jupiter router (allowlist)
-        require_keys_eq!(
-            ctx.accounts.jupiter_router.key(),
-            protocol_config.jupiter_router,
-            QantaraError::InvalidRouterProgram
+    // SECURITY CHECK 7b: Only allowlisted swap entrypoints may be invoked
+    if buyback_bps > 0 && !buyback_accounts.is_empty() {
+        require!(
+            swap_router.allows(&swap_data),
+            QantaraError::SwapInstructionNotAllowed
         );
+    }
 
-        // SECURITY CHECK 8: Validate min_out > 0 (slippage protection)
-        if buyback_bps > 0 {
-            require!(min_out > 0, QantaraError::InvalidMinOut);
-        }
+    // SECURITY CHECK 8: Validate min_out > 0 (slippage protection)
+    if buyback_bps > 0 {
+        require!(min_out > 0, QantaraError::InvalidMinOut);
+    }
 
-        // STEP 1: Receive payment from buyer FIRST
-        receive_payment(&ctx, amount, pay_token)?;
+    // STEP 1: Receive payment from buyer FIRST (swapping into USDC if paying with another token)
+    match input_swap {
+        Some(input_swap) => receive_swapped_payment(ctx, amount, input_swap, input_accounts)?,
+        None if escrowed => receive_escrowed_payment(ctx, amount, pay_token)?,
+        None => receive_payment(ctx, amount, pay_token)?,
+    }
 
-        // STEP 2: Calculate and enforce protocol fee (cannot bypass)
-        let protocol_fee = (amount as u128)
-            .checked_mul(protocol_config.protocol_fee_bps as u128)
-            .and_then(|v| v.checked_div(10000))
-            .ok_or(QantaraError::InvalidBasisPoints)? as u64;
+    // STEP 2: Calculate and enforce protocol fee (cannot bypass; waived only for donations to
+    // verified nonprofits)
+    let protocol_fee = protocol_config.fee_on(amount, fee_waived)?;
 
-        // STEP 3: Transfer protocol fee FIRST (before any other splits)
-        transfer_protocol_fee(&ctx, protocol_fee, pay_token)?;
+    // STEP 3: Transfer protocol fee FIRST (before any other splits)
+    transfer_protocol_fee(ctx, protocol_fee, pay_token)?;
 
-        // STEP 4: Calculate remaining amount after protocol fee
-        let remaining = amount
-            .checked_sub(protocol_fee)
-            .ok_or(QantaraError::InvalidBasisPoints)?;
+    // STEP 3b: Tip goes straight to the tip wallet, bypassing the vault and the buyback split
+    let tip_fee = if tip > 0 {
+        pay_tip(ctx, tip, pay_token, fee_on_tip)?
+    } else {
+        0
+    };
 
-        // STEP 5: Calculate merchant splits
-        let merchant_payout = (remaining as u128)
-            .checked_mul(payout_bps as u128)
-            .and_then(|v| v.checked_div(10000))
-            .ok_or(QantaraError::InvalidBasisPoints)? as u64;
+    // STEP 4: Calculate remaining amount after protocol fee
+    let remaining = amount
+        .checked_sub(protocol_fee)
+        .ok_or(QantaraError::InvalidBasisPoints)?;
 
-        let buyback_amount = (remaining as u128)
-            .checked_mul(buyback_bps as u128)
-            .and_then(|v| v.checked_div(10000))
-            .ok_or(QantaraError::InvalidBasisPoints)? as u64;
+    // STEP 5: Calculate merchant splits
+    let merchant_payout = (remaining as u128)
+        .checked_mul(payout_bps as u128)
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(QantaraError::InvalidBasisPoints)? as u64;
 
-        // STEP 5.5: Buyback flow clarification
-        // - All community token purchases use SOL from vault_sol
-        // - If payment is USDC: The Jupiter swap route must be USDC → SOL → buyback_token
-        //   (USDC from vault_usdc → SOL to vault_sol → buyback_token to vault_buyback_token)
-        // - If payment is SOL: The Jupiter swap route must be SOL → buyback_token
-        //   (SOL from vault_sol → buyback_token to vault_buyback_token)
-        // The off-chain transaction builder is responsible for constructing the correct Jupiter route
-
-        // STEP 6: Execute buyback swap using SOL from vault_sol → buyback_token
-        // If payment was USDC, the USDC→SOL swap should have been executed first
-        // The Jupiter swap route should be: SOL → buyback_token
-        let buyback_output = if buyback_amount > 0 && !ctx.remaining_accounts.is_empty() {
-            execute_buyback_swap(&ctx, buyback_amount, min_out, pay_token)?;
-            ctx.accounts.vault_buyback_token.amount
-        } else {
-            0
-        };
+    let buyback_amount = (remaining as u128)
+        .checked_mul(buyback_bps as u128)
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(QantaraError::InvalidBasisPoints)? as u64;
 
-        // SECURITY CHECK 9: Verify slippage protection
-        if buyback_amount > 0 {
-            require!(
-                buyback_output >= min_out,
-                QantaraError::SlippageExceeded
-            );
+    // STEP 5.5: Buyback flow clarification
+    // - All community token purchases use SOL from vault_sol
+    // - If payment is USDC: The Jupiter swap route must be USDC → SOL → buyback_token
+    //   (USDC from vault_usdc → SOL to vault_sol → buyback_token to vault_buyback_token)
+    // - If payment is SOL: The Jupiter swap route must be SOL → buyback_token
+    //   (SOL from vault_sol → buyback_token to vault_buyback_token)
+    // The off-chain transaction builder is responsible for constructing the correct Jupiter route
+
+    // STEP 6: Execute buyback swap using SOL from vault_sol → buyback_token
+    // If payment was USDC, the USDC→SOL swap should have been executed first
+    // The Jupiter swap route should be: SOL → buyback_token
+    let buyback_output = if buyback_amount > 0 && !buyback_accounts.is_empty() {
+        // SECURITY CHECK 8b: min_out must stay within the merchant's slippage bound at oracle price
+        verify_min_out_against_oracle(ctx, buyback_amount, min_out, pay_token)?;
+
+        // Snapshot the shared vaults so a bad route cannot drain other merchants' funds
+        let before = snapshot_vaults(ctx)?;
+        execute_buyback_swap(ctx, buyback_amount, min_out, pay_token, swap_data, buyback_accounts)?;
+        let after = snapshot_vaults(ctx)?;
+
+        // SECURITY CHECK 10: Vault conservation (each vault moved at most its allowance)
+        after.verify_conservation(&before, pay_token, buyback_amount)?;
+
+        // Only the tokens acquired by this swap count as buyback output
+        after
+            .buyback_amount
+            .checked_sub(before.buyback_amount)
+            .ok_or(QantaraError::VaultConservationViolated)?
+    } else {
+        0
+    };
+
+    // SECURITY CHECK 9: Verify slippage protection
+    if buyback_amount > 0 {
+        require!(
+            buyback_output >= min_out,
+            QantaraError::SlippageExceeded
+        );
+    }
+
+    // STEP 7: Burn portion of acquired tokens
+    let burn_amount = if buyback_output > 0 {
+        let burn = (buyback_output as u128)
+            .checked_mul(burn_of_buyback_bps as u128)
+            .and_then(|v| v.checked_div(10000))
+            .ok_or(QantaraError::InvalidBasisPoints)? as u64;
+        
+        if burn > 0 {
+            token::burn(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Burn {
+                        mint: ctx.accounts.buyback_mint.to_account_info(),
+                        from: ctx.accounts.vault_buyback_token.to_account_info(),
+                        authority: ctx.accounts.merchant_registry.to_account_info(),
+                    },
+                    &[&[
+                        b"merchant",
+                        &merchant_id.to_le_bytes(),
+                        &[merchant.bump],
+                    ]],
+                ),
+                burn,
+            )?;
         }
+        burn
+    } else {
+        0
+    };
 
-        // STEP 7: Burn portion of acquired tokens
-        let burn_amount = if buyback_output > 0 {
-            let burn = (buyback_output as u128)
-                .checked_mul(burn_of_buyback_bps as u128)
-                .and_then(|v| v.checked_div(10000))
-                .ok_or(QantaraError::InvalidBasisPoints)? as u64;
-            
-            if burn > 0 {
-                token::burn(
-                    CpiContext::new_with_signer(
-                        ctx.accounts.token_program.to_account_info(),
-                        token::Burn {
-                            mint: ctx.accounts.buyback_mint.to_account_info(),
-                            from: ctx.accounts.vault_buyback_token.to_account_info(),
-                            authority: ctx.accounts.merchant_registry.to_account_info(),
-                        },
-                        &[&[
-                            b"merchant",
-                            &merchant_id.to_le_bytes(),
-                            &[merchant.bump],
-                        ]],
-                    ),
-                    burn,
-                )?;
-            }
-            burn
-        } else {
-            0
-        };
+    // STEP 8: Convert payout into the merchant's settlement currency, then transfer
+    let (payout_amount, payout_token) = match (settlement_token, payout_swap) {
+        (Some(settlement_token), Some(payout_swap)) if merchant_payout > 0 => {
+            let converted = convert_merchant_payout(
+                ctx,
+                merchant_payout,
+                pay_token,
+                settlement_token,
+                payout_swap,
+                payout_accounts,
+            )?;
+            (converted, settlement_token)
+        }
+        _ => (merchant_payout, pay_token),
+    };
+    transfer_merchant_payout(ctx, payout_amount, payout_token)?;
 
-        // STEP 8: Transfer merchant payout
-        transfer_merchant_payout(&ctx, merchant_payout, pay_token)?;
+    // Running totals for dashboards and volume-based fee tiers
+    // (volume excludes tips; fees include any fee taken from the tip)
+    let total_fee = protocol_fee.saturating_add(tip_fee);
+    ctx.accounts.merchant_registry.record_settlement(
+        pay_token,
+        amount,
+        total_fee,
+        buyback_output,
+        burn_amount,
+    );
+    ctx.accounts
+        .protocol_stats
+        .record_settlement(pay_token, amount, total_fee);
+    if let Some(day_stats) = ctx.accounts.merchant_day_stats.as_mut() {
+        day_stats.record_settlement(pay_token, amount, total_fee);
+    }
 
-        // STEP 9: Emit event
-        emit!(PaymentSettled {
+    // STEP 9: Write the receipt (fails if this order was already receipted)
+    if let (Some(receipt), Some(bump), Some(order_reference)) =
+        (&ctx.accounts.receipt, receipt_bump, order_reference)
+    {
+        let rent_payer = rent_payer(ctx.accounts);
+        create_pda_account(
+            &receipt.to_account_info(),
+            &rent_payer,
+            &ctx.accounts.system_program.to_account_info(),
+            8 + Receipt::INIT_SPACE,
+            &[b"receipt", &merchant_id.to_le_bytes(), &order_reference, &[bump]],
+        )?;
+        let state = Receipt {
+            version: RECEIPT_VERSION,
             merchant_id,
+            order_reference,
             payer: ctx.accounts.payer.key(),
+            rent_payer: rent_payer.key(),
             amount,
             pay_token,
             protocol_fee,
-            payout_amount: merchant_payout,
+            payout_amount,
+            payout_token,
             buyback_amount: buyback_output,
             burn_amount,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+            timestamp,
+            bump,
+            reserved: [0; RECEIPT_RESERVED],
+        };
+        let mut data = receipt.try_borrow_mut_data()?;
+        state.try_serialize(&mut &mut data[..])?;
+    }
 
-        Ok(())
+    // STEP 10: Emit event
+    emit!(PaymentSettled {
+        merchant_id,
+        payer: ctx.accounts.payer.key(),
+        amount,
+        pay_token,
+        protocol_fee,
+        payout_amount,
+        payout_token,
+        buyback_amount: buyback_output,
+        burn_amount,
+        usd_conversion,
+        order_reference,
+        memo,
+        beneficiary,
+        tip_amount: tip,
+        tip_fee,
+        timestamp,
+    });
+
+    Ok(())
+}
+
+/// Account funding rent created during settlement: the sponsor if one signed, else the payer
+fn rent_payer<'info>(accounts: &Settle<'info>) -> AccountInfo<'info> {
+    match &accounts.sponsor {
+        Some(sponsor) => sponsor.to_account_info(),
+        None => accounts.payer.to_account_info(),
     }
 }
 
-// Helper functions
+/// Convert a USD amount into lamports using the configured SOL/USD feed
+fn convert_usd_to_lamports(
+    ctx: &Context<Settle>,
+    usd_amount: u64,
+) -> Result<(u64, UsdConversion)> {
+    let config = &ctx.accounts.protocol_config;
+    require!(
+        config.oracle_program != Pubkey::default(),
+        QantaraError::OracleNotConfigured
+    );
+
+    let feed = ctx
+        .accounts
+        .pay_price_feed
+        .as_ref()
+        .ok_or(QantaraError::OracleNotConfigured)?;
+    require_keys_eq!(feed.key(), config.sol_price_feed, QantaraError::InvalidPriceFeed);
+
+    let sol_price = load_price(
+        feed,
+        &config.oracle_program,
+        Clock::get()?.unix_timestamp,
+        config.max_price_age_secs,
+    )?;
+    usd_to_lamports(usd_amount, &sol_price)
+}
+
 fn transfer_protocol_fee(
     ctx: &Context<Settle>,
     fee: u64,
@@ -324,29 +2321,245 @@ fn transfer_protocol_fee(
 ) -> Result<()> {
     match pay_token {
         PayToken::Sol => {
-            // Transfer from vault to protocol wallet
-            **ctx.accounts.vault_sol.try_borrow_mut_lamports()? -= fee;
-            **ctx.accounts.protocol_wallet.try_borrow_mut_lamports()? += fee;
+            transfer_from_vault_sol(ctx, ctx.accounts.protocol_wallet.to_account_info(), fee)?;
+        }
+        PayToken::Usdc => {
+            transfer_from_vault_usdc(ctx, ctx.accounts.protocol_wallet_usdc.to_account_info(), fee)?;
+        }
+    }
+    Ok(())
+}
+
+/// Swap the payer's token into vault_usdc, keep `amount` and refund the excess USDC
+fn receive_swapped_payment(
+    ctx: &Context<Settle>,
+    amount: u64,
+    input_swap: InputSwap,
+    route_accounts: &[AccountInfo],
+) -> Result<()> {
+    let payer = ctx.accounts.payer.key();
+    let payer_input = ctx
+        .accounts
+        .payer_input_token
+        .as_ref()
+        .ok_or(QantaraError::InvalidInputSwap)?;
+    require!(
+        !route_accounts.is_empty() && input_swap.max_amount_in > 0,
+        QantaraError::InvalidInputSwap
+    );
+    require_keys_eq!(payer_input.owner, payer, QantaraError::InvalidInputSwap);
+    require_keys_eq!(
+        ctx.accounts.payer_usdc_account.owner,
+        payer,
+        QantaraError::InvalidInputSwap
+    );
+    require!(
+        ctx.accounts.swap_router.allows(&input_swap.swap_data),
+        QantaraError::SwapInstructionNotAllowed
+    );
+
+    let input_before = read_token_amount(&payer_input.to_account_info())?;
+    let before = snapshot_vaults(ctx)?;
+    // Payer authorizes the swap with their own signature; the merchant PDA does not sign
+    invoke_router(ctx, route_accounts, input_swap.swap_data, false)?;
+    let after = snapshot_vaults(ctx)?;
+    let input_after = read_token_amount(&payer_input.to_account_info())?;
+
+    // SECURITY CHECK: No vault may be spent by a payer-funded swap
+    after.verify_conservation(&before, PayToken::Usdc, 0)?;
+
+    let amount_in = input_before
+        .checked_sub(input_after)
+        .ok_or(QantaraError::InvalidInputSwap)?;
+    require!(
+        amount_in <= input_swap.max_amount_in,
+        QantaraError::InputSwapExceedsMax
+    );
+
+    let usdc_received = after.received(&before, PayToken::Usdc)?;
+    let refund = usdc_received
+        .checked_sub(amount)
+        .ok_or(QantaraError::InputSwapShortfall)?;
+
+    if refund > 0 {
+        transfer_from_vault_usdc(ctx, ctx.accounts.payer_usdc_account.to_account_info(), refund)?;
+    }
+
+    emit!(PaymentSwappedIn {
+        merchant_id: ctx.accounts.merchant_registry.merchant_id,
+        payer,
+        input_mint: payer_input.mint,
+        amount_in,
+        usdc_received,
+        refund,
+    });
+
+    Ok(())
+}
+
+/// Move an escrowed amount (captured authorization or funded group invoice) into the paying vault
+fn receive_escrowed_payment(
+    ctx: &Context<Settle>,
+    amount: u64,
+    pay_token: PayToken,
+) -> Result<()> {
+    let (holder, seed_prefix, escrow_id, bump): (AccountInfo, &[u8], [u8; 32], u8) =
+        match (&ctx.accounts.authorization, &ctx.accounts.group_invoice) {
+            (Some(authorization), _) => (
+                authorization.to_account_info(),
+                b"authorization",
+                authorization.authorization_id,
+                authorization.bump,
+            ),
+            (None, Some(group_invoice)) => (
+                group_invoice.to_account_info(),
+                b"group_invoice",
+                group_invoice.invoice_id,
+                group_invoice.bump,
+            ),
+            (None, None) => return err!(QantaraError::AuthorizationRequired),
+        };
+    match pay_token {
+        PayToken::Sol => {
+            // The escrow holder is program-owned, so its lamports move directly
+            let to = ctx.accounts.vault_sol.to_account_info();
+            **holder.try_borrow_mut_lamports()? = holder
+                .lamports()
+                .checked_sub(amount)
+                .ok_or(QantaraError::CaptureExceedsAuthorization)?;
+            **to.try_borrow_mut_lamports()? = to
+                .lamports()
+                .checked_add(amount)
+                .ok_or(QantaraError::CaptureExceedsAuthorization)?;
         }
         PayToken::Usdc => {
-            // Note: Vault USDC needs proper PDA authority setup
-            // For now, this is a placeholder - actual implementation needs vault PDA
+            let escrow = ctx
+                .accounts
+                .escrow_usdc
+                .as_ref()
+                .ok_or(QantaraError::AuthorizationEscrowRequired)?;
             token::transfer(
-                CpiContext::new(
+                CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
                     token::Transfer {
-                        from: ctx.accounts.vault_usdc.to_account_info(),
-                        to: ctx.accounts.protocol_wallet_usdc.to_account_info(),
-                        authority: ctx.accounts.vault_usdc.to_account_info(),
+                        from: escrow.to_account_info(),
+                        to: ctx.accounts.vault_usdc.to_account_info(),
+                        authority: holder,
                     },
+                    &[&[
+                        seed_prefix,
+                        &ctx.accounts.merchant_registry.merchant_id.to_le_bytes(),
+                        &escrow_id,
+                        &[bump],
+                    ]],
                 ),
-                fee,
+                amount,
             )?;
         }
     }
     Ok(())
 }
 
+/// Check the tip accounts against the merchant's tip policy; returns the policy
+fn verify_tip_destination<'a>(
+    ctx: &'a Context<Settle>,
+    pay_token: PayToken,
+) -> Result<&'a TipPolicy> {
+    let policy = ctx
+        .accounts
+        .tip_policy
+        .as_ref()
+        .ok_or(QantaraError::TipPolicyRequired)?;
+    match pay_token {
+        PayToken::Sol => {
+            let tip_wallet = ctx
+                .accounts
+                .tip_wallet
+                .as_ref()
+                .ok_or(QantaraError::InvalidTipWallet)?;
+            require_keys_eq!(tip_wallet.key(), policy.tip_wallet, QantaraError::InvalidTipWallet);
+        }
+        PayToken::Usdc => {
+            let tip_wallet_usdc = ctx
+                .accounts
+                .tip_wallet_usdc
+                .as_ref()
+                .ok_or(QantaraError::InvalidTipWallet)?;
+            require_keys_eq!(
+                tip_wallet_usdc.owner,
+                policy.tip_wallet,
+                QantaraError::InvalidTipWallet
+            );
+            require_keys_eq!(
+                tip_wallet_usdc.mint,
+                ctx.accounts.usdc_mint.key(),
+                QantaraError::InvalidTipWallet
+            );
+        }
+    }
+    Ok(policy)
+}
+
+/// Pay a tip from the payer to the (verified) tip wallet, taking the protocol fee out of it
+/// when `fee_on_tip`; returns the fee
+fn pay_tip(ctx: &Context<Settle>, tip: u64, pay_token: PayToken, fee_on_tip: bool) -> Result<u64> {
+    let fee = ctx.accounts.protocol_config.fee_on(tip, !fee_on_tip)?;
+    let net = tip.checked_sub(fee).ok_or(QantaraError::InvalidBasisPoints)?;
+
+    match pay_token {
+        PayToken::Sol => {
+            let tip_wallet = ctx
+                .accounts
+                .tip_wallet
+                .as_ref()
+                .ok_or(QantaraError::InvalidTipWallet)?;
+            for (to, lamports) in [
+                (tip_wallet.to_account_info(), net),
+                (ctx.accounts.protocol_wallet.to_account_info(), fee),
+            ] {
+                if lamports > 0 {
+                    system_program::transfer(
+                        CpiContext::new(
+                            ctx.accounts.system_program.to_account_info(),
+                            system_program::Transfer {
+                                from: ctx.accounts.payer.to_account_info(),
+                                to,
+                            },
+                        ),
+                        lamports,
+                    )?;
+                }
+            }
+        }
+        PayToken::Usdc => {
+            let tip_wallet_usdc = ctx
+                .accounts
+                .tip_wallet_usdc
+                .as_ref()
+                .ok_or(QantaraError::InvalidTipWallet)?;
+            for (to, amount) in [
+                (tip_wallet_usdc.to_account_info(), net),
+                (ctx.accounts.protocol_wallet_usdc.to_account_info(), fee),
+            ] {
+                if amount > 0 {
+                    token::transfer(
+                        CpiContext::new(
+                            ctx.accounts.token_program.to_account_info(),
+                            token::Transfer {
+                                from: ctx.accounts.payer_usdc_account.to_account_info(),
+                                to,
+                                authority: ctx.accounts.payer.to_account_info(),
+                            },
+                        ),
+                        amount,
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(fee)
+}
+
 fn receive_payment(
     ctx: &Context<Settle>,
     amount: u64,
@@ -384,11 +2597,146 @@ fn receive_payment(
     Ok(())
 }
 
+fn verify_min_out_against_oracle(
+    ctx: &Context<Settle>,
+    amount_in: u64,
+    min_out: u64,
+    pay_token: PayToken,
+) -> Result<()> {
+    let config = &ctx.accounts.protocol_config;
+    let merchant = &ctx.accounts.merchant_registry;
+
+    require!(
+        config.oracle_program != Pubkey::default()
+            && merchant.buyback_price_feed != Pubkey::default(),
+        QantaraError::OracleNotConfigured
+    );
+    let buyback_feed = ctx
+        .accounts
+        .buyback_price_feed
+        .as_ref()
+        .ok_or(QantaraError::OracleNotConfigured)?;
+    require_keys_eq!(
+        buyback_feed.key(),
+        merchant.buyback_price_feed,
+        QantaraError::InvalidPriceFeed
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let (price_in, decimals_in) =
+        load_vault_token_price(ctx, ctx.accounts.pay_price_feed.as_ref(), pay_token, now)?;
+    let price_out = load_price(buyback_feed, &config.oracle_program, now, config.max_price_age_secs)?;
+
+    let expected_out = quote_amount_out(
+        amount_in,
+        decimals_in,
+        &price_in,
+        ctx.accounts.buyback_mint.decimals,
+        &price_out,
+    )?;
+    require!(
+        min_out >= min_out_bound(expected_out, merchant.slippage_bps_max),
+        QantaraError::MinOutBelowOracleBound
+    );
+
+    Ok(())
+}
+
+/// Load the USD price and decimals of a vault token from its protocol-configured feed
+fn load_vault_token_price(
+    ctx: &Context<Settle>,
+    feed: Option<&UncheckedAccount>,
+    token: PayToken,
+    now: i64,
+) -> Result<(OraclePrice, u8)> {
+    let config = &ctx.accounts.protocol_config;
+    require!(
+        config.oracle_program != Pubkey::default(),
+        QantaraError::OracleNotConfigured
+    );
+    let feed = feed.ok_or(QantaraError::OracleNotConfigured)?;
+
+    let (expected_feed, decimals) = match token {
+        PayToken::Sol => (config.sol_price_feed, SOL_DECIMALS),
+        PayToken::Usdc => (config.usdc_price_feed, read_mint_decimals(&ctx.accounts.usdc_mint)?),
+    };
+    require_keys_eq!(feed.key(), expected_feed, QantaraError::InvalidPriceFeed);
+
+    let price = load_price(feed, &config.oracle_program, now, config.max_price_age_secs)?;
+    Ok((price, decimals))
+}
+
+/// Swap the merchant payout into its settlement currency and return the amount received
+fn convert_merchant_payout(
+    ctx: &Context<Settle>,
+    amount_in: u64,
+    pay_token: PayToken,
+    settlement_token: PayToken,
+    payout_swap: PayoutSwap,
+    route_accounts: &[AccountInfo],
+) -> Result<u64> {
+    // SECURITY CHECK 11: Converted USDC may only be paid to the registered payout wallet
+    if settlement_token == PayToken::Usdc {
+        let payout_usdc = &ctx.accounts.merchant_payout_usdc;
+        require_keys_eq!(
+            payout_usdc.owner,
+            ctx.accounts.merchant_registry.payout_wallet,
+            QantaraError::InvalidPayoutWallet
+        );
+        require_keys_eq!(
+            payout_usdc.mint,
+            ctx.accounts.usdc_mint.key(),
+            QantaraError::InvalidPayoutWallet
+        );
+    }
+
+    // SECURITY CHECK 12: Payout min_out within the merchant's slippage bound at oracle price
+    let now = Clock::get()?.unix_timestamp;
+    let (price_in, decimals_in) =
+        load_vault_token_price(ctx, ctx.accounts.pay_price_feed.as_ref(), pay_token, now)?;
+    let (price_out, decimals_out) = load_vault_token_price(
+        ctx,
+        ctx.accounts.settlement_price_feed.as_ref(),
+        settlement_token,
+        now,
+    )?;
+    let expected_out = quote_amount_out(amount_in, decimals_in, &price_in, decimals_out, &price_out)?;
+    require!(
+        payout_swap.min_out >= min_out_bound(expected_out, ctx.accounts.merchant_registry.slippage_bps_max),
+        QantaraError::MinOutBelowOracleBound
+    );
+
+    let before = snapshot_vaults(ctx)?;
+    invoke_router(ctx, route_accounts, payout_swap.swap_data, true)?;
+    let after = snapshot_vaults(ctx)?;
+
+    // SECURITY CHECK 13: Vault conservation (only the payout share may be spent)
+    after.verify_conservation(&before, pay_token, amount_in)?;
+
+    let converted = after.received(&before, settlement_token)?;
+    require!(
+        converted >= payout_swap.min_out,
+        QantaraError::SlippageExceeded
+    );
+
+    Ok(converted)
+}
+
+fn snapshot_vaults(ctx: &Context<Settle>) -> Result<VaultSnapshot> {
+    VaultSnapshot::capture(
+        &ctx.accounts.vault_sol,
+        &ctx.accounts.vault_usdc.to_account_info(),
+        &ctx.accounts.vault_buyback_token.to_account_info(),
+    )
+}
+
 fn execute_buyback_swap(
     ctx: &Context<Settle>,
     amount_in: u64,
     min_out: u64,
     pay_token: PayToken,
+    swap_data: Vec<u8>,
+    route_accounts: &[AccountInfo],
 ) -> Result<()> {
     // Jupiter swap via remaining accounts
     // 
@@ -401,35 +2749,49 @@ fn execute_buyback_swap(
     //
     // The swap instructions should be built off-chain and passed via remaining_accounts
     // All swaps use vault_sol as the source for buying community tokens
-    if !ctx.remaining_accounts.is_empty() {
-        // Execute all swap instructions in remaining_accounts
+    if !route_accounts.is_empty() {
         // The off-chain builder is responsible for constructing the correct swap route
-        let swap_ix = anchor_lang::solana_program::instruction::Instruction {
-            program_id: ctx.accounts.jupiter_router.key(),
-            accounts: ctx.remaining_accounts
-                .iter()
-                .map(|acc| AccountMeta {
-                    pubkey: acc.key(),
-                    is_signer: acc.is_signer,
-                    is_writable: acc.is_writable,
-                })
-                .collect(),
-            data: vec![], // Built off-chain
-        };
-
-        invoke_signed(
-            &swap_ix,
-            ctx.remaining_accounts,
-            &[&[
-                b"merchant",
-                &ctx.accounts.merchant_registry.merchant_id.to_le_bytes(),
-                &[ctx.accounts.merchant_registry.bump],
-            ]],
-        )?;
+        invoke_router(ctx, route_accounts, swap_data, true)?;
     }
     Ok(())
 }
 
+/// Invoke the router, with the merchant and vault PDAs as signers when swapping vault funds
+/// (vault_sol and vault_usdc must sign for the route to debit them)
+fn invoke_router(
+    ctx: &Context<Settle>,
+    route_accounts: &[AccountInfo],
+    data: Vec<u8>,
+    vault_funded: bool,
+) -> Result<()> {
+    let swap_ix = anchor_lang::solana_program::instruction::Instruction {
+        program_id: ctx.accounts.jupiter_router.key(),
+        accounts: route_accounts
+            .iter()
+            .map(|acc| AccountMeta {
+                pubkey: acc.key(),
+                is_signer: acc.is_signer,
+                is_writable: acc.is_writable,
+            })
+            .collect(),
+        data, // Built off-chain, entrypoint checked against the router registry
+    };
+
+    let merchant_id = ctx.accounts.merchant_registry.merchant_id.to_le_bytes();
+    let merchant_seeds: &[&[u8]] = &[b"merchant", &merchant_id, &[ctx.accounts.merchant_registry.bump]];
+    let vault_sol_seeds: &[&[u8]] = &[b"vault", b"sol", &[ctx.bumps.vault_sol]];
+    let usdc_mint = ctx.accounts.usdc_mint.key();
+    let vault_usdc_seeds: &[&[u8]] = &[b"vault_usdc", usdc_mint.as_ref(), &[ctx.bumps.vault_usdc]];
+    let signer_seeds: &[&[&[u8]]] = if vault_funded {
+        &[merchant_seeds, vault_sol_seeds, vault_usdc_seeds]
+    } else {
+        &[]
+    };
+
+    invoke_signed(&swap_ix, route_accounts, signer_seeds)?;
+    Ok(())
+}
+
 fn transfer_merchant_payout(
     ctx: &Context<Settle>,
     amount: u64,
@@ -437,24 +2799,57 @@ fn transfer_merchant_payout(
 ) -> Result<()> {
     match pay_token {
         PayToken::Sol => {
-            **ctx.accounts.vault_sol.try_borrow_mut_lamports()? -= amount;
-            **ctx.accounts.merchant_payout_wallet.try_borrow_mut_lamports()? += amount;
+            transfer_from_vault_sol(ctx, ctx.accounts.merchant_payout_wallet.to_account_info(), amount)?;
         }
         PayToken::Usdc => {
-            // Note: Vault USDC needs proper PDA authority setup
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.vault_usdc.to_account_info(),
-                        to: ctx.accounts.merchant_payout_usdc.to_account_info(),
-                        authority: ctx.accounts.vault_usdc.to_account_info(),
-                    },
-                ),
-                amount,
-            )?;
+            transfer_from_vault_usdc(ctx, ctx.accounts.merchant_payout_usdc.to_account_info(), amount)?;
         }
     }
     Ok(())
 }
 
+/// Transfer SOL out of vault_sol, a system-owned PDA the program signs for
+fn transfer_from_vault_sol<'info>(
+    ctx: &Context<Settle<'info>>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    system_program::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.vault_sol.to_account_info(),
+                to,
+            },
+            &[&[b"vault", b"sol", &[ctx.bumps.vault_sol]]],
+        ),
+        amount,
+    )
+}
+
+/// Transfer USDC out of vault_usdc, signed by the vault PDA (its own token authority)
+fn transfer_from_vault_usdc<'info>(
+    ctx: &Context<Settle<'info>>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let usdc_mint = ctx.accounts.usdc_mint.key();
+    let vault_seeds: &[&[u8]] = &[b"vault_usdc", usdc_mint.as_ref(), &[ctx.bumps.vault_usdc]];
+    let vault_usdc = ctx.accounts.vault_usdc.to_account_info();
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: vault_usdc.clone(),
+                to,
+                authority: vault_usdc,
+            },
+            &[vault_seeds],
+        ),
+        amount,
+    )
+}
+
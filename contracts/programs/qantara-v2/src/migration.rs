@@ -0,0 +1,241 @@
+// Account migration for V2
+// Upgrades accounts created before versioning (version 0) to the current layouts
+
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+
+use crate::errors::QantaraError;
+use crate::oracle::DEFAULT_SLIPPAGE_BPS;
+use crate::state::*;
+
+/// ProtocolConfig as created before versioning
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyProtocolConfig {
+    pub authority: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub protocol_wallet: Pubkey,
+    pub jupiter_router: Pubkey,
+    pub paused: bool,
+    pub bump: u8,
+}
+
+impl LegacyProtocolConfig {
+    /// Account size including the discriminator
+    pub const LEN: usize = 8 + 32 + 2 + 32 + 32 + 1 + 1;
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        decode_legacy(data, ProtocolConfig::DISCRIMINATOR, Self::LEN)
+    }
+
+    /// Current layout with new fields at their defaults (oracle unconfigured)
+    pub fn upgrade(self) -> ProtocolConfig {
+        ProtocolConfig {
+            version: PROTOCOL_CONFIG_VERSION,
+            authority: self.authority,
+            protocol_fee_bps: self.protocol_fee_bps,
+            protocol_wallet: self.protocol_wallet,
+            jupiter_router: self.jupiter_router,
+            paused: self.paused,
+            oracle_program: Pubkey::default(),
+            sol_price_feed: Pubkey::default(),
+            usdc_price_feed: Pubkey::default(),
+            max_price_age_secs: 0,
+            bump: self.bump,
+            denied_payers: 0,
+            usdc_mint: Pubkey::default(),
+            reserved: [0; PROTOCOL_CONFIG_RESERVED],
+        }
+    }
+}
+
+/// MerchantRegistry as created before versioning
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LegacyMerchantRegistry {
+    pub merchant_id: u64,
+    pub owner: Pubkey,
+    pub payout_wallet: Pubkey,
+    pub buyback_mint: Pubkey,
+    pub frozen: bool,
+    pub bump: u8,
+}
+
+impl LegacyMerchantRegistry {
+    /// Account size including the discriminator
+    pub const LEN: usize = 8 + 8 + 32 + 32 + 32 + 1 + 1;
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        decode_legacy(data, MerchantRegistry::DISCRIMINATOR, Self::LEN)
+    }
+
+    /// Current layout with new fields at the values register_merchant assigns
+    pub fn upgrade(self) -> MerchantRegistry {
+        MerchantRegistry {
+            version: MERCHANT_REGISTRY_VERSION,
+            merchant_id: self.merchant_id,
+            owner: self.owner,
+            payout_wallet: self.payout_wallet,
+            buyback_mint: self.buyback_mint,
+            frozen: self.frozen,
+            buyback_price_feed: Pubkey::default(),
+            slippage_bps_max: DEFAULT_SLIPPAGE_BPS,
+            settlement_mint: Pubkey::default(),
+            bump: self.bump,
+            payment_count: 0,
+            volume_sol: 0,
+            volume_usdc: 0,
+            fees_sol: 0,
+            fees_usdc: 0,
+            total_bought_back: 0,
+            total_burned: 0,
+            limits_enabled: false,
+            denied_payers: 0,
+            allowlist_enabled: false,
+            quote_signer: Pubkey::default(),
+            open_authorizations: 0,
+            open_group_invoices: 0,
+            verified_nonprofit: false,
+            open_installment_plans: 0,
+            claimed_handles: 0,
+            payout_bps: 10000,
+            buyback_bps: 0,
+            burn_of_buyback_bps: 0,
+            reserved: [0; MERCHANT_REGISTRY_RESERVED],
+        }
+    }
+}
+
+/// Decode a pre-versioning account, which is identified by its exact size
+fn decode_legacy<T: AnchorDeserialize>(data: &[u8], discriminator: &[u8], len: usize) -> Result<T> {
+    require!(
+        data.len() == len && data[..8] == discriminator[..],
+        QantaraError::UnsupportedAccountVersion
+    );
+    T::deserialize(&mut &data[8..]).map_err(|_| QantaraError::UnsupportedAccountVersion.into())
+}
+
+/// Grow `account` to fit `state` (payer tops up rent) and write it in place
+pub fn write_upgraded<'info, T: AccountSerialize + Space>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    state: &T,
+) -> Result<()> {
+    let new_len = 8 + T::INIT_SPACE;
+    let rent = Rent::get()?.minimum_balance(new_len);
+    let top_up = rent.saturating_sub(account.lamports());
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+
+    account.resize(new_len)?;
+    let mut data = account.try_borrow_mut_data()?;
+    state.try_serialize(&mut &mut data[..])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw bytes of an account as the pre-versioning program wrote it
+    fn legacy_fixture<T: AnchorSerialize>(discriminator: &[u8], body: &T) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        body.serialize(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn upgrades_legacy_protocol_config() {
+        let legacy = LegacyProtocolConfig {
+            authority: Pubkey::new_unique(),
+            protocol_fee_bps: 100,
+            protocol_wallet: Pubkey::new_unique(),
+            jupiter_router: Pubkey::new_unique(),
+            paused: true,
+            bump: 254,
+        };
+        let data = legacy_fixture(ProtocolConfig::DISCRIMINATOR, &legacy);
+        assert_eq!(data.len(), LegacyProtocolConfig::LEN);
+
+        let config = LegacyProtocolConfig::decode(&data).unwrap().upgrade();
+        assert_eq!(config.version, PROTOCOL_CONFIG_VERSION);
+        assert_eq!(config.authority, legacy.authority);
+        assert_eq!(config.protocol_fee_bps, 100);
+        assert_eq!(config.protocol_wallet, legacy.protocol_wallet);
+        assert_eq!(config.jupiter_router, legacy.jupiter_router);
+        assert!(config.paused);
+        assert_eq!(config.oracle_program, Pubkey::default());
+        assert_eq!(config.bump, 254);
+
+        // Upgraded state round-trips through the current layout at full size
+        let mut upgraded = vec![0u8; 8 + ProtocolConfig::INIT_SPACE];
+        config.try_serialize(&mut &mut upgraded[..]).unwrap();
+        let decoded = ProtocolConfig::try_deserialize(&mut &upgraded[..]).unwrap();
+        assert_eq!(decoded.authority, legacy.authority);
+        assert_eq!(decoded.reserved, [0; PROTOCOL_CONFIG_RESERVED]);
+    }
+
+    #[test]
+    fn upgrades_legacy_merchant_registry() {
+        let legacy = LegacyMerchantRegistry {
+            merchant_id: 0x1234_5678_9abc_def0,
+            owner: Pubkey::new_unique(),
+            payout_wallet: Pubkey::new_unique(),
+            buyback_mint: Pubkey::new_unique(),
+            frozen: false,
+            bump: 253,
+        };
+        let data = legacy_fixture(MerchantRegistry::DISCRIMINATOR, &legacy);
+        assert_eq!(data.len(), LegacyMerchantRegistry::LEN);
+
+        let merchant = LegacyMerchantRegistry::decode(&data).unwrap().upgrade();
+        assert_eq!(merchant.version, MERCHANT_REGISTRY_VERSION);
+        assert_eq!(merchant.merchant_id, legacy.merchant_id);
+        assert_eq!(merchant.owner, legacy.owner);
+        assert_eq!(merchant.payout_wallet, legacy.payout_wallet);
+        assert_eq!(merchant.buyback_mint, legacy.buyback_mint);
+        assert_eq!(merchant.slippage_bps_max, DEFAULT_SLIPPAGE_BPS);
+        assert_eq!(merchant.settlement_mint, Pubkey::default());
+        assert_eq!(merchant.bump, 253);
+        assert_eq!(
+            (merchant.payout_bps, merchant.buyback_bps, merchant.burn_of_buyback_bps),
+            (10000, 0, 0)
+        );
+
+        let mut upgraded = vec![0u8; 8 + MerchantRegistry::INIT_SPACE];
+        merchant.try_serialize(&mut &mut upgraded[..]).unwrap();
+        let decoded = MerchantRegistry::try_deserialize(&mut &upgraded[..]).unwrap();
+        assert_eq!(decoded.merchant_id, legacy.merchant_id);
+    }
+
+    #[test]
+    fn rejects_current_layout_and_foreign_discriminator() {
+        let legacy = LegacyMerchantRegistry {
+            merchant_id: 1,
+            owner: Pubkey::new_unique(),
+            payout_wallet: Pubkey::new_unique(),
+            buyback_mint: Pubkey::new_unique(),
+            frozen: false,
+            bump: 255,
+        };
+
+        // Already migrated: size no longer matches the legacy layout
+        let mut current = vec![0u8; 8 + MerchantRegistry::INIT_SPACE];
+        legacy.clone().upgrade().try_serialize(&mut &mut current[..]).unwrap();
+        assert!(LegacyMerchantRegistry::decode(&current).is_err());
+
+        // Legacy size but another account type's discriminator
+        let foreign = legacy_fixture(SwapRouter::DISCRIMINATOR, &legacy);
+        assert!(LegacyMerchantRegistry::decode(&foreign).is_err());
+    }
+}
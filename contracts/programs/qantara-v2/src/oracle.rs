@@ -0,0 +1,318 @@
+// Price oracle support for V2
+// Reads Pyth (v2 price account layout) or any program writing the same layout (local mock)
+
+use anchor_lang::prelude::*;
+
+use crate::errors::QantaraError;
+use crate::state::UsdConversion;
+
+/// Pyth price account layout (offsets into the raw account data)
+pub const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+pub const PYTH_ACCOUNT_TYPE_PRICE: u32 = 3;
+pub const PYTH_STATUS_TRADING: u32 = 1;
+const MAGIC_OFFSET: usize = 0;
+const ATYPE_OFFSET: usize = 8;
+const EXPO_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+pub const PYTH_PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+/// Maximum merchant slippage tolerance (1000 bps = 10%)
+pub const MAX_SLIPPAGE_BPS: u16 = 1000;
+
+/// Slippage tolerance assigned to newly registered merchants (1%)
+pub const DEFAULT_SLIPPAGE_BPS: u16 = 100;
+
+/// Native SOL decimals (lamports)
+pub const SOL_DECIMALS: u8 = 9;
+
+/// Decimals of USD amounts passed to settle_usd (micro-USD)
+pub const USD_DECIMALS: u8 = 6;
+
+/// Widest accepted confidence interval relative to the price (200 bps = 2%)
+pub const MAX_PRICE_CONFIDENCE_BPS: u64 = 200;
+
+/// Aggregate price read from an oracle account
+#[derive(Clone, Copy, Debug)]
+pub struct OraclePrice {
+    pub price: i64,        // Price in units of 10^expo USD
+    pub conf: u64,         // Confidence interval (same units as price)
+    pub expo: i32,         // Price exponent
+    pub publish_time: i64, // Unix timestamp of the aggregate
+}
+
+impl OraclePrice {
+    /// Price of one US dollar in USD, for converting USD amounts
+    pub const USD: OraclePrice = OraclePrice {
+        price: 1,
+        conf: 0,
+        expo: 0,
+        publish_time: 0,
+    };
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Parse a Pyth-layout price account owned by `oracle_program`
+/// Rejects foreign owners, malformed layouts, non-trading status, stale prices
+/// and prices whose confidence interval is too wide to treat as exact
+pub fn load_price(
+    feed: &AccountInfo,
+    oracle_program: &Pubkey,
+    now: i64,
+    max_age_secs: u32,
+) -> Result<OraclePrice> {
+    require_keys_eq!(*feed.owner, *oracle_program, QantaraError::InvalidPriceFeed);
+
+    let data = feed.try_borrow_data()?;
+    require!(
+        data.len() >= PYTH_PRICE_ACCOUNT_MIN_LEN
+            && read_u32(&data, MAGIC_OFFSET) == PYTH_MAGIC
+            && read_u32(&data, ATYPE_OFFSET) == PYTH_ACCOUNT_TYPE_PRICE,
+        QantaraError::InvalidPriceFeed
+    );
+
+    let price = OraclePrice {
+        price: read_i64(&data, AGG_PRICE_OFFSET),
+        conf: read_u64(&data, AGG_CONF_OFFSET),
+        expo: read_i32(&data, EXPO_OFFSET),
+        publish_time: read_i64(&data, TIMESTAMP_OFFSET),
+    };
+
+    require!(
+        read_u32(&data, AGG_STATUS_OFFSET) == PYTH_STATUS_TRADING && price.price > 0,
+        QantaraError::InvalidPriceFeed
+    );
+    require!(
+        now.saturating_sub(price.publish_time) <= max_age_secs as i64,
+        QantaraError::OraclePriceStale
+    );
+    require!(
+        (price.conf as u128) * 10000 <= (price.price as u128) * MAX_PRICE_CONFIDENCE_BPS as u128,
+        QantaraError::OraclePriceUncertain
+    );
+
+    Ok(price)
+}
+
+/// Convert `amount_in` base units of the input token into the fair amount of
+/// output token base units, using USD prices of both tokens
+pub fn quote_amount_out(
+    amount_in: u64,
+    decimals_in: u8,
+    price_in: &OraclePrice,
+    decimals_out: u8,
+    price_out: &OraclePrice,
+) -> Result<u64> {
+    // out = in * p_in * 10^(expo_in + dec_out) / (p_out * 10^(expo_out + dec_in))
+    // Exponents come straight from account data, so a garbage feed must not overflow
+    let scale = price_in
+        .expo
+        .checked_add(decimals_out as i32)
+        .and_then(|v| v.checked_sub(price_out.expo))
+        .and_then(|v| v.checked_sub(decimals_in as i32))
+        .ok_or(QantaraError::InvalidPriceFeed)?;
+    let pow = 10u128
+        .checked_pow(scale.unsigned_abs())
+        .ok_or(QantaraError::InvalidPriceFeed)?;
+
+    let mut numerator = (amount_in as u128)
+        .checked_mul(price_in.price as u128)
+        .ok_or(QantaraError::InvalidPriceFeed)?;
+    let mut denominator = price_out.price as u128;
+    if scale >= 0 {
+        numerator = numerator.checked_mul(pow).ok_or(QantaraError::InvalidPriceFeed)?;
+    } else {
+        denominator = denominator.checked_mul(pow).ok_or(QantaraError::InvalidPriceFeed)?;
+    }
+
+    u64::try_from(numerator / denominator).map_err(|_| QantaraError::InvalidPriceFeed.into())
+}
+
+/// Lowest acceptable swap output given the oracle quote and slippage tolerance
+pub fn min_out_bound(expected_out: u64, slippage_bps_max: u16) -> u64 {
+    ((expected_out as u128) * (10000 - slippage_bps_max.min(10000)) as u128 / 10000) as u64
+}
+
+/// Lamports owed for `usd_amount` micro-USD at the given SOL/USD price, with the
+/// rate applied (reported in `PaymentSettled`)
+pub fn usd_to_lamports(usd_amount: u64, sol_price: &OraclePrice) -> Result<(u64, UsdConversion)> {
+    let lamports =
+        quote_amount_out(usd_amount, USD_DECIMALS, &OraclePrice::USD, SOL_DECIMALS, sol_price)?;
+    Ok((
+        lamports,
+        UsdConversion {
+            usd_amount,
+            price: sol_price.price,
+            expo: sol_price.expo,
+            publish_time: sol_price.publish_time,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn price_account(price: i64, conf: u64, expo: i32, status: u32, publish_time: i64) -> Vec<u8> {
+        let mut data = vec![0u8; PYTH_PRICE_ACCOUNT_MIN_LEN];
+        data[MAGIC_OFFSET..MAGIC_OFFSET + 4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[ATYPE_OFFSET..ATYPE_OFFSET + 4].copy_from_slice(&PYTH_ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[EXPO_OFFSET..EXPO_OFFSET + 4].copy_from_slice(&expo.to_le_bytes());
+        data[TIMESTAMP_OFFSET..TIMESTAMP_OFFSET + 8].copy_from_slice(&publish_time.to_le_bytes());
+        data[AGG_PRICE_OFFSET..AGG_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+        data[AGG_CONF_OFFSET..AGG_CONF_OFFSET + 8].copy_from_slice(&conf.to_le_bytes());
+        data[AGG_STATUS_OFFSET..AGG_STATUS_OFFSET + 4].copy_from_slice(&status.to_le_bytes());
+        data
+    }
+
+    fn load(mut data: Vec<u8>, owner: Pubkey, oracle_program: Pubkey) -> Result<OraclePrice> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let feed = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        load_price(&feed, &oracle_program, NOW, 60)
+    }
+
+    fn expect_error(result: Result<impl std::fmt::Debug>, expected: QantaraError) {
+        assert_eq!(result.unwrap_err(), expected.into());
+    }
+
+    fn price(price: i64, expo: i32) -> OraclePrice {
+        OraclePrice { price, conf: 0, expo, publish_time: NOW }
+    }
+
+    #[test]
+    fn loads_a_fresh_trading_price() {
+        let oracle = Pubkey::new_unique();
+        let data = price_account(15_000_000_000, 1_000_000, -8, PYTH_STATUS_TRADING, NOW - 60);
+        let loaded = load(data, oracle, oracle).unwrap();
+        assert_eq!(loaded.price, 15_000_000_000);
+        assert_eq!(loaded.conf, 1_000_000);
+        assert_eq!(loaded.expo, -8);
+        assert_eq!(loaded.publish_time, NOW - 60);
+    }
+
+    #[test]
+    fn rejects_stale_malformed_and_untrusted_feeds() {
+        let oracle = Pubkey::new_unique();
+        let fresh = || price_account(15_000_000_000, 0, -8, PYTH_STATUS_TRADING, NOW);
+
+        let stale = price_account(15_000_000_000, 0, -8, PYTH_STATUS_TRADING, NOW - 61);
+        expect_error(load(stale, oracle, oracle), QantaraError::OraclePriceStale);
+
+        let mut bad_magic = fresh();
+        bad_magic[MAGIC_OFFSET] ^= 0xff;
+        expect_error(load(bad_magic, oracle, oracle), QantaraError::InvalidPriceFeed);
+
+        let halted = price_account(15_000_000_000, 0, -8, 0, NOW);
+        expect_error(load(halted, oracle, oracle), QantaraError::InvalidPriceFeed);
+
+        let negative = price_account(-1, 0, -8, PYTH_STATUS_TRADING, NOW);
+        expect_error(load(negative, oracle, oracle), QantaraError::InvalidPriceFeed);
+
+        let mut truncated = fresh();
+        truncated.truncate(PYTH_PRICE_ACCOUNT_MIN_LEN - 1);
+        expect_error(load(truncated, oracle, oracle), QantaraError::InvalidPriceFeed);
+
+        expect_error(load(fresh(), Pubkey::new_unique(), oracle), QantaraError::InvalidPriceFeed);
+    }
+
+    #[test]
+    fn rejects_prices_with_a_wide_confidence_interval() {
+        let oracle = Pubkey::new_unique();
+        // 2% of $150 is the widest band accepted
+        let at_bound = price_account(15_000_000_000, 300_000_000, -8, PYTH_STATUS_TRADING, NOW);
+        assert!(load(at_bound, oracle, oracle).is_ok());
+        let too_wide = price_account(15_000_000_000, 300_000_001, -8, PYTH_STATUS_TRADING, NOW);
+        expect_error(load(too_wide, oracle, oracle), QantaraError::OraclePriceUncertain);
+    }
+
+    #[test]
+    fn quotes_across_positive_and_negative_scales() {
+        // 1 SOL at $150 buys 150 USDC (scale = -8 + 6 - (-8) - 9 = -3)
+        let sol = price(15_000_000_000, -8);
+        let usdc = price(100_000_000, -8);
+        assert_eq!(quote_amount_out(1_000_000_000, 9, &sol, 6, &usdc).unwrap(), 150_000_000);
+
+        // 150 USDC buys 1 SOL (scale = -8 + 9 - (-8) - 6 = 3)
+        assert_eq!(quote_amount_out(150_000_000, 6, &usdc, 9, &sol).unwrap(), 1_000_000_000);
+
+        // Exponents on different scales cancel out
+        let sol_expo_5 = price(15_000_000, -5);
+        assert_eq!(quote_amount_out(1_000_000_000, 9, &sol_expo_5, 6, &usdc).unwrap(), 150_000_000);
+
+        // Rounds down
+        assert_eq!(quote_amount_out(1, 6, &usdc, 9, &price(3, 0)).unwrap(), 333);
+    }
+
+    #[test]
+    fn quote_rejects_overflowing_inputs() {
+        let usdc = price(100_000_000, -8);
+        // Exponent arithmetic overflows i32
+        let garbage = price(1, i32::MAX);
+        expect_error(quote_amount_out(1, 6, &garbage, 9, &usdc), QantaraError::InvalidPriceFeed);
+        let garbage = price(1, i32::MIN);
+        expect_error(quote_amount_out(1, 6, &usdc, 9, &garbage), QantaraError::InvalidPriceFeed);
+        // 10^scale does not fit in u128
+        expect_error(quote_amount_out(1, 6, &price(1, 40), 9, &usdc), QantaraError::InvalidPriceFeed);
+        // Output does not fit in u64
+        expect_error(
+            quote_amount_out(u64::MAX, 6, &price(i64::MAX, 0), 6, &price(1, 0)),
+            QantaraError::InvalidPriceFeed,
+        );
+    }
+
+    fn lamports_for(usd_amount: u64, sol_price: OraclePrice) -> u64 {
+        usd_to_lamports(usd_amount, &sol_price).unwrap().0
+    }
+
+    #[test]
+    fn converts_usd_to_lamports() {
+        // $150 at $150/SOL is one SOL
+        let sol = price(15_000_000_000, -8);
+        assert_eq!(lamports_for(150_000_000, sol), 1_000_000_000);
+        // $1 at $150/SOL, rounded down to the lamport
+        assert_eq!(lamports_for(1_000_000, sol), 6_666_666);
+        // One micro-USD
+        assert_eq!(lamports_for(1, sol), 6);
+        assert_eq!(lamports_for(0, sol), 0);
+        // Same price with a coarser exponent
+        assert_eq!(lamports_for(1_000_000, price(150, 0)), 6_666_666);
+        // Sub-cent SOL price on a positive scale
+        assert_eq!(lamports_for(1_000_000, price(5, -3)), 200_000_000_000);
+        // Amounts beyond u64 lamports are rejected rather than truncated
+        expect_error(
+            usd_to_lamports(u64::MAX, &price(1, -12)).map(|(lamports, _)| lamports),
+            QantaraError::InvalidPriceFeed,
+        );
+    }
+
+    #[test]
+    fn usd_conversion_reports_the_rate_applied() {
+        let sol = OraclePrice { price: 14_250_000_000, conf: 0, expo: -8, publish_time: NOW - 5 };
+        let (lamports, conversion) = usd_to_lamports(25_000_000, &sol).unwrap();
+        assert_eq!(lamports, 175_438_596);
+        assert_eq!(conversion.usd_amount, 25_000_000);
+        assert_eq!(conversion.price, 14_250_000_000);
+        assert_eq!(conversion.expo, -8);
+        assert_eq!(conversion.publish_time, NOW - 5);
+    }
+}
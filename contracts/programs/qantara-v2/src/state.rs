@@ -1,16 +1,74 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount, Mint};
+use crate::errors::QantaraError;
+
+/// Current layout versions (accounts created before versioning are version 0)
+pub const PROTOCOL_CONFIG_VERSION: u8 = 1;
+pub const MERCHANT_REGISTRY_VERSION: u8 = 1;
+pub const SWAP_ROUTER_VERSION: u8 = 1;
+pub const MERCHANT_HANDLE_VERSION: u8 = 1;
+pub const MERCHANT_METADATA_VERSION: u8 = 1;
+pub const RECEIPT_VERSION: u8 = 1;
+pub const PROTOCOL_STATS_VERSION: u8 = 1;
+pub const MERCHANT_DAY_STATS_VERSION: u8 = 1;
+pub const MERCHANT_LIMITS_VERSION: u8 = 1;
+pub const PAYER_LIST_ENTRY_VERSION: u8 = 1;
+pub const AUTHORIZATION_VERSION: u8 = 1;
+pub const INSTALLMENT_PLAN_VERSION: u8 = 1;
+pub const GROUP_INVOICE_VERSION: u8 = 1;
+pub const CONTRIBUTION_VERSION: u8 = 1;
+pub const DONATION_SETTINGS_VERSION: u8 = 1;
+pub const TIP_POLICY_VERSION: u8 = 1;
+
+/// Reserved bytes per account; new fields are carved out of these
+pub const PROTOCOL_CONFIG_RESERVED: usize = 28;
+pub const MERCHANT_REGISTRY_RESERVED: usize = 11;
+pub const SWAP_ROUTER_RESERVED: usize = 32;
+pub const MERCHANT_HANDLE_RESERVED: usize = 32;
+pub const RECEIPT_RESERVED: usize = 16;
+pub const PROTOCOL_STATS_RESERVED: usize = 63;
+pub const MERCHANT_DAY_STATS_RESERVED: usize = 16;
+pub const MERCHANT_LIMITS_RESERVED: usize = 32;
+pub const PAYER_LIST_ENTRY_RESERVED: usize = 16;
+pub const AUTHORIZATION_RESERVED: usize = 16;
+pub const INSTALLMENT_PLAN_RESERVED: usize = 32;
+pub const GROUP_INVOICE_RESERVED: usize = 32;
+pub const CONTRIBUTION_RESERVED: usize = 16;
+pub const DONATION_SETTINGS_RESERVED: usize = 32;
+pub const TIP_POLICY_RESERVED: usize = 32;
 
 /// Global protocol configuration
 #[account]
 #[derive(InitSpace)]
 pub struct ProtocolConfig {
+    pub version: u8,                    // Layout version
     pub authority: Pubkey,              // Protocol admin (multisig)
     pub protocol_fee_bps: u16,         // Protocol fee (e.g., 100 = 1%)
     pub protocol_wallet: Pubkey,       // Fee recipient
-    pub jupiter_router: Pubkey,         // Jupiter v6 program
+    pub jupiter_router: Pubkey,         // Default router for off-chain builders (Jupiter v6)
     pub paused: bool,                   // Emergency pause
+    pub oracle_program: Pubkey,         // Owner of price feeds (Pyth or local mock)
+    pub sol_price_feed: Pubkey,         // SOL/USD price feed
+    pub usdc_price_feed: Pubkey,        // USDC/USD price feed
+    pub max_price_age_secs: u32,        // Oracle staleness limit
     pub bump: u8,                       // PDA bump
+    pub denied_payers: u32,             // Protocol denylist entries (settle checks the list while > 0)
+    pub usdc_mint: Pubkey,              // The USDC mint settle, escrows and payout conversion accept
+    pub reserved: [u8; PROTOCOL_CONFIG_RESERVED], // Space for future fields
+}
+
+impl ProtocolConfig {
+    /// Protocol fee on `amount`; zero when `waived` (donations to verified nonprofits)
+    pub fn fee_on(&self, amount: u64, waived: bool) -> Result<u64> {
+        if waived {
+            return Ok(0);
+        }
+        let fee = (amount as u128)
+            .checked_mul(self.protocol_fee_bps as u128)
+            .and_then(|v| v.checked_div(10000))
+            .ok_or(QantaraError::InvalidBasisPoints)?;
+        Ok(fee as u64)
+    }
 }
 
 /// On-chain merchant registry (REQUIRED for security)
@@ -18,12 +76,676 @@ pub struct ProtocolConfig {
 #[account]
 #[derive(InitSpace)]
 pub struct MerchantRegistry {
-    pub merchant_id: u64,              // Hash-based merchant ID
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,              // Hash of (owner, nonce), see derive_merchant_id
     pub owner: Pubkey,                  // Merchant owner (can update config)
     pub payout_wallet: Pubkey,          // CRITICAL: Validated on-chain
     pub buyback_mint: Pubkey,           // CRITICAL: Validated on-chain
     pub frozen: bool,                    // Emergency freeze
+    pub buyback_price_feed: Pubkey,     // Buyback token/USD feed (bounds min_out)
+    pub slippage_bps_max: u16,          // Max swap slippage vs oracle price
+    pub settlement_mint: Pubkey,        // Payout currency (default = pay token, native mint = SOL)
+    pub bump: u8,                       // PDA bump
+    pub payment_count: u64,             // Settlements received
+    pub volume_sol: u64,                // Gross SOL received (lamports)
+    pub volume_usdc: u64,               // Gross USDC received (base units)
+    pub fees_sol: u64,                  // Protocol fees paid in SOL
+    pub fees_usdc: u64,                 // Protocol fees paid in USDC
+    pub total_bought_back: u64,         // Buyback tokens bought
+    pub total_burned: u64,              // Buyback tokens burned
+    pub limits_enabled: bool,           // settle requires the MerchantLimits account
+    pub denied_payers: u32,             // Merchant denylist entries
+    pub allowlist_enabled: bool,        // Only allowlisted payers may pay
+    pub quote_signer: Pubkey,           // Key that signs payment quotes (default = quotes not required)
+    pub open_authorizations: u32,       // Authorizations holding funds (merchant cannot close while > 0)
+    pub open_group_invoices: u32,       // Group invoices not yet closed (merchant cannot close while > 0)
+    pub verified_nonprofit: bool,       // Set by the protocol authority; donations pay no protocol fee
+    pub open_installment_plans: u32,    // Installment plans not yet closed (merchant cannot close while > 0)
+    pub claimed_handles: u32,           // Handles resolving here (merchant cannot close while > 0)
+    pub payout_bps: u16,                // Default split for off-chain builders
+    pub buyback_bps: u16,               // Default split for off-chain builders
+    pub burn_of_buyback_bps: u16,       // Default split for off-chain builders
+    pub reserved: [u8; MERCHANT_REGISTRY_RESERVED], // Space for future fields
+}
+
+impl MerchantRegistry {
+    /// Add a settlement to the running totals (saturating, so totals never block a payment)
+    pub fn record_settlement(
+        &mut self,
+        pay_token: PayToken,
+        amount: u64,
+        protocol_fee: u64,
+        bought_back: u64,
+        burned: u64,
+    ) {
+        self.payment_count = self.payment_count.saturating_add(1);
+        match pay_token {
+            PayToken::Sol => {
+                self.volume_sol = self.volume_sol.saturating_add(amount);
+                self.fees_sol = self.fees_sol.saturating_add(protocol_fee);
+            }
+            PayToken::Usdc => {
+                self.volume_usdc = self.volume_usdc.saturating_add(amount);
+                self.fees_usdc = self.fees_usdc.saturating_add(protocol_fee);
+            }
+        }
+        self.total_bought_back = self.total_bought_back.saturating_add(bought_back);
+        self.total_burned = self.total_burned.saturating_add(burned);
+    }
+}
+
+/// Number of ProtocolStats shards
+pub const PROTOCOL_STATS_SHARDS: u8 = 16;
+
+/// Protocol-wide settlement totals, split into shards (PDA seeds = ["stats", shard]) keyed by
+/// merchant_id % PROTOCOL_STATS_SHARDS, so concurrent settles for different merchants rarely
+/// write-lock the same account; readers sum the shards off-chain
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolStats {
+    pub version: u8,                    // Layout version
+    pub shard: u8,                      // Shard index (< PROTOCOL_STATS_SHARDS)
+    pub payment_count: u64,             // Settlements across all merchants
+    pub volume_sol: u64,                // Gross SOL settled (lamports)
+    pub volume_usdc: u64,               // Gross USDC settled (base units)
+    pub fees_sol: u64,                  // Protocol fees collected in SOL
+    pub fees_usdc: u64,                 // Protocol fees collected in USDC
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; PROTOCOL_STATS_RESERVED], // Space for future fields
+}
+
+impl ProtocolStats {
+    /// Shard that records a merchant's settlements
+    pub fn shard_of(merchant_id: u64) -> u8 {
+        (merchant_id % PROTOCOL_STATS_SHARDS as u64) as u8
+    }
+
+    /// Add a settlement to the running totals (saturating, so totals never block a payment)
+    pub fn record_settlement(&mut self, pay_token: PayToken, amount: u64, protocol_fee: u64) {
+        self.payment_count = self.payment_count.saturating_add(1);
+        match pay_token {
+            PayToken::Sol => {
+                self.volume_sol = self.volume_sol.saturating_add(amount);
+                self.fees_sol = self.fees_sol.saturating_add(protocol_fee);
+            }
+            PayToken::Usdc => {
+                self.volume_usdc = self.volume_usdc.saturating_add(amount);
+                self.fees_usdc = self.fees_usdc.saturating_add(protocol_fee);
+            }
+        }
+    }
+}
+
+/// Handle length bounds (a handle is a single PDA seed, so at most 32 bytes)
+pub const MIN_HANDLE_LEN: usize = 3;
+pub const MAX_HANDLE_LEN: usize = 32;
+
+/// Human-readable name for a merchant (e.g. "acme-coffee")
+/// PDA seeds = ["handle", handle], so each normalized handle exists at most once
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantHandle {
+    pub version: u8,                    // Layout version
+    #[max_len(MAX_HANDLE_LEN)]
+    pub handle: String,                 // Normalized handle (see validate_handle)
+    pub merchant_id: u64,               // Merchant the handle resolves to
+    pub owner: Pubkey,                  // Handle owner (owner of that merchant)
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; MERCHANT_HANDLE_RESERVED], // Space for future fields
+}
+
+/// Length of the rolling volume cap window (24h)
+pub const LIMIT_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Payment limits for one token (0 = no limit)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct TokenLimits {
+    pub min_amount: u64,                // Smallest payment accepted
+    pub max_amount: u64,                // Largest payment accepted
+    pub daily_cap: u64,                 // Rolling 24h volume cap
+}
+
+impl TokenLimits {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.max_amount == 0 || self.min_amount <= self.max_amount,
+            QantaraError::InvalidLimits
+        );
+        Ok(())
+    }
+
+    /// Strictest combination of merchant and protocol limits
+    fn tightest(&self, other: &TokenLimits) -> TokenLimits {
+        let lowest_nonzero = |a: u64, b: u64| match (a, b) {
+            (0, b) => b,
+            (a, 0) => a,
+            (a, b) => a.min(b),
+        };
+        TokenLimits {
+            min_amount: self.min_amount.max(other.min_amount),
+            max_amount: lowest_nonzero(self.max_amount, other.max_amount),
+            daily_cap: lowest_nonzero(self.daily_cap, other.daily_cap),
+        }
+    }
+}
+
+/// Volume in the current and previous 24h windows (sliding window estimate)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct RollingVolume {
+    pub current: u64,
+    pub previous: u64,
+}
+
+/// Risk limits for a merchant (PDA seeds = ["limits", merchant_id])
+/// The merchant sets its own limits; protocol limits are ceilings it cannot raise
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantLimits {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant
+    pub sol: TokenLimits,               // Merchant limits for SOL (lamports)
+    pub usdc: TokenLimits,              // Merchant limits for USDC (base units)
+    pub protocol_sol: TokenLimits,      // Protocol ceilings for SOL
+    pub protocol_usdc: TokenLimits,     // Protocol ceilings for USDC
+    pub window_start: i64,              // Start of the current 24h window
+    pub volume_sol: RollingVolume,      // SOL volume for the rolling cap
+    pub volume_usdc: RollingVolume,     // USDC volume for the rolling cap
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; MERCHANT_LIMITS_RESERVED], // Space for future fields
+}
+
+impl MerchantLimits {
+    /// Check a payment against the effective limits and add it to the rolling volume
+    pub fn enforce(&mut self, pay_token: PayToken, amount: u64, now: i64) -> Result<()> {
+        let limits = match pay_token {
+            PayToken::Sol => self.sol.tightest(&self.protocol_sol),
+            PayToken::Usdc => self.usdc.tightest(&self.protocol_usdc),
+        };
+        require!(amount >= limits.min_amount, QantaraError::PaymentBelowMinimum);
+        require!(
+            limits.max_amount == 0 || amount <= limits.max_amount,
+            QantaraError::PaymentAboveMaximum
+        );
+
+        self.roll_window(now);
+        let elapsed = now.saturating_sub(self.window_start);
+        let volume = match pay_token {
+            PayToken::Sol => &mut self.volume_sol,
+            PayToken::Usdc => &mut self.volume_usdc,
+        };
+
+        if limits.daily_cap > 0 {
+            // Previous window weighted by how much of it still falls within the last 24h
+            let carried = (volume.previous as u128)
+                .saturating_mul(LIMIT_WINDOW_SECS.saturating_sub(elapsed) as u128)
+                / LIMIT_WINDOW_SECS as u128;
+            let last_24h = carried
+                .saturating_add(volume.current as u128)
+                .saturating_add(amount as u128);
+            require!(
+                last_24h <= limits.daily_cap as u128,
+                QantaraError::DailyCapExceeded
+            );
+        }
+
+        volume.current = volume.current.saturating_add(amount);
+        Ok(())
+    }
+
+    /// Advance the window so that `now` falls inside the current one
+    fn roll_window(&mut self, now: i64) {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < LIMIT_WINDOW_SECS {
+            return;
+        }
+        let windows = elapsed / LIMIT_WINDOW_SECS;
+        for volume in [&mut self.volume_sol, &mut self.volume_usdc] {
+            volume.previous = if windows == 1 { volume.current } else { 0 };
+            volume.current = 0;
+        }
+        self.window_start = self
+            .window_start
+            .saturating_add(windows.saturating_mul(LIMIT_WINDOW_SECS));
+    }
+}
+
+/// Whether a payer list entry blocks or admits the payer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum PayerListKind {
+    Deny,
+    Allow,
+}
+
+/// Payer list entry, one PDA per (list, payer) so settle can look a payer up directly
+/// Protocol denylist: seeds = ["payer_list", payer]
+/// Merchant list: seeds = ["payer_list", merchant_id, payer]
+#[account]
+#[derive(InitSpace)]
+pub struct PayerListEntry {
+    pub version: u8,                    // Layout version
+    pub merchant_id: Option<u64>,       // Merchant list, or None for the protocol denylist
+    pub payer: Pubkey,                  // Listed wallet
+    pub kind: PayerListKind,            // Deny or Allow (protocol entries are always Deny)
+    pub created_at: i64,                // Unix timestamp the entry was added
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; PAYER_LIST_ENTRY_RESERVED], // Space for future fields
+}
+
+/// Longest hold an authorization may place on a payer's funds (30 days)
+pub const MAX_AUTHORIZATION_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Funds held for a merchant until captured or voided
+/// PDA seeds = ["authorization", merchant_id, authorization_id]
+/// SOL is held in this account's lamports; USDC in an escrow token account it owns
+/// (seeds = ["escrow", authorization])
+#[account]
+#[derive(InitSpace)]
+pub struct Authorization {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant the funds are held for
+    pub authorization_id: [u8; 32],     // Client-chosen id, used as the capture's order reference
+    pub payer: Pubkey,                  // Payer (receives the remainder and rent on void)
+    pub pay_token: PayToken,            // Token held
+    pub amount: u64,                    // Authorized total held in escrow
+    pub captured_amount: u64,           // Amount captured (0 until captured)
+    pub captured: bool,                 // Captured (at most once)
+    pub expires_at: i64,                // Capture deadline; afterwards anyone may void
+    pub created_at: i64,                // Unix timestamp of the authorization
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; AUTHORIZATION_RESERVED], // Space for future fields
+}
+
+impl Authorization {
+    /// A capture happens at most once, before expiry, for up to the authorized amount
+    pub fn check_capture(&self, amount: u64, now: i64) -> Result<()> {
+        require!(!self.captured, QantaraError::AuthorizationAlreadyCaptured);
+        require!(now < self.expires_at, QantaraError::AuthorizationExpired);
+        require!(
+            amount > 0 && amount <= self.amount,
+            QantaraError::CaptureExceedsAuthorization
+        );
+        Ok(())
+    }
+
+    /// Mark `amount` captured; returns the remainder left in escrow
+    pub fn record_capture(&mut self, amount: u64) -> u64 {
+        self.captured = true;
+        self.captured_amount = amount;
+        self.remainder()
+    }
+
+    /// Held funds not captured, returned to the payer on void
+    pub fn remainder(&self) -> u64 {
+        self.amount.saturating_sub(self.captured_amount)
+    }
+
+    /// Anyone may void once the authorization is captured or expired; before that only the
+    /// merchant owner can
+    pub fn voidable_by_anyone(&self, now: i64) -> bool {
+        self.captured || now >= self.expires_at
+    }
+}
+
+/// Most installments a plan may be split into
+pub const MAX_INSTALLMENTS: u16 = 60;
+
+/// Amount and schedule of an installment plan, set by the merchant
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct InstallmentTerms {
+    pub pay_token: PayToken,            // Token every installment is paid in
+    pub installment_amount: u64,        // Amount per installment (base units)
+    pub installment_count: u16,         // Number of installments (2..=MAX_INSTALLMENTS)
+    pub first_due_at: i64,              // Due date of the first installment
+    pub interval_secs: i64,             // Time between due dates
+    pub grace_period_secs: i64,         // Time after a due date before the plan can default
+}
+
+impl InstallmentTerms {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.installment_amount > 0
+                && (2..=MAX_INSTALLMENTS).contains(&self.installment_count)
+                && self.first_due_at > 0
+                && self.interval_secs > 0
+                && self.grace_period_secs >= 0,
+            QantaraError::InvalidInstallmentTerms
+        );
+        Ok(())
+    }
+}
+
+/// Where an installment plan stands
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum InstallmentStatus {
+    Active,                             // Paid up, or late but within the grace period
+    Completed,                          // Every installment paid
+    Defaulted,                          // An installment went unpaid past its grace period
+}
+
+/// A purchase paid in N installments, each settled through the normal split
+/// PDA seeds = ["installments", merchant_id, plan_id]
+#[account]
+#[derive(InitSpace)]
+pub struct InstallmentPlan {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant being paid
+    pub plan_id: [u8; 32],              // Merchant-chosen id (per-installment order references derive from it)
+    pub payer: Pubkey,                  // Only wallet that may pay the installments
+    pub terms: InstallmentTerms,        // Amount and schedule
+    pub paid_count: u16,                // Installments paid so far
+    pub status: InstallmentStatus,      // Active, completed or defaulted
+    pub last_paid_at: i64,              // Unix timestamp of the latest installment (0 if none)
+    pub created_at: i64,                // Unix timestamp the plan was created
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; INSTALLMENT_PLAN_RESERVED], // Space for future fields
+}
+
+impl InstallmentPlan {
+    /// Due date of installment `index` (0-based)
+    pub fn due_at(&self, index: u16) -> i64 {
+        self.terms
+            .first_due_at
+            .saturating_add(self.terms.interval_secs.saturating_mul(index as i64))
+    }
+
+    /// Whether the next unpaid installment is past its due date plus grace period
+    pub fn is_overdue(&self, now: i64) -> bool {
+        self.paid_count < self.terms.installment_count
+            && now > self.due_at(self.paid_count).saturating_add(self.terms.grace_period_secs)
+    }
+
+    /// Count a paid installment; a defaulted plan returns to Active once it is caught up
+    pub fn record_payment(&mut self, now: i64) {
+        self.paid_count = self.paid_count.saturating_add(1);
+        self.last_paid_at = now;
+        if self.paid_count >= self.terms.installment_count {
+            self.status = InstallmentStatus::Completed;
+        } else if self.status == InstallmentStatus::Defaulted && !self.is_overdue(now) {
+            self.status = InstallmentStatus::Active;
+        }
+    }
+}
+
+/// Longest a group invoice may stay open for contributions (30 days)
+pub const MAX_GROUP_INVOICE_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// One order funded by several payers; settled once the target is reached
+/// PDA seeds = ["group_invoice", merchant_id, invoice_id]
+/// SOL is held in this account's lamports; USDC in an escrow token account it owns
+/// (seeds = ["escrow", group_invoice])
+#[account]
+#[derive(InitSpace)]
+pub struct GroupInvoice {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant being paid
+    pub invoice_id: [u8; 32],           // Merchant-chosen id, used as the settlement's order reference
+    pub pay_token: PayToken,            // Token contributions are made in
+    pub target_amount: u64,             // Amount settled once fully funded
+    pub funded_amount: u64,             // Contributions currently held in escrow
+    pub contributor_count: u32,         // Contribution records not yet reclaimed
+    pub expires_at: i64,                // Funding deadline; afterwards contributors may reclaim
+    pub settled: bool,                  // Settled (escrow paid out to the merchant)
+    pub created_at: i64,                // Unix timestamp the invoice was created
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; GROUP_INVOICE_RESERVED], // Space for future fields
+}
+
+impl GroupInvoice {
+    /// A contribution must fund part of the unfunded remainder of an open, unexpired invoice
+    pub fn check_contribution(&self, amount: u64, now: i64) -> Result<()> {
+        require!(!self.settled, QantaraError::GroupInvoiceSettled);
+        require!(now < self.expires_at, QantaraError::GroupInvoiceExpired);
+        let remaining = self
+            .target_amount
+            .checked_sub(self.funded_amount)
+            .ok_or(QantaraError::InvalidContributionAmount)?;
+        require!(
+            amount > 0 && amount <= remaining,
+            QantaraError::InvalidContributionAmount
+        );
+        Ok(())
+    }
+
+    /// Add a checked contribution, counting its contributor on their first one
+    pub fn record_contribution(&mut self, amount: u64, first_contribution: bool) -> Result<()> {
+        self.funded_amount = self
+            .funded_amount
+            .checked_add(amount)
+            .ok_or(QantaraError::InvalidContributionAmount)?;
+        if first_contribution {
+            self.contributor_count = self.contributor_count.saturating_add(1);
+        }
+        Ok(())
+    }
+
+    /// Refund owed when a contribution of `contributed` is reclaimed: nothing once the invoice
+    /// is settled, all of it once it expired unsettled; an open invoice keeps its contributions
+    pub fn reclaim_refund(&self, contributed: u64, now: i64) -> Result<u64> {
+        require!(self.settled || now >= self.expires_at, QantaraError::GroupInvoiceOpen);
+        Ok(if self.settled { 0 } else { contributed })
+    }
+
+    /// Drop a reclaimed contribution record and its refunded amount
+    pub fn record_reclaim(&mut self, refunded: u64) {
+        self.funded_amount = self.funded_amount.saturating_sub(refunded);
+        self.contributor_count = self.contributor_count.saturating_sub(1);
+    }
+}
+
+/// One payer's contributions to a group invoice
+/// PDA seeds = ["contribution", group_invoice, contributor]
+#[account]
+#[derive(InitSpace)]
+pub struct Contribution {
+    pub version: u8,                    // Layout version
+    pub group_invoice: Pubkey,          // Invoice contributed to
+    pub contributor: Pubkey,            // Payer (funds the rent, receives refunds)
+    pub amount: u64,                    // Total contributed
+    pub last_contributed_at: i64,       // Unix timestamp of the latest contribution
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; CONTRIBUTION_RESERVED], // Space for future fields
+}
+
+/// Days a MerchantDayStats bucket is kept before the crank may close it
+pub const DAY_STATS_RETENTION_DAYS: u32 = 90;
+
+/// One merchant's settlements for one UTC day
+/// PDA seeds = ["day_stats", merchant_id, day], day = unix_timestamp / 86400
+#[account]
+#[derive(InitSpace)]
+pub struct MerchantDayStats {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant
+    pub day: u32,                       // UTC day index (days since the unix epoch)
+    pub payment_count: u64,             // Settlements received this day
+    pub volume_sol: u64,                // Gross SOL received (lamports)
+    pub volume_usdc: u64,               // Gross USDC received (base units)
+    pub fees_sol: u64,                  // Protocol fees paid in SOL
+    pub fees_usdc: u64,                 // Protocol fees paid in USDC
+    pub rent_payer: Pubkey,             // Funded the bucket, receives the rent on close
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; MERCHANT_DAY_STATS_RESERVED], // Space for future fields
+}
+
+impl MerchantDayStats {
+    /// Add a settlement to the day's totals (saturating, so totals never block a payment)
+    pub fn record_settlement(&mut self, pay_token: PayToken, amount: u64, protocol_fee: u64) {
+        self.payment_count = self.payment_count.saturating_add(1);
+        match pay_token {
+            PayToken::Sol => {
+                self.volume_sol = self.volume_sol.saturating_add(amount);
+                self.fees_sol = self.fees_sol.saturating_add(protocol_fee);
+            }
+            PayToken::Usdc => {
+                self.volume_usdc = self.volume_usdc.saturating_add(amount);
+                self.fees_usdc = self.fees_usdc.saturating_add(protocol_fee);
+            }
+        }
+    }
+}
+
+/// How long a receipt must be kept before it can be closed (90 days)
+pub const RECEIPT_RETENTION_SECS: i64 = 90 * 24 * 60 * 60;
+
+/// Proof of payment (PDA seeds = ["receipt", merchant_id, order_reference])
+/// Created by settle when the payer passes the receipt account; the payer funds the rent
+#[account]
+#[derive(InitSpace)]
+pub struct Receipt {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant paid
+    pub order_reference: [u8; 32],      // Order reference the receipt is keyed by
+    pub payer: Pubkey,                  // Payer
+    pub rent_payer: Pubkey,             // Funded the receipt (payer, or sponsor), receives the rent on close
+    pub amount: u64,                    // Amount paid (pay token base units)
+    pub pay_token: PayToken,            // Token paid in
+    pub protocol_fee: u64,              // Protocol fee taken
+    pub payout_amount: u64,             // Amount paid out to the merchant
+    pub payout_token: PayToken,         // Token the merchant was paid in
+    pub buyback_amount: u64,            // Buyback tokens bought
+    pub burn_amount: u64,               // Buyback tokens burned
+    pub timestamp: i64,                 // Settlement time
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; RECEIPT_RESERVED], // Space for future fields
+}
+
+/// Maximum settlement memo length (bytes)
+pub const MAX_MEMO_LEN: usize = 64;
+
+/// Maximum donor message length (bytes)
+pub const MAX_DONATION_MESSAGE_LEN: usize = 280;
+
+/// Suggested donation amounts shown at checkout (0 = unused slot)
+pub const MAX_DONATION_TIERS: usize = 4;
+
+/// Donation minimum and suggested tiers for one pay token (base units)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub struct DonationTerms {
+    pub min_amount: u64,                // Smallest accepted donation (0 = any non-zero amount)
+    pub suggested: [u64; MAX_DONATION_TIERS], // Suggested amounts, each at least min_amount
+}
+
+impl DonationTerms {
+    /// Whether `amount` is a non-zero donation at or above the minimum
+    pub fn accepts(&self, amount: u64) -> bool {
+        amount > 0 && amount >= self.min_amount
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.suggested
+                .iter()
+                .all(|tier| *tier == 0 || *tier >= self.min_amount),
+            QantaraError::InvalidDonationSettings
+        );
+        Ok(())
+    }
+}
+
+/// A merchant's open-amount donation mode, used by `donate`
+/// PDA seeds = ["donations", merchant_id]
+#[account]
+#[derive(InitSpace)]
+pub struct DonationSettings {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant accepting donations
+    pub enabled: bool,                  // donate is rejected while false
+    pub sol: DonationTerms,             // SOL minimum and tiers (lamports)
+    pub usdc: DonationTerms,            // USDC minimum and tiers (base units)
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; DONATION_SETTINGS_RESERVED], // Space for future fields
+}
+
+impl DonationSettings {
+    pub fn terms(&self, pay_token: PayToken) -> &DonationTerms {
+        match pay_token {
+            PayToken::Sol => &self.sol,
+            PayToken::Usdc => &self.usdc,
+        }
+    }
+}
+
+/// Where a merchant's tips go and whether they pay the protocol fee
+/// PDA seeds = ["tips", merchant_id]
+#[account]
+#[derive(InitSpace)]
+pub struct TipPolicy {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant
+    pub tip_wallet: Pubkey,             // Tip recipient, e.g. a staff pool (USDC tips go to its token account)
+    pub fee_on_tips: bool,              // Protocol fee also applies to tips
+    pub bump: u8,                       // PDA bump
+    pub reserved: [u8; TIP_POLICY_RESERVED], // Space for future fields
+}
+
+impl TipPolicy {
+    /// Whether tips pay the protocol fee: only when the merchant opted in and the settlement
+    /// itself is not fee-waived
+    pub fn fee_applies(&self, fee_waived: bool) -> bool {
+        self.fee_on_tips && !fee_waived
+    }
+}
+
+/// Merchant metadata limits (bytes)
+pub const MAX_METADATA_NAME_LEN: usize = 64;
+pub const MAX_METADATA_URI_LEN: usize = 200;
+pub const MAX_METADATA_WEBSITE_LEN: usize = 100;
+pub const MAX_METADATA_CONTACT_LEN: usize = 100;
+/// Category codes are ISO 18245 merchant category codes (4 digits)
+pub const MAX_CATEGORY_CODE: u16 = 9999;
+
+/// Display information for wallets and checkout (PDA seeds = ["metadata", merchant_id])
+/// Sized to its contents; resized on every update
+#[account]
+pub struct MerchantMetadata {
+    pub version: u8,                    // Layout version
+    pub merchant_id: u64,               // Merchant this metadata describes
+    pub name: String,                   // Display name
+    pub uri: String,                    // Logo / metadata JSON (https://, ipfs:// or ar://)
+    pub website: String,                // Merchant website (https://)
+    pub support_contact: String,        // Support email or URL
+    pub category_code: u16,             // ISO 18245 merchant category code (0 = unset)
+    pub terms_hash: [u8; 32],           // sha256 of the off-chain terms of sale
+    pub bump: u8,                       // PDA bump
+}
+
+impl MerchantMetadata {
+    /// Account size including the discriminator for the given contents
+    pub fn space(args: &MerchantMetadataArgs) -> usize {
+        8 + 1
+            + 8
+            + 4 + args.name.len()
+            + 4 + args.uri.len()
+            + 4 + args.website.len()
+            + 4 + args.support_contact.len()
+            + 2
+            + 32
+            + 1
+    }
+}
+
+/// Maximum number of allowlisted instruction discriminators per router
+pub const MAX_ROUTER_DISCRIMINATORS: usize = 8;
+
+/// Allowlisted swap router (one PDA per router program)
+/// Lets the protocol fail over between aggregators (Jupiter, Raydium, Orca, ...)
+#[account]
+#[derive(InitSpace)]
+pub struct SwapRouter {
+    pub version: u8,                    // Layout version
+    pub program_id: Pubkey,             // Router program
+    #[max_len(MAX_ROUTER_DISCRIMINATORS)]
+    pub discriminators: Vec<[u8; 8]>,   // Permitted swap entrypoints
+    pub enabled: bool,                  // Disable during aggregator incidents
     pub bump: u8,                       // PDA bump
+    pub reserved: [u8; SWAP_ROUTER_RESERVED], // Space for future fields
+}
+
+impl SwapRouter {
+    /// Whether `data` targets one of the allowlisted swap entrypoints
+    pub fn allows(&self, data: &[u8]) -> bool {
+        data.len() >= 8 && self.discriminators.iter().any(|d| d[..] == data[..8])
+    }
 }
 
 /// Initialize protocol context
@@ -44,6 +766,32 @@ pub struct InitProtocol<'info> {
     pub system_program: Program<'info, System>,
 }
 
+/// Initialize protocol stats shard context (one-time per shard, admin only)
+#[derive(Accounts)]
+#[instruction(shard: u8)]
+pub struct InitProtocolStats<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProtocolStats::INIT_SPACE,
+        seeds = [b"stats".as_ref(), &[shard]],
+        bump
+    )]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 /// Update protocol context
 #[derive(Accounts)]
 pub struct UpdateProtocol<'info> {
@@ -91,101 +839,1406 @@ pub struct UpdateMerchant<'info> {
     pub owner: Signer<'info>,
 }
 
-/// Payment settlement context
+/// Update settlement mint context (checked against the protocol's USDC mint)
 #[derive(Accounts)]
-#[instruction(merchant_id: u64)]
-pub struct Settle<'info> {
-    /// Protocol configuration
+pub struct UpdateSettlementMint<'info> {
     #[account(
         seeds = [b"protocol"],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
 
-    /// Merchant registry (validated on-chain)
     #[account(
-        seeds = [b"merchant", &merchant_id.to_le_bytes()],
-        bump = merchant_registry.bump
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
     )]
     pub merchant_registry: Account<'info, MerchantRegistry>,
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
-
-    /// CHECK: Vault for SOL (PDA owned by program)
-    #[account(mut)]
-    pub vault_sol: AccountInfo<'info>,
-
-    /// CHECK: Vault USDC token account (PDA-owned, must be initialized before first use)
-    #[account(mut)]
-    pub vault_usdc: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+}
 
-    /// CHECK: USDC mint
-    pub usdc_mint: AccountInfo<'info>,
+/// Close merchant context (returns rent to the owner)
+#[derive(Accounts)]
+pub struct CloseMerchant<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
 
-    #[account(mut)]
+    /// Merchant buyback vault (must be empty, closed alongside the registry)
+    #[account(
+        mut,
+        token::mint = merchant_registry.buyback_mint,
+        token::authority = merchant_registry
+    )]
     pub vault_buyback_token: Account<'info, TokenAccount>,
 
-    #[account(mut)]
-    pub buyback_mint: Account<'info, Mint>,
-
-    /// CHECK: Protocol fee recipient (SOL)
-    #[account(mut)]
-    pub protocol_wallet: AccountInfo<'info>,
-
-    /// CHECK: Protocol fee recipient (USDC)
-    #[account(mut)]
-    pub protocol_wallet_usdc: Account<'info, TokenAccount>,
+    /// CHECK: MerchantMetadata PDA (may be uninitialized); closed alongside the registry
+    #[account(
+        mut,
+        seeds = [b"metadata", &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub merchant_metadata: UncheckedAccount<'info>,
 
-    /// CHECK: Merchant payout destination (validated against registry)
-    #[account(mut)]
-    pub merchant_payout_wallet: AccountInfo<'info>,
+    /// CHECK: MerchantLimits PDA (may be uninitialized); closed alongside the registry
+    #[account(
+        mut,
+        seeds = [b"limits".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub merchant_limits: UncheckedAccount<'info>,
 
-    /// CHECK: Merchant payout destination (USDC)
-    #[account(mut)]
-    pub merchant_payout_usdc: Account<'info, TokenAccount>,
+    /// CHECK: TipPolicy PDA (may be uninitialized); closed alongside the registry
+    #[account(
+        mut,
+        seeds = [b"tips".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub tip_policy: UncheckedAccount<'info>,
 
-    /// CHECK: Payer USDC account
+    /// CHECK: DonationSettings PDA (may be uninitialized); closed alongside the registry
     #[account(
-        token::mint = usdc_mint
+        mut,
+        seeds = [b"donations".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump
     )]
-    pub payer_usdc_account: Account<'info, TokenAccount>,
+    pub donation_settings: UncheckedAccount<'info>,
 
-    /// CHECK: Jupiter router program (validated against protocol config)
-    pub jupiter_router: AccountInfo<'info>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
 
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-
-    // Remaining accounts: Jupiter swap route accounts (dynamic)
 }
 
-/// Initialize vault USDC token account context
+/// Claim handle context (handle must be unclaimed)
 #[derive(Accounts)]
-pub struct InitVaultUsdc<'info> {
+#[instruction(handle: String)]
+pub struct ClaimHandle<'info> {
     #[account(
         init,
-        payer = payer,
-        token::mint = usdc_mint,
-        token::authority = vault_usdc,
-        seeds = [b"vault_usdc", usdc_mint.key().as_ref()],
+        payer = owner,
+        space = 8 + MerchantHandle::INIT_SPACE,
+        seeds = [b"handle", handle.as_bytes()],
         bump
     )]
-    pub vault_usdc: Account<'info, TokenAccount>,
+    pub merchant_handle: Account<'info, MerchantHandle>,
 
-    /// CHECK: USDC mint
-    pub usdc_mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
 
     #[account(mut)]
-    pub payer: Signer<'info>,
+    pub owner: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
 
-/// Instruction arguments
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct InitProtocolArgs {
+/// Transfer handle context (current and new owner both sign)
+#[derive(Accounts)]
+pub struct TransferHandle<'info> {
+    #[account(
+        mut,
+        seeds = [b"handle", merchant_handle.handle.as_bytes()],
+        bump = merchant_handle.bump,
+        has_one = owner
+    )]
+    pub merchant_handle: Account<'info, MerchantHandle>,
+
+    /// Merchant the handle resolves to now
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_handle.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub owner: Signer<'info>,
+
+    /// Merchant the handle will resolve to
+    #[account(
+        mut,
+        seeds = [b"merchant", &new_merchant_registry.merchant_id.to_le_bytes()],
+        bump = new_merchant_registry.bump,
+        constraint = new_merchant_registry.owner == new_owner.key() @ QantaraError::Unauthorized
+    )]
+    pub new_merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub new_owner: Signer<'info>,
+}
+
+/// Release handle context (returns rent to the owner, handle becomes claimable)
+#[derive(Accounts)]
+pub struct ReleaseHandle<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"handle", merchant_handle.handle.as_bytes()],
+        bump = merchant_handle.bump,
+        has_one = owner
+    )]
+    pub merchant_handle: Account<'info, MerchantHandle>,
+
+    /// Merchant the handle resolves to
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_handle.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+/// Create merchant metadata context (owner only)
+#[derive(Accounts)]
+#[instruction(args: MerchantMetadataArgs)]
+pub struct InitMerchantMetadata<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = MerchantMetadata::space(&args),
+        seeds = [b"metadata", &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub merchant_metadata: Account<'info, MerchantMetadata>,
+
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Update merchant metadata context (owner only, account is resized to the new contents)
+#[derive(Accounts)]
+#[instruction(args: MerchantMetadataArgs)]
+pub struct UpdateMerchantMetadata<'info> {
+    #[account(
+        mut,
+        seeds = [b"metadata", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_metadata.bump,
+        realloc = MerchantMetadata::space(&args),
+        realloc::payer = owner,
+        realloc::zero = false
+    )]
+    pub merchant_metadata: Account<'info, MerchantMetadata>,
+
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Create a merchant's limits account (merchant owner or protocol authority)
+/// Once created, settle requires it for every payment to the merchant
+#[derive(Accounts)]
+pub struct InitMerchantLimits<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MerchantLimits::INIT_SPACE,
+        seeds = [b"limits".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub merchant_limits: Account<'info, MerchantLimits>,
+
+    /// Merchant owner or protocol authority (checked in the handler)
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Update merchant limits context (owner only)
+#[derive(Accounts)]
+pub struct UpdateMerchantLimits<'info> {
+    #[account(
+        mut,
+        seeds = [b"limits".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_limits.bump
+    )]
+    pub merchant_limits: Account<'info, MerchantLimits>,
+
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Create a merchant's donation settings (owner only, disabled until updated)
+#[derive(Accounts)]
+pub struct InitDonationSettings<'info> {
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + DonationSettings::INIT_SPACE,
+        seeds = [b"donations".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub donation_settings: Account<'info, DonationSettings>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Update donation settings context (owner only)
+#[derive(Accounts)]
+pub struct UpdateDonationSettings<'info> {
+    #[account(
+        mut,
+        seeds = [b"donations".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump = donation_settings.bump
+    )]
+    pub donation_settings: Account<'info, DonationSettings>,
+
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Create a merchant's tip policy (owner only)
+#[derive(Accounts)]
+pub struct InitTipPolicy<'info> {
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + TipPolicy::INIT_SPACE,
+        seeds = [b"tips".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub tip_policy: Account<'info, TipPolicy>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Update tip policy context (owner only)
+#[derive(Accounts)]
+pub struct UpdateTipPolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"tips".as_ref(), &merchant_registry.merchant_id.to_le_bytes()],
+        bump = tip_policy.bump
+    )]
+    pub tip_policy: Account<'info, TipPolicy>,
+
+    #[account(
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Mark a merchant as a verified nonprofit (admin only)
+#[derive(Accounts)]
+pub struct SetVerifiedNonprofit<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub authority: Signer<'info>,
+}
+
+/// Set protocol ceilings on a merchant's limits (admin only)
+#[derive(Accounts)]
+pub struct SetProtocolLimits<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"limits".as_ref(), &merchant_limits.merchant_id.to_le_bytes()],
+        bump = merchant_limits.bump
+    )]
+    pub merchant_limits: Account<'info, MerchantLimits>,
+
+    pub authority: Signer<'info>,
+}
+
+/// Add a wallet to the protocol denylist (admin only)
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct AddProtocolDenylistEntry<'info> {
+    #[account(
+        mut,
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PayerListEntry::INIT_SPACE,
+        seeds = [b"payer_list".as_ref(), wallet.as_ref()],
+        bump
+    )]
+    pub payer_list_entry: Account<'info, PayerListEntry>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Remove a wallet from the protocol denylist (admin only, rent returns to the admin)
+#[derive(Accounts)]
+pub struct RemoveProtocolDenylistEntry<'info> {
+    #[account(
+        mut,
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"payer_list".as_ref(), payer_list_entry.payer.as_ref()],
+        bump = payer_list_entry.bump
+    )]
+    pub payer_list_entry: Account<'info, PayerListEntry>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+/// Add a wallet to a merchant's denylist or allowlist (owner only)
+#[derive(Accounts)]
+#[instruction(wallet: Pubkey)]
+pub struct AddPayerListEntry<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + PayerListEntry::INIT_SPACE,
+        seeds = [b"payer_list".as_ref(), &merchant_registry.merchant_id.to_le_bytes(), wallet.as_ref()],
+        bump
+    )]
+    pub payer_list_entry: Account<'info, PayerListEntry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Remove a wallet from a merchant's list (owner only, rent returns to the owner)
+#[derive(Accounts)]
+pub struct RemovePayerListEntry<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            b"payer_list".as_ref(),
+            &merchant_registry.merchant_id.to_le_bytes(),
+            payer_list_entry.payer.as_ref()
+        ],
+        bump = payer_list_entry.bump
+    )]
+    pub payer_list_entry: Account<'info, PayerListEntry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+/// Turn a merchant's allowlist on or off (owner only)
+#[derive(Accounts)]
+pub struct SetPayerAllowlist<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Hold a payer's funds for a merchant
+#[derive(Accounts)]
+#[instruction(authorization_id: [u8; 32])]
+pub struct Authorize<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Authorization::INIT_SPACE,
+        seeds = [
+            b"authorization".as_ref(),
+            &merchant_registry.merchant_id.to_le_bytes(),
+            authorization_id.as_ref()
+        ],
+        bump
+    )]
+    pub authorization: Account<'info, Authorization>,
+
+    /// Escrow for USDC authorizations (owned by the authorization PDA)
+    #[account(
+        init,
+        payer = payer,
+        token::mint = usdc_mint,
+        token::authority = authorization,
+        seeds = [b"escrow".as_ref(), authorization.key().as_ref()],
+        bump
+    )]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// USDC mint (USDC authorizations, must be the protocol's USDC mint)
+    #[account(address = protocol_config.usdc_mint @ QantaraError::InvalidUsdcMint)]
+    pub usdc_mint: Option<Account<'info, Mint>>,
+
+    /// Payer USDC account (USDC authorizations, payer signs as its owner or delegate)
+    #[account(mut)]
+    pub payer_usdc_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Protocol denylist entry PDA for the payer (see Settle)
+    #[account(
+        seeds = [b"payer_list".as_ref(), payer.key().as_ref()],
+        bump
+    )]
+    pub protocol_payer_entry: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Merchant list entry PDA for the payer (see Settle)
+    #[account(
+        seeds = [
+            b"payer_list".as_ref(),
+            &merchant_registry.merchant_id.to_le_bytes(),
+            payer.key().as_ref()
+        ],
+        bump
+    )]
+    pub merchant_payer_entry: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Release an authorization's remaining funds to the payer and close it
+/// (merchant owner at any time; anyone once captured or expired)
+#[derive(Accounts)]
+pub struct VoidAuthorization<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &authorization.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [
+            b"authorization".as_ref(),
+            &authorization.merchant_id.to_le_bytes(),
+            authorization.authorization_id.as_ref()
+        ],
+        bump = authorization.bump,
+        has_one = payer
+    )]
+    pub authorization: Account<'info, Authorization>,
+
+    /// CHECK: Original payer, receives the remainder and rent (checked by has_one)
+    #[account(mut)]
+    pub payer: UncheckedAccount<'info>,
+
+    /// Escrow of a USDC authorization
+    #[account(
+        mut,
+        seeds = [b"escrow".as_ref(), authorization.key().as_ref()],
+        bump
+    )]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Payer USDC account receiving the refund (USDC authorizations)
+    #[account(
+        mut,
+        token::authority = payer
+    )]
+    pub payer_usdc_account: Option<Account<'info, TokenAccount>>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Create an installment plan for a payer (merchant owner funds the rent)
+#[derive(Accounts)]
+#[instruction(plan_id: [u8; 32])]
+pub struct CreateInstallmentPlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + InstallmentPlan::INIT_SPACE,
+        seeds = [
+            b"installments".as_ref(),
+            &merchant_registry.merchant_id.to_le_bytes(),
+            plan_id.as_ref()
+        ],
+        bump
+    )]
+    pub installment_plan: Account<'info, InstallmentPlan>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Flag an overdue installment plan as defaulted (permissionless crank)
+#[derive(Accounts)]
+pub struct MarkInstallmentDefault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"installments".as_ref(),
+            &installment_plan.merchant_id.to_le_bytes(),
+            installment_plan.plan_id.as_ref()
+        ],
+        bump = installment_plan.bump
+    )]
+    pub installment_plan: Account<'info, InstallmentPlan>,
+}
+
+/// Close an installment plan (merchant owner, any status; rent returns to the owner)
+#[derive(Accounts)]
+pub struct CloseInstallmentPlan<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &installment_plan.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            b"installments".as_ref(),
+            &installment_plan.merchant_id.to_le_bytes(),
+            installment_plan.plan_id.as_ref()
+        ],
+        bump = installment_plan.bump
+    )]
+    pub installment_plan: Account<'info, InstallmentPlan>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+/// Open a group invoice for contributions (merchant owner funds the rent)
+#[derive(Accounts)]
+#[instruction(invoice_id: [u8; 32])]
+pub struct CreateGroupInvoice<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_registry.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GroupInvoice::INIT_SPACE,
+        seeds = [
+            b"group_invoice".as_ref(),
+            &merchant_registry.merchant_id.to_le_bytes(),
+            invoice_id.as_ref()
+        ],
+        bump
+    )]
+    pub group_invoice: Account<'info, GroupInvoice>,
+
+    /// Escrow for USDC invoices (owned by the group invoice PDA)
+    #[account(
+        init,
+        payer = owner,
+        token::mint = usdc_mint,
+        token::authority = group_invoice,
+        seeds = [b"escrow".as_ref(), group_invoice.key().as_ref()],
+        bump
+    )]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// USDC mint (USDC invoices, must be the protocol's USDC mint)
+    #[account(address = protocol_config.usdc_mint @ QantaraError::InvalidUsdcMint)]
+    pub usdc_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Contribute to a group invoice (contributor funds their record's rent on first contribution)
+#[derive(Accounts)]
+pub struct Contribute<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [b"merchant", &group_invoice.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"group_invoice".as_ref(),
+            &group_invoice.merchant_id.to_le_bytes(),
+            group_invoice.invoice_id.as_ref()
+        ],
+        bump = group_invoice.bump
+    )]
+    pub group_invoice: Account<'info, GroupInvoice>,
+
+    /// CHECK: Contribution record PDA, created on the first contribution
+    #[account(
+        mut,
+        seeds = [
+            b"contribution".as_ref(),
+            group_invoice.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump
+    )]
+    pub contribution: UncheckedAccount<'info>,
+
+    /// Escrow of a USDC invoice
+    #[account(
+        mut,
+        seeds = [b"escrow".as_ref(), group_invoice.key().as_ref()],
+        bump
+    )]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Contributor USDC account (USDC invoices, contributor signs as its owner or delegate)
+    #[account(mut)]
+    pub contributor_usdc_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Protocol denylist entry PDA for the contributor (see Settle)
+    #[account(
+        seeds = [b"payer_list".as_ref(), contributor.key().as_ref()],
+        bump
+    )]
+    pub protocol_payer_entry: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Merchant list entry PDA for the contributor (see Settle)
+    #[account(
+        seeds = [
+            b"payer_list".as_ref(),
+            &group_invoice.merchant_id.to_le_bytes(),
+            contributor.key().as_ref()
+        ],
+        bump
+    )]
+    pub merchant_payer_entry: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub contributor: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Close a contribution record once its invoice is settled or expired, refunding an unsettled
+/// contribution (permissionless; funds and rent go to the contributor)
+#[derive(Accounts)]
+pub struct ReclaimContribution<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"group_invoice".as_ref(),
+            &group_invoice.merchant_id.to_le_bytes(),
+            group_invoice.invoice_id.as_ref()
+        ],
+        bump = group_invoice.bump
+    )]
+    pub group_invoice: Account<'info, GroupInvoice>,
+
+    #[account(
+        mut,
+        close = contributor,
+        seeds = [
+            b"contribution".as_ref(),
+            group_invoice.key().as_ref(),
+            contributor.key().as_ref()
+        ],
+        bump = contribution.bump,
+        has_one = contributor
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    /// CHECK: Contributor, receives the refund and rent (checked by has_one)
+    #[account(mut)]
+    pub contributor: UncheckedAccount<'info>,
+
+    /// Escrow of a USDC invoice
+    #[account(
+        mut,
+        seeds = [b"escrow".as_ref(), group_invoice.key().as_ref()],
+        bump
+    )]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Contributor USDC account receiving the refund (USDC invoices)
+    #[account(
+        mut,
+        token::authority = contributor
+    )]
+    pub contributor_usdc_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Close a group invoice with no contribution records left (merchant owner; rent to the owner)
+#[derive(Accounts)]
+pub struct CloseGroupInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", &group_invoice.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump,
+        has_one = owner
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            b"group_invoice".as_ref(),
+            &group_invoice.merchant_id.to_le_bytes(),
+            group_invoice.invoice_id.as_ref()
+        ],
+        bump = group_invoice.bump
+    )]
+    pub group_invoice: Account<'info, GroupInvoice>,
+
+    /// Escrow of a USDC invoice (closed along with it)
+    #[account(
+        mut,
+        seeds = [b"escrow".as_ref(), group_invoice.key().as_ref()],
+        bump
+    )]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Open a day bucket ahead of settlement (permissionless, caller funds the rent)
+#[derive(Accounts)]
+#[instruction(merchant_id: u64, day: u32)]
+pub struct OpenDayStats<'info> {
+    #[account(
+        seeds = [b"merchant", &merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MerchantDayStats::INIT_SPACE,
+        seeds = [b"day_stats".as_ref(), &merchant_id.to_le_bytes(), &day.to_le_bytes()],
+        bump
+    )]
+    pub merchant_day_stats: Account<'info, MerchantDayStats>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Close an expired day bucket (permissionless crank, rent returns to whoever funded it)
+#[derive(Accounts)]
+pub struct CloseDayStats<'info> {
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [
+            b"day_stats".as_ref(),
+            &merchant_day_stats.merchant_id.to_le_bytes(),
+            &merchant_day_stats.day.to_le_bytes()
+        ],
+        bump = merchant_day_stats.bump,
+        has_one = rent_payer
+    )]
+    pub merchant_day_stats: Account<'info, MerchantDayStats>,
+
+    /// CHECK: Funded the bucket, receives the rent (checked by has_one)
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+}
+
+/// Close receipt context (payer, rent payer or merchant owner, after the retention period)
+#[derive(Accounts)]
+pub struct CloseReceipt<'info> {
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [b"receipt", &receipt.merchant_id.to_le_bytes(), receipt.order_reference.as_ref()],
+        bump = receipt.bump,
+        has_one = rent_payer
+    )]
+    pub receipt: Account<'info, Receipt>,
+
+    /// CHECK: Account that funded the receipt, receives the rent (checked by has_one)
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Merchant registry, required when the merchant owner closes the receipt
+    #[account(
+        seeds = [b"merchant", &receipt.merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Option<Account<'info, MerchantRegistry>>,
+
+    pub authority: Signer<'info>,
+}
+
+/// Migrate protocol config context (the pre-versioning layout cannot be deserialized as Account)
+#[derive(Accounts)]
+pub struct MigrateProtocol<'info> {
+    /// CHECK: Legacy ProtocolConfig (owner and seeds checked here, discriminator and layout in handler)
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"protocol"],
+        bump
+    )]
+    pub protocol_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Migrate merchant registry context (permissionless; payer funds the extra rent)
+#[derive(Accounts)]
+#[instruction(merchant_id: u64)]
+pub struct MigrateMerchant<'info> {
+    /// CHECK: Legacy MerchantRegistry (owner and seeds checked here, discriminator and layout in handler)
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"merchant", &merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub merchant_registry: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Migrate v1 merchant context (signed by the v1 owner)
+#[derive(Accounts)]
+#[instruction(merchant_id: u64)]
+pub struct MigrateFromV1<'info> {
+    /// V1 merchant config (owned by the v1 program, keyed by owner)
+    #[account(
+        mut,
+        seeds = [b"merchant", owner.key().as_ref()],
+        bump = v1_merchant_config.bump,
+        seeds::program = qantara_program.key(),
+        has_one = owner
+    )]
+    pub v1_merchant_config: Account<'info, qantara::state::MerchantConfig>,
+
+    /// CHECK: V1 migration record PDA, created and validated by the v1 program
+    #[account(mut)]
+    pub v1_migration_record: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + MerchantRegistry::INIT_SPACE,
+        seeds = [b"merchant", &merchant_id.to_le_bytes()],
+        bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub qantara_program: Program<'info, qantara::program::Qantara>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Add router context
+#[derive(Accounts)]
+#[instruction(program_id: Pubkey)]
+pub struct AddRouter<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + SwapRouter::INIT_SPACE,
+        seeds = [b"router", program_id.as_ref()],
+        bump
+    )]
+    pub swap_router: Account<'info, SwapRouter>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Update router context
+#[derive(Accounts)]
+pub struct UpdateRouter<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"router", swap_router.program_id.as_ref()],
+        bump = swap_router.bump
+    )]
+    pub swap_router: Account<'info, SwapRouter>,
+
+    pub authority: Signer<'info>,
+}
+
+/// Remove router context (returns rent to the protocol authority)
+#[derive(Accounts)]
+pub struct RemoveRouter<'info> {
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump,
+        has_one = authority
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"router", swap_router.program_id.as_ref()],
+        bump = swap_router.bump
+    )]
+    pub swap_router: Account<'info, SwapRouter>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+/// Payment settlement context
+#[derive(Accounts)]
+#[instruction(merchant_id: u64)]
+pub struct Settle<'info> {
+    /// Protocol configuration
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// Merchant registry (validated on-chain, settlement totals updated)
+    #[account(
+        mut,
+        seeds = [b"merchant", &merchant_id.to_le_bytes()],
+        bump = merchant_registry.bump
+    )]
+    pub merchant_registry: Account<'info, MerchantRegistry>,
+
+    /// Protocol-wide settlement totals (this merchant's shard)
+    #[account(
+        mut,
+        seeds = [b"stats".as_ref(), &[ProtocolStats::shard_of(merchant_id)]],
+        bump = protocol_stats.bump
+    )]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Vault for SOL (system-owned PDA; the program signs transfers out of it)
+    #[account(
+        mut,
+        seeds = [b"vault", b"sol"],
+        bump
+    )]
+    pub vault_sol: AccountInfo<'info>,
+
+    /// Vault USDC token account (PDA that is its own token authority, see InitVaultUsdc)
+    #[account(
+        mut,
+        seeds = [b"vault_usdc", usdc_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    /// CHECK: USDC mint (must be the protocol's USDC mint)
+    #[account(address = protocol_config.usdc_mint @ QantaraError::InvalidUsdcMint)]
+    pub usdc_mint: AccountInfo<'info>,
+
+    /// Merchant buyback vault (bought tokens land here; burned with the registry as authority)
+    #[account(
+        mut,
+        token::mint = merchant_registry.buyback_mint,
+        token::authority = merchant_registry
+    )]
+    pub vault_buyback_token: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub buyback_mint: Account<'info, Mint>,
+
+    /// CHECK: Protocol fee recipient (SOL)
+    #[account(mut)]
+    pub protocol_wallet: AccountInfo<'info>,
+
+    /// CHECK: Protocol fee recipient (USDC)
+    #[account(mut)]
+    pub protocol_wallet_usdc: Account<'info, TokenAccount>,
+
+    /// CHECK: Merchant payout destination (validated against registry)
+    #[account(mut)]
+    pub merchant_payout_wallet: AccountInfo<'info>,
+
+    /// CHECK: Merchant payout destination (USDC)
+    #[account(mut)]
+    pub merchant_payout_usdc: Account<'info, TokenAccount>,
+
+    /// CHECK: Payer USDC account (payer signs as its owner or delegate)
+    #[account(
+        mut,
+        token::mint = usdc_mint
+    )]
+    pub payer_usdc_account: Account<'info, TokenAccount>,
+
+    /// Handle the payer resolved the merchant from (must point at merchant_id)
+    #[account(
+        seeds = [b"handle", merchant_handle.handle.as_bytes()],
+        bump = merchant_handle.bump
+    )]
+    pub merchant_handle: Option<Account<'info, MerchantHandle>>,
+
+    /// CHECK: Solana Pay reference (read-only marker so indexers can find the transaction by
+    /// address; must equal `order_reference`)
+    pub reference: Option<UncheckedAccount<'info>>,
+
+    /// Merchant limits (required once the merchant has limits enabled)
+    #[account(
+        mut,
+        seeds = [b"limits".as_ref(), &merchant_id.to_le_bytes()],
+        bump = merchant_limits.bump
+    )]
+    pub merchant_limits: Option<Account<'info, MerchantLimits>>,
+
+    /// CHECK: Protocol denylist entry PDA for the payer (required while the denylist is non-empty;
+    /// an uninitialized account means the payer is not listed)
+    #[account(
+        seeds = [b"payer_list".as_ref(), payer.key().as_ref()],
+        bump
+    )]
+    pub protocol_payer_entry: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Merchant list entry PDA for the payer (required while the merchant has denylist
+    /// entries or an allowlist; an uninitialized account means the payer is not listed)
+    #[account(
+        seeds = [b"payer_list".as_ref(), &merchant_id.to_le_bytes(), payer.key().as_ref()],
+        bump
+    )]
+    pub merchant_payer_entry: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Instructions sysvar, required when the merchant signs quotes (the ed25519
+    /// verify instruction must directly precede this instruction)
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// Today's day bucket, opened ahead of time by a crank (open_day_stats); settlements
+    /// without it skip the daily totals and never pay its rent
+    #[account(
+        mut,
+        seeds = [
+            b"day_stats".as_ref(),
+            &merchant_id.to_le_bytes(),
+            &merchant_day_stats.day.to_le_bytes()
+        ],
+        bump = merchant_day_stats.bump
+    )]
+    pub merchant_day_stats: Option<Account<'info, MerchantDayStats>>,
+
+    /// CHECK: Receipt PDA ["receipt", merchant_id, order_reference], created in the handler
+    #[account(mut)]
+    pub receipt: Option<UncheckedAccount<'info>>,
+
+    /// Relayer funding the rent of accounts created during settlement (settle_sponsored)
+    #[account(mut)]
+    pub sponsor: Option<Signer<'info>>,
+
+    /// Authorization being captured (capture only)
+    #[account(
+        mut,
+        seeds = [
+            b"authorization".as_ref(),
+            &merchant_id.to_le_bytes(),
+            authorization.authorization_id.as_ref()
+        ],
+        bump = authorization.bump
+    )]
+    pub authorization: Option<Account<'info, Authorization>>,
+
+    /// Escrow of a USDC authorization or group invoice being settled (owner checked on-chain)
+    #[account(mut)]
+    pub escrow_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Installment plan being paid (settle_installment only)
+    #[account(
+        mut,
+        seeds = [
+            b"installments".as_ref(),
+            &merchant_id.to_le_bytes(),
+            installment_plan.plan_id.as_ref()
+        ],
+        bump = installment_plan.bump
+    )]
+    pub installment_plan: Option<Account<'info, InstallmentPlan>>,
+
+    /// Group invoice being settled (settle_group_invoice only)
+    #[account(
+        mut,
+        seeds = [
+            b"group_invoice".as_ref(),
+            &merchant_id.to_le_bytes(),
+            group_invoice.invoice_id.as_ref()
+        ],
+        bump = group_invoice.bump
+    )]
+    pub group_invoice: Option<Account<'info, GroupInvoice>>,
+
+    /// Merchant's donation settings (donate only)
+    #[account(
+        seeds = [b"donations".as_ref(), &merchant_id.to_le_bytes()],
+        bump = donation_settings.bump
+    )]
+    pub donation_settings: Option<Account<'info, DonationSettings>>,
+
+    /// Merchant's tip policy (required when the payer adds a tip)
+    #[account(
+        seeds = [b"tips".as_ref(), &merchant_id.to_le_bytes()],
+        bump = tip_policy.bump
+    )]
+    pub tip_policy: Option<Account<'info, TipPolicy>>,
+
+    /// CHECK: Tip recipient for SOL tips (validated against the tip policy)
+    #[account(mut)]
+    pub tip_wallet: Option<UncheckedAccount<'info>>,
+
+    /// Tip recipient's USDC account for USDC tips (owner validated against the tip policy)
+    #[account(mut)]
+    pub tip_wallet_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Payer's input token account for settle_with_swap (owner validated on-chain)
+    #[account(mut)]
+    pub payer_input_token: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Swap router program (validated against the router registry)
+    pub jupiter_router: AccountInfo<'info>,
+
+    /// Router registry entry for `jupiter_router` (unknown programs have no entry)
+    #[account(
+        seeds = [b"router", jupiter_router.key().as_ref()],
+        bump = swap_router.bump
+    )]
+    pub swap_router: Account<'info, SwapRouter>,
+
+    /// CHECK: Pay token/USD price feed (validated against protocol config, required for buybacks)
+    pub pay_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Buyback token/USD price feed (validated against registry, required for buybacks)
+    pub buyback_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Settlement token/USD price feed (validated against protocol config, required for payout conversion)
+    pub settlement_price_feed: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    // Remaining accounts: Jupiter swap route accounts (dynamic)
+    // Layout: [input swap route (settle_with_swap) | buyback route | payout conversion route]
+}
+
+/// Initialize vault USDC token account context
+#[derive(Accounts)]
+pub struct InitVaultUsdc<'info> {
+    #[account(
+        init,
+        payer = payer,
+        token::mint = usdc_mint,
+        token::authority = vault_usdc,
+        seeds = [b"vault_usdc", usdc_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    /// CHECK: USDC mint
+    pub usdc_mint: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Instruction arguments
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InitProtocolArgs {
     pub protocol_fee_bps: u16,
     pub protocol_wallet: Pubkey,
     pub jupiter_router: Pubkey,
@@ -198,12 +2251,80 @@ pub struct RegisterMerchantArgs {
     pub buyback_mint: Pubkey,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MerchantMetadataArgs {
+    pub name: String,
+    pub uri: String,
+    pub website: String,
+    pub support_contact: String,
+    pub category_code: u16,
+    pub terms_hash: [u8; 32],
+}
+
+/// Split and optional inputs shared by the settle instructions
+/// Captures, installments and group invoices derive their own order reference, and only
+/// `settle` takes a tip; those instructions reject the field when it is set
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SettleArgs {
+    pub min_out: u64,                       // Minimum buyback output (slippage protection)
+    pub payout_bps: u16,                    // Share of the net amount paid to the merchant
+    pub buyback_bps: u16,                   // Share of the net amount spent on the buyback
+    pub burn_of_buyback_bps: u16,           // Share of the bought tokens burned
+    pub swap_data: Vec<u8>,                 // Buyback router instruction data
+    pub payout_swap: Option<PayoutSwap>,    // Converts the payout into the settlement currency
+    pub order_reference: Option<[u8; 32]>,  // Order id (receipt seed and Solana Pay reference)
+    pub memo: Option<String>,               // Note recorded in PaymentSettled
+    pub tip: Option<u64>,                   // Gratuity for the merchant's tip wallet
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
 pub enum PayToken {
     Sol,
     Usdc,
 }
 
+/// USD → lamports conversion applied by settle_usd
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct UsdConversion {
+    pub usd_amount: u64,                // Invoice amount in micro-USD
+    pub price: i64,                     // SOL/USD oracle price used
+    pub expo: i32,                      // Oracle price exponent
+    pub publish_time: i64,              // Oracle publish timestamp
+}
+
+/// Domain prefix of a signed quote message, so a quote signature cannot be
+/// mistaken for anything else the merchant's key signs
+pub const QUOTE_DOMAIN: &[u8] = b"qantara-quote-v1";
+
+/// Merchant-signed payment quote
+/// Signed message = QUOTE_DOMAIN || borsh(PaymentQuote), verified by an ed25519 program
+/// instruction placed directly before settle. `mint` is the native mint for SOL, the USDC
+/// mint for USDC, or Pubkey::default() for a USD-priced quote (amount in micro-USD, settle_usd)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentQuote {
+    pub merchant_id: u64,
+    pub amount: u64,
+    pub mint: Pubkey,
+    pub order_reference: [u8; 32],
+    pub expires_at: i64,
+}
+
+/// Router leg converting the merchant payout into its settlement currency
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PayoutSwap {
+    pub swap_data: Vec<u8>,             // Router instruction data (allowlisted entrypoint)
+    pub min_out: u64,                   // Minimum settlement token output
+    pub account_count: u8,              // Trailing remaining accounts used by this route
+}
+
+/// Router leg swapping the payer's token into USDC (settle_with_swap)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InputSwap {
+    pub swap_data: Vec<u8>,             // Router instruction data (allowlisted entrypoint)
+    pub max_amount_in: u64,             // Most input tokens the payer will spend
+    pub account_count: u8,              // Leading remaining accounts used by this route
+}
+
 /// Events
 #[event]
 pub struct ProtocolInitialized {
@@ -231,6 +2352,288 @@ pub struct MerchantFrozen {
     pub frozen: bool,
 }
 
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub version: u8,
+}
+
+#[event]
+pub struct MerchantMigratedFromV1 {
+    pub merchant_id: u64,
+    pub owner: Pubkey,
+    pub v1_merchant_config: Pubkey,
+}
+
+#[event]
+pub struct MerchantClosed {
+    pub merchant_id: u64,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct HandleClaimed {
+    pub handle: String,
+    pub merchant_id: u64,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct HandleTransferred {
+    pub handle: String,
+    pub from_merchant_id: u64,
+    pub to_merchant_id: u64,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct HandleReleased {
+    pub handle: String,
+    pub merchant_id: u64,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct MerchantMetadataUpdated {
+    pub merchant_id: u64,
+    pub name: String,
+    pub uri: String,
+    pub website: String,
+    pub support_contact: String,
+    pub category_code: u16,
+    pub terms_hash: [u8; 32],
+}
+
+#[event]
+pub struct MerchantLimitsUpdated {
+    pub merchant_id: u64,
+    pub sol: TokenLimits,
+    pub usdc: TokenLimits,
+    pub by_protocol: bool,
+}
+
+#[event]
+pub struct DonationSettingsUpdated {
+    pub merchant_id: u64,
+    pub enabled: bool,
+    pub sol: DonationTerms,
+    pub usdc: DonationTerms,
+}
+
+#[event]
+pub struct TipPolicyUpdated {
+    pub merchant_id: u64,
+    pub tip_wallet: Pubkey,
+    pub fee_on_tips: bool,
+}
+
+#[event]
+pub struct NonprofitVerificationUpdated {
+    pub merchant_id: u64,
+    pub verified: bool,
+}
+
+#[event]
+pub struct DonationReceived {
+    pub merchant_id: u64,
+    pub donor: Pubkey,
+    pub pay_token: PayToken,
+    pub amount: u64,
+    pub fee_waived: bool,
+    pub message: Option<String>,
+}
+
+#[event]
+pub struct PayerListUpdated {
+    pub merchant_id: Option<u64>,
+    pub payer: Pubkey,
+    pub kind: PayerListKind,
+    pub added: bool,
+}
+
+#[event]
+pub struct PaymentAuthorized {
+    pub merchant_id: u64,
+    pub authorization_id: [u8; 32],
+    pub payer: Pubkey,
+    pub pay_token: PayToken,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct AuthorizationCaptured {
+    pub merchant_id: u64,
+    pub authorization_id: [u8; 32],
+    pub amount: u64,
+    pub remainder: u64,
+}
+
+#[event]
+pub struct AuthorizationVoided {
+    pub merchant_id: u64,
+    pub authorization_id: [u8; 32],
+    pub refunded: u64,
+    pub voided_by: Pubkey,
+}
+
+#[event]
+pub struct InstallmentPlanCreated {
+    pub merchant_id: u64,
+    pub plan_id: [u8; 32],
+    pub payer: Pubkey,
+    pub terms: InstallmentTerms,
+}
+
+#[event]
+pub struct InstallmentPaid {
+    pub merchant_id: u64,
+    pub plan_id: [u8; 32],
+    pub installment_index: u16,
+    pub order_reference: [u8; 32],
+    pub paid_count: u16,
+    pub status: InstallmentStatus,
+}
+
+#[event]
+pub struct InstallmentDefaulted {
+    pub merchant_id: u64,
+    pub plan_id: [u8; 32],
+    pub payer: Pubkey,
+    pub installment_index: u16,
+    pub due_at: i64,
+    pub outstanding: u64,
+}
+
+#[event]
+pub struct InstallmentPlanClosed {
+    pub merchant_id: u64,
+    pub plan_id: [u8; 32],
+    pub status: InstallmentStatus,
+    pub paid_count: u16,
+}
+
+#[event]
+pub struct GroupInvoiceCreated {
+    pub merchant_id: u64,
+    pub invoice_id: [u8; 32],
+    pub pay_token: PayToken,
+    pub target_amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct GroupContribution {
+    pub merchant_id: u64,
+    pub invoice_id: [u8; 32],
+    pub contributor: Pubkey,
+    pub amount: u64,
+    pub funded_amount: u64,
+    pub target_amount: u64,
+}
+
+#[event]
+pub struct GroupInvoiceSettled {
+    pub merchant_id: u64,
+    pub invoice_id: [u8; 32],
+    pub amount: u64,
+    pub contributor_count: u32,
+}
+
+#[event]
+pub struct ContributionReclaimed {
+    pub merchant_id: u64,
+    pub invoice_id: [u8; 32],
+    pub contributor: Pubkey,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct GroupInvoiceClosed {
+    pub merchant_id: u64,
+    pub invoice_id: [u8; 32],
+    pub settled: bool,
+}
+
+#[event]
+pub struct PayerAllowlistUpdated {
+    pub merchant_id: u64,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct DayStatsClosed {
+    pub merchant_id: u64,
+    pub day: u32,
+}
+
+#[event]
+pub struct ReceiptClosed {
+    pub merchant_id: u64,
+    pub order_reference: [u8; 32],
+    pub closed_by: Pubkey,
+}
+
+#[event]
+pub struct UsdcMintUpdated {
+    pub usdc_mint: Pubkey,
+}
+
+#[event]
+pub struct BuybackOracleUpdated {
+    pub merchant_id: u64,
+    pub buyback_price_feed: Pubkey,
+    pub slippage_bps_max: u16,
+}
+
+#[event]
+pub struct SettlementMintUpdated {
+    pub merchant_id: u64,
+    pub settlement_mint: Pubkey,
+}
+
+#[event]
+pub struct QuoteSignerUpdated {
+    pub merchant_id: u64,
+    pub quote_signer: Option<Pubkey>,
+}
+
+#[event]
+pub struct OracleConfigured {
+    pub oracle_program: Pubkey,
+    pub sol_price_feed: Pubkey,
+    pub usdc_price_feed: Pubkey,
+    pub max_price_age_secs: u32,
+}
+
+#[event]
+pub struct RouterRegistered {
+    pub program_id: Pubkey,
+    pub discriminators: Vec<[u8; 8]>,
+}
+
+#[event]
+pub struct RouterUpdated {
+    pub program_id: Pubkey,
+    pub discriminators: Vec<[u8; 8]>,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct RouterRemoved {
+    pub program_id: Pubkey,
+}
+
+#[event]
+pub struct PaymentSwappedIn {
+    pub merchant_id: u64,
+    pub payer: Pubkey,
+    pub input_mint: Pubkey,
+    pub amount_in: u64,
+    pub usdc_received: u64,
+    pub refund: u64,
+}
+
 #[event]
 pub struct PaymentSettled {
     pub merchant_id: u64,
@@ -239,8 +2642,300 @@ pub struct PaymentSettled {
     pub pay_token: PayToken,
     pub protocol_fee: u64,
     pub payout_amount: u64,
+    pub payout_token: PayToken,
     pub buyback_amount: u64,
     pub burn_amount: u64,
+    pub usd_conversion: Option<UsdConversion>,
+    pub order_reference: Option<[u8; 32]>,
+    pub memo: Option<String>,
+    pub beneficiary: Option<Pubkey>,
+    pub tip_amount: u64,
+    pub tip_fee: u64,
     pub timestamp: i64,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits_with_sol_cap(daily_cap: u64) -> MerchantLimits {
+        MerchantLimits {
+            version: MERCHANT_LIMITS_VERSION,
+            merchant_id: 1,
+            sol: TokenLimits { min_amount: 0, max_amount: 0, daily_cap },
+            usdc: TokenLimits::default(),
+            protocol_sol: TokenLimits::default(),
+            protocol_usdc: TokenLimits::default(),
+            window_start: 0,
+            volume_sol: RollingVolume::default(),
+            volume_usdc: RollingVolume::default(),
+            bump: 255,
+            reserved: [0; MERCHANT_LIMITS_RESERVED],
+        }
+    }
+
+    #[test]
+    fn rolling_cap_carries_previous_window_proportionally() {
+        let day = LIMIT_WINDOW_SECS;
+        let mut limits = limits_with_sol_cap(1_000);
+
+        limits.enforce(PayToken::Sol, 1_000, 10 * day).unwrap();
+        assert!(limits.enforce(PayToken::Sol, 1, 10 * day + 60).is_err());
+
+        // Half way into the next window, half of the previous volume still counts
+        let half = 10 * day + day + day / 2;
+        assert!(limits.enforce(PayToken::Sol, 501, half).is_err());
+        limits.enforce(PayToken::Sol, 500, half).unwrap();
+
+        // Two windows later nothing carries over
+        limits.enforce(PayToken::Sol, 1_000, 13 * day).unwrap();
+    }
+
+    #[test]
+    fn protocol_limits_are_ceilings() {
+        let merchant = TokenLimits { min_amount: 10, max_amount: 0, daily_cap: 5_000 };
+        let protocol = TokenLimits { min_amount: 0, max_amount: 100, daily_cap: 10_000 };
+        let effective = merchant.tightest(&protocol);
+        assert_eq!(effective.min_amount, 10);
+        assert_eq!(effective.max_amount, 100);
+        assert_eq!(effective.daily_cap, 5_000);
+    }
+
+    fn plan(count: u16) -> InstallmentPlan {
+        InstallmentPlan {
+            version: INSTALLMENT_PLAN_VERSION,
+            merchant_id: 1,
+            plan_id: [0; 32],
+            payer: Pubkey::default(),
+            terms: InstallmentTerms {
+                pay_token: PayToken::Usdc,
+                installment_amount: 100,
+                installment_count: count,
+                first_due_at: 1_000,
+                interval_secs: 100,
+                grace_period_secs: 10,
+            },
+            paid_count: 0,
+            status: InstallmentStatus::Active,
+            last_paid_at: 0,
+            created_at: 0,
+            bump: 0,
+            reserved: [0; INSTALLMENT_PLAN_RESERVED],
+        }
+    }
+
+    #[test]
+    fn installment_terms_bound_count_and_schedule() {
+        let terms = plan(2).terms;
+        terms.validate().unwrap();
+        assert!(InstallmentTerms { installment_count: 1, ..terms }.validate().is_err());
+        assert!(InstallmentTerms { installment_count: MAX_INSTALLMENTS + 1, ..terms }.validate().is_err());
+        assert!(InstallmentTerms { installment_amount: 0, ..terms }.validate().is_err());
+        assert!(InstallmentTerms { interval_secs: 0, ..terms }.validate().is_err());
+        assert!(InstallmentTerms { grace_period_secs: -1, ..terms }.validate().is_err());
+    }
+
+    #[test]
+    fn installment_plan_completes_on_the_last_payment() {
+        let mut plan = plan(2);
+        assert_eq!(plan.due_at(1), 1_100);
+        plan.record_payment(1_000);
+        assert_eq!(plan.status, InstallmentStatus::Active);
+        assert_eq!(plan.last_paid_at, 1_000);
+        plan.record_payment(1_100);
+        assert_eq!(plan.paid_count, 2);
+        assert_eq!(plan.status, InstallmentStatus::Completed);
+    }
+
+    #[test]
+    fn installment_plan_defaults_after_grace_and_recovers_when_caught_up() {
+        let mut plan = plan(3);
+        assert!(!plan.is_overdue(1_010));
+        assert!(plan.is_overdue(1_011));
+
+        plan.status = InstallmentStatus::Defaulted;
+        // Paying the first installment late still leaves the second overdue
+        plan.record_payment(1_120);
+        assert_eq!(plan.status, InstallmentStatus::Defaulted);
+        plan.record_payment(1_120);
+        assert_eq!(plan.status, InstallmentStatus::Active);
+        plan.record_payment(1_130);
+        assert_eq!(plan.status, InstallmentStatus::Completed);
+        assert!(!plan.is_overdue(i64::MAX));
+    }
+
+    fn authorization(amount: u64) -> Authorization {
+        Authorization {
+            version: AUTHORIZATION_VERSION,
+            merchant_id: 1,
+            authorization_id: [0; 32],
+            payer: Pubkey::default(),
+            pay_token: PayToken::Usdc,
+            amount,
+            captured_amount: 0,
+            captured: false,
+            expires_at: 1_000,
+            created_at: 0,
+            bump: 0,
+            reserved: [0; AUTHORIZATION_RESERVED],
+        }
+    }
+
+    #[test]
+    fn partial_capture_leaves_the_remainder_for_void() {
+        let mut auth = authorization(1_000);
+        assert_eq!(auth.remainder(), 1_000);
+        assert!(!auth.voidable_by_anyone(999));
+        assert!(auth.voidable_by_anyone(1_000));
+
+        assert!(auth.check_capture(0, 500).is_err());
+        assert!(auth.check_capture(1_001, 500).is_err());
+        assert!(auth.check_capture(400, 1_000).is_err());
+        auth.check_capture(400, 500).unwrap();
+        assert_eq!(auth.record_capture(400), 600);
+
+        // Captured once: the rest can only be voided, by anyone
+        assert!(auth.check_capture(1, 500).is_err());
+        assert!(auth.voidable_by_anyone(500));
+        assert_eq!(auth.remainder(), 600);
+
+        let mut full = authorization(1_000);
+        assert_eq!(full.record_capture(1_000), 0);
+    }
+
+    fn group_invoice(target_amount: u64) -> GroupInvoice {
+        GroupInvoice {
+            version: GROUP_INVOICE_VERSION,
+            merchant_id: 1,
+            invoice_id: [0; 32],
+            pay_token: PayToken::Sol,
+            target_amount,
+            funded_amount: 0,
+            contributor_count: 0,
+            expires_at: 1_000,
+            settled: false,
+            created_at: 0,
+            bump: 0,
+            reserved: [0; GROUP_INVOICE_RESERVED],
+        }
+    }
+
+    #[test]
+    fn contributions_fill_the_unfunded_remainder() {
+        let mut invoice = group_invoice(1_000);
+
+        assert!(invoice.check_contribution(0, 0).is_err());
+        assert!(invoice.check_contribution(1_001, 0).is_err());
+        invoice.check_contribution(600, 0).unwrap();
+        invoice.record_contribution(600, true).unwrap();
+        invoice.record_contribution(100, false).unwrap();
+        assert_eq!((invoice.funded_amount, invoice.contributor_count), (700, 1));
+
+        assert!(invoice.check_contribution(301, 0).is_err());
+        invoice.check_contribution(300, 0).unwrap();
+        assert!(invoice.check_contribution(300, 1_000).is_err());
+
+        // A corrupt or overfunded invoice fails cleanly instead of wrapping
+        invoice.funded_amount = 1_200;
+        assert!(invoice.check_contribution(1, 0).is_err());
+        invoice.funded_amount = u64::MAX;
+        assert!(invoice.record_contribution(1, false).is_err());
+    }
+
+    #[test]
+    fn expired_group_invoice_refunds_each_contribution() {
+        let mut invoice = group_invoice(1_000);
+        invoice.funded_amount = 700;
+        invoice.contributor_count = 2;
+
+        assert!(invoice.reclaim_refund(400, 999).is_err());
+        let refunded = invoice.reclaim_refund(400, 1_000).unwrap();
+        assert_eq!(refunded, 400);
+        invoice.record_reclaim(refunded);
+        assert_eq!((invoice.funded_amount, invoice.contributor_count), (300, 1));
+
+        invoice.record_reclaim(invoice.reclaim_refund(300, 2_000).unwrap());
+        assert_eq!((invoice.funded_amount, invoice.contributor_count), (0, 0));
+    }
+
+    #[test]
+    fn settled_group_invoice_only_releases_records() {
+        let mut invoice = group_invoice(1_000);
+        invoice.settled = true;
+        invoice.contributor_count = 2;
+
+        // Settling paid the escrow out, so records close early and refund nothing
+        assert_eq!(invoice.reclaim_refund(600, 500).unwrap(), 0);
+        invoice.record_reclaim(0);
+        assert_eq!((invoice.funded_amount, invoice.contributor_count), (0, 1));
+    }
+
+    fn protocol_config(protocol_fee_bps: u16) -> ProtocolConfig {
+        ProtocolConfig {
+            version: PROTOCOL_CONFIG_VERSION,
+            authority: Pubkey::default(),
+            protocol_fee_bps,
+            protocol_wallet: Pubkey::default(),
+            jupiter_router: Pubkey::default(),
+            paused: false,
+            oracle_program: Pubkey::default(),
+            sol_price_feed: Pubkey::default(),
+            usdc_price_feed: Pubkey::default(),
+            max_price_age_secs: 0,
+            bump: 0,
+            denied_payers: 0,
+            usdc_mint: Pubkey::default(),
+            reserved: [0; PROTOCOL_CONFIG_RESERVED],
+        }
+    }
+
+    #[test]
+    fn verified_nonprofit_donations_pay_no_protocol_fee() {
+        let config = protocol_config(100);
+        assert_eq!(config.fee_on(1_000_000, false).unwrap(), 10_000);
+        assert_eq!(config.fee_on(1_000_000, true).unwrap(), 0);
+        // Rounds down, and never overflows on the largest amount
+        assert_eq!(config.fee_on(99, false).unwrap(), 0);
+        assert_eq!(config.fee_on(u64::MAX, false).unwrap(), u64::MAX / 100);
+    }
+
+    #[test]
+    fn donation_terms_enforce_the_minimum() {
+        let terms = DonationTerms { min_amount: 500, suggested: [500, 1_000, 0, 0] };
+        terms.validate().unwrap();
+        assert!(!terms.accepts(499));
+        assert!(terms.accepts(500));
+        assert!(!DonationTerms::default().accepts(0));
+        assert!(DonationTerms::default().accepts(1));
+        assert!(DonationTerms { min_amount: 500, suggested: [499, 0, 0, 0] }.validate().is_err());
+    }
+
+    #[test]
+    fn tip_fee_follows_the_merchant_policy() {
+        let config = protocol_config(100);
+        let mut policy = TipPolicy {
+            version: TIP_POLICY_VERSION,
+            merchant_id: 1,
+            tip_wallet: Pubkey::new_unique(),
+            fee_on_tips: false,
+            bump: 0,
+            reserved: [0; TIP_POLICY_RESERVED],
+        };
+        let tip_fee = |policy: &TipPolicy, fee_waived: bool| {
+            config.fee_on(10_000, !policy.fee_applies(fee_waived)).unwrap()
+        };
+
+        assert_eq!(tip_fee(&policy, false), 0);
+        policy.fee_on_tips = true;
+        assert_eq!(tip_fee(&policy, false), 100);
+        // A fee-waived settlement waives the tip fee too
+        assert_eq!(tip_fee(&policy, true), 0);
+    }
+
+    #[test]
+    fn protocol_stats_shards_cover_the_range() {
+        assert_eq!(ProtocolStats::shard_of(0), 0);
+        assert_eq!(ProtocolStats::shard_of(PROTOCOL_STATS_SHARDS as u64 + 3), 3);
+        assert_eq!(ProtocolStats::shard_of(u64::MAX), PROTOCOL_STATS_SHARDS - 1);
+    }
+}
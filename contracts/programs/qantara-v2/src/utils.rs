@@ -2,7 +2,390 @@
 // Most logic is in lib.rs for clarity
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::system_program;
+use anchor_spl::token::{spl_token::native_mint, Mint, TokenAccount};
 
-// Placeholder for future utility functions
-// Currently all logic is in the main program file
+use crate::errors::QantaraError;
+use crate::state::*;
 
+/// Domain separator for merchant_id derivation
+pub const MERCHANT_ID_DOMAIN: &[u8] = b"qantara:merchant_id";
+
+/// Derive a merchant_id from its owner and a nonce: first 8 bytes (LE) of
+/// sha256(domain || owner || nonce_le). IDs are bound to the owner's key, so
+/// nobody else can claim them ahead of the owner.
+pub fn derive_merchant_id(owner: &Pubkey, nonce: u64) -> u64 {
+    let hash = hashv(&[MERCHANT_ID_DOMAIN, owner.as_ref(), &nonce.to_le_bytes()]);
+    u64::from_le_bytes(hash.to_bytes()[..8].try_into().unwrap())
+}
+
+/// Domain separator for installment order references
+pub const INSTALLMENT_REFERENCE_DOMAIN: &[u8] = b"qantara:installment";
+
+/// Order reference of installment `index` of a plan:
+/// sha256(domain || plan_id || index_le), so every installment can carry its own receipt
+pub fn installment_reference(plan_id: &[u8; 32], index: u16) -> [u8; 32] {
+    hashv(&[INSTALLMENT_REFERENCE_DOMAIN, plan_id, &index.to_le_bytes()]).to_bytes()
+}
+
+/// Seconds in a UTC day (day buckets ignore leap seconds, like unix time)
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// UTC day index of a unix timestamp (days since the epoch)
+pub fn day_index(unix_timestamp: i64) -> u32 {
+    (unix_timestamp.max(0) / SECONDS_PER_DAY) as u32
+}
+
+/// Read the quote signed by `signer` from the ed25519 program instruction directly before
+/// the current one. The ed25519 program has already verified the signature (the transaction
+/// fails otherwise); this checks that it covers `signer` and a quote message.
+pub fn load_signed_quote(instructions: &AccountInfo, signer: &Pubkey) -> Result<PaymentQuote> {
+    let current = load_current_index_checked(instructions)? as usize;
+    require!(current > 0, QantaraError::InvalidQuoteSignature);
+    let ix = load_instruction_at_checked(current - 1, instructions)?;
+    require_keys_eq!(ix.program_id, ed25519_program::ID, QantaraError::InvalidQuoteSignature);
+
+    // Header: signature count, padding, then one Ed25519SignatureOffsets (7 x u16 LE):
+    // signature offset/ix, public key offset/ix, message offset/size/ix
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, QantaraError::InvalidQuoteSignature);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    // Signature, key and message must live in the ed25519 instruction itself
+    require!(
+        read_u16(4) == u16::MAX && read_u16(8) == u16::MAX && read_u16(14) == u16::MAX,
+        QantaraError::InvalidQuoteSignature
+    );
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(QantaraError::InvalidQuoteSignature)?;
+    require!(public_key == signer.as_ref(), QantaraError::InvalidQuoteSignature);
+
+    let message = data
+        .get(message_offset..message_offset + message_size)
+        .and_then(|message| message.strip_prefix(QUOTE_DOMAIN))
+        .ok_or(QantaraError::InvalidQuoteSignature)?;
+    PaymentQuote::try_from_slice(message).map_err(|_| error!(QantaraError::InvalidQuoteSignature))
+}
+
+/// Kind of a payer list entry, or None if the (PDA-checked) account was never created
+pub fn payer_list_kind(entry: &AccountInfo) -> Result<Option<PayerListKind>> {
+    if entry.owner != &crate::ID || entry.data_is_empty() {
+        return Ok(None);
+    }
+    let data = entry.try_borrow_data()?;
+    let entry = PayerListEntry::try_deserialize(&mut &data[..])?;
+    Ok(Some(entry.kind))
+}
+
+/// Check that a handle is already in normalized form: lowercase a-z, 0-9 and
+/// '-', without a leading or trailing '-'. Clients lowercase and trim before
+/// claiming, so "Acme-Coffee" and "acme-coffee" cannot become two handles.
+pub fn validate_handle(handle: &str) -> Result<()> {
+    let bytes = handle.as_bytes();
+    require!(
+        (MIN_HANDLE_LEN..=MAX_HANDLE_LEN).contains(&bytes.len())
+            && bytes
+                .iter()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+            && bytes[0] != b'-'
+            && bytes[bytes.len() - 1] != b'-',
+        QantaraError::InvalidHandle
+    );
+    Ok(())
+}
+
+/// Validate merchant metadata against the size limits and allowed URI schemes
+/// Empty optional fields (uri, website, support_contact) are allowed
+pub fn validate_metadata(args: &MerchantMetadataArgs) -> Result<()> {
+    let is_clean = |s: &str| !s.chars().any(char::is_control);
+
+    require!(
+        !args.name.is_empty()
+            && args.name.len() <= MAX_METADATA_NAME_LEN
+            && args.name.trim() == args.name
+            && is_clean(&args.name),
+        QantaraError::InvalidMetadataName
+    );
+
+    for (field, max_len) in [
+        (&args.uri, MAX_METADATA_URI_LEN),
+        (&args.website, MAX_METADATA_WEBSITE_LEN),
+        (&args.support_contact, MAX_METADATA_CONTACT_LEN),
+    ] {
+        require!(
+            field.len() <= max_len && is_clean(field),
+            QantaraError::InvalidMetadataField
+        );
+    }
+
+    require!(
+        args.uri.is_empty()
+            || ["https://", "ipfs://", "ar://"]
+                .iter()
+                .any(|scheme| args.uri.starts_with(scheme)),
+        QantaraError::InvalidMetadataUri
+    );
+    require!(
+        args.website.is_empty() || args.website.starts_with("https://"),
+        QantaraError::InvalidMetadataUri
+    );
+
+    require!(
+        args.category_code <= MAX_CATEGORY_CODE,
+        QantaraError::InvalidCategoryCode
+    );
+
+    Ok(())
+}
+
+/// Create a program-owned PDA of `space` bytes, funded by `payer`
+/// Works when the address was pre-funded (a plain create_account would fail)
+pub fn create_pda_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let top_up = rent.saturating_sub(account.lamports());
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+
+    let signer = &[signer_seeds];
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Allocate {
+                account_to_allocate: account.clone(),
+            },
+            signer,
+        ),
+        space as u64,
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::Assign {
+                account_to_assign: account.clone(),
+            },
+            signer,
+        ),
+        &crate::ID,
+    )?;
+    Ok(())
+}
+
+/// Close a program-owned PDA if it was ever created, returning its lamports to `destination`
+/// (no-op for an address that holds no account of this program)
+pub fn close_if_initialized<'info>(
+    account: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    if account.owner != &crate::ID {
+        return Ok(());
+    }
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.assign(&system_program::ID);
+    account.resize(0)?;
+    Ok(())
+}
+
+/// Read the current amount of an SPL token account straight from its data
+/// (bypasses Anchor's cached deserialization, so it reflects CPI side effects)
+pub fn read_token_amount(account: &AccountInfo) -> Result<u64> {
+    let data = account.try_borrow_data()?;
+    let token_account = TokenAccount::try_deserialize(&mut &data[..])?;
+    Ok(token_account.amount)
+}
+
+/// Read the decimals of an SPL mint passed as an unchecked account
+pub fn read_mint_decimals(mint: &AccountInfo) -> Result<u8> {
+    let data = mint.try_borrow_data()?;
+    let mint = Mint::try_deserialize(&mut &data[..])?;
+    Ok(mint.decimals)
+}
+
+/// Resolve a merchant's settlement mint preference to a vault token
+/// `Pubkey::default()` means the merchant is paid in whatever token came in
+pub fn resolve_settlement_token(
+    settlement_mint: &Pubkey,
+    usdc_mint: &Pubkey,
+) -> Result<Option<PayToken>> {
+    if *settlement_mint == Pubkey::default() {
+        Ok(None)
+    } else if *settlement_mint == native_mint::ID {
+        Ok(Some(PayToken::Sol))
+    } else if settlement_mint == usdc_mint {
+        Ok(Some(PayToken::Usdc))
+    } else {
+        err!(QantaraError::UnsupportedSettlementMint)
+    }
+}
+
+/// Balances of the shared vaults, captured around the router CPI
+#[derive(Clone, Copy)]
+pub struct VaultSnapshot {
+    pub sol_lamports: u64,
+    pub usdc_amount: u64,
+    pub buyback_amount: u64,
+}
+
+impl VaultSnapshot {
+    pub fn capture(
+        vault_sol: &AccountInfo,
+        vault_usdc: &AccountInfo,
+        vault_buyback_token: &AccountInfo,
+    ) -> Result<Self> {
+        Ok(Self {
+            sol_lamports: vault_sol.lamports(),
+            usdc_amount: read_token_amount(vault_usdc)?,
+            buyback_amount: read_token_amount(vault_buyback_token)?,
+        })
+    }
+
+    /// Verify that the swap only spent what it was allowed to spend.
+    ///
+    /// vault_sol and vault_usdc are shared by every merchant, so the vault
+    /// holding the payment may decrease by at most `max_spend`, the other
+    /// vault may not decrease at all (USDC → SOL → buyback routes must net
+    /// out), and the buyback vault may only grow.
+    pub fn verify_conservation(
+        &self,
+        before: &VaultSnapshot,
+        pay_token: PayToken,
+        max_spend: u64,
+    ) -> Result<()> {
+        let sol_spent = before.sol_lamports.saturating_sub(self.sol_lamports);
+        let usdc_spent = before.usdc_amount.saturating_sub(self.usdc_amount);
+
+        let (sol_allowance, usdc_allowance) = match pay_token {
+            PayToken::Sol => (max_spend, 0),
+            PayToken::Usdc => (0, max_spend),
+        };
+
+        require!(
+            sol_spent <= sol_allowance,
+            QantaraError::VaultConservationViolated
+        );
+        require!(
+            usdc_spent <= usdc_allowance,
+            QantaraError::VaultConservationViolated
+        );
+        require!(
+            self.buyback_amount >= before.buyback_amount,
+            QantaraError::VaultConservationViolated
+        );
+
+        Ok(())
+    }
+
+    /// Amount of `token` the vaults gained since `before`
+    pub fn received(&self, before: &VaultSnapshot, token: PayToken) -> Result<u64> {
+        let (after, before) = match token {
+            PayToken::Sol => (self.sol_lamports, before.sol_lamports),
+            PayToken::Usdc => (self.usdc_amount, before.usdc_amount),
+        };
+        after
+            .checked_sub(before)
+            .ok_or(QantaraError::VaultConservationViolated.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(sol_lamports: u64, usdc_amount: u64, buyback_amount: u64) -> VaultSnapshot {
+        VaultSnapshot { sol_lamports, usdc_amount, buyback_amount }
+    }
+
+    fn assert_violation(result: Result<()>) {
+        assert_eq!(result.unwrap_err(), QantaraError::VaultConservationViolated.into());
+    }
+
+    #[test]
+    fn sol_vault_may_spend_up_to_its_allowance() {
+        let before = snapshot(10_000, 5_000, 0);
+        snapshot(9_000, 5_000, 7).verify_conservation(&before, PayToken::Sol, 1_000).unwrap();
+        assert_violation(snapshot(8_999, 5_000, 7).verify_conservation(&before, PayToken::Sol, 1_000));
+    }
+
+    #[test]
+    fn usdc_vault_may_spend_up_to_its_allowance() {
+        let before = snapshot(10_000, 5_000, 0);
+        snapshot(10_000, 4_000, 7).verify_conservation(&before, PayToken::Usdc, 1_000).unwrap();
+        assert_violation(snapshot(10_000, 3_999, 7).verify_conservation(&before, PayToken::Usdc, 1_000));
+    }
+
+    #[test]
+    fn vault_that_was_not_the_swap_input_may_not_decrease() {
+        let before = snapshot(10_000, 5_000, 0);
+        // USDC payment: SOL may pass through the vault but must net out
+        snapshot(10_000, 4_000, 7).verify_conservation(&before, PayToken::Usdc, 1_000).unwrap();
+        assert_violation(snapshot(9_999, 4_000, 7).verify_conservation(&before, PayToken::Usdc, 1_000));
+        // SOL payment: USDC is untouchable
+        assert_violation(snapshot(9_000, 4_999, 7).verify_conservation(&before, PayToken::Sol, 1_000));
+        // Payer-funded swaps get no allowance at all
+        assert_violation(snapshot(10_000, 4_999, 0).verify_conservation(&before, PayToken::Usdc, 0));
+    }
+
+    #[test]
+    fn buyback_vault_may_not_decrease() {
+        let before = snapshot(10_000, 5_000, 100);
+        snapshot(9_000, 5_000, 100).verify_conservation(&before, PayToken::Sol, 1_000).unwrap();
+        assert_violation(snapshot(9_000, 5_000, 99).verify_conservation(&before, PayToken::Sol, 1_000));
+    }
+
+    #[test]
+    fn settlement_mint_must_be_sol_or_the_protocol_usdc() {
+        let usdc = Pubkey::new_unique();
+        assert_eq!(resolve_settlement_token(&Pubkey::default(), &usdc).unwrap(), None);
+        assert_eq!(resolve_settlement_token(&native_mint::ID, &usdc).unwrap(), Some(PayToken::Sol));
+        assert_eq!(resolve_settlement_token(&usdc, &usdc).unwrap(), Some(PayToken::Usdc));
+        assert_eq!(
+            resolve_settlement_token(&Pubkey::new_unique(), &usdc).unwrap_err(),
+            QantaraError::UnsupportedSettlementMint.into()
+        );
+    }
+
+    #[test]
+    fn received_rejects_a_vault_that_shrank() {
+        let before = snapshot(10_000, 5_000, 0);
+        assert_eq!(snapshot(10_000, 5_250, 0).received(&before, PayToken::Usdc).unwrap(), 250);
+        assert_violation(snapshot(9_999, 5_000, 0).received(&before, PayToken::Sol).map(|_| ()));
+    }
+
+    #[test]
+    fn installment_references_are_unique_per_plan_and_index() {
+        let plan_a = [1u8; 32];
+        let plan_b = [2u8; 32];
+        assert_eq!(installment_reference(&plan_a, 0), installment_reference(&plan_a, 0));
+        assert_ne!(installment_reference(&plan_a, 0), installment_reference(&plan_a, 1));
+        assert_ne!(installment_reference(&plan_a, 0), installment_reference(&plan_b, 0));
+    }
+}
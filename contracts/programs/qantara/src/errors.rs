@@ -20,5 +20,9 @@ pub enum QantaraError {
     InvalidPayoutWallet,
     #[msg("Invalid payer account")]
     InvalidPayerAccount,
+    #[msg("Buyback vault must be empty before the merchant can be closed")]
+    BuybackVaultNotEmpty,
+    #[msg("Merchant has migrated to qantara-v2 and cannot be unfrozen or re-created")]
+    MerchantMigrated,
 }
 
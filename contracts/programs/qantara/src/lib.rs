@@ -17,8 +17,12 @@ pub mod qantara {
     use super::*;
 
     /// Initialize a merchant configuration
-    /// Authority: Merchant owner must sign
+    /// Authority: Merchant owner must sign; owners who migrated to qantara-v2 cannot re-create it
     pub fn init_merchant(ctx: Context<InitMerchant>, args: InitMerchantArgs) -> Result<()> {
+        require!(
+            ctx.accounts.migration_record.data_is_empty(),
+            QantaraError::MerchantMigrated
+        );
         require!(
             args.payout_bps
                 .checked_add(args.buyback_bps)
@@ -93,6 +97,10 @@ pub mod qantara {
         }
 
         if let Some(frozen) = args.frozen {
+            require!(
+                frozen || ctx.accounts.migration_record.data_is_empty(),
+                QantaraError::MerchantMigrated
+            );
             config.frozen = frozen;
             emit!(MerchantFrozen {
                 owner: config.owner,
@@ -103,6 +111,60 @@ pub mod qantara {
         Ok(())
     }
 
+    /// Mark merchant as migrated to qantara-v2 and stop accepting payments
+    /// Authority: Current owner must sign (directly or through qantara-v2 migrate_from_v1)
+    pub fn mark_migrated(ctx: Context<MarkMigrated>, v2_merchant_id: u64) -> Result<()> {
+        let config = &mut ctx.accounts.merchant_config;
+        config.frozen = true;
+
+        let record = &mut ctx.accounts.migration_record;
+        record.owner = config.owner;
+        record.v2_merchant_id = v2_merchant_id;
+        record.migrated_at = Clock::get()?.unix_timestamp;
+        record.bump = ctx.bumps.migration_record;
+
+        emit!(MerchantFrozen {
+            owner: config.owner,
+            frozen: true,
+        });
+        emit!(MerchantMigrated {
+            owner: config.owner,
+            v2_merchant_id,
+        });
+
+        Ok(())
+    }
+
+    /// Close merchant configuration and its buyback vault
+    /// Authority: Current owner must sign; rent is returned to the owner
+    pub fn close_merchant(ctx: Context<CloseMerchant>) -> Result<()> {
+        let config = &ctx.accounts.merchant_config;
+        require!(
+            ctx.accounts.vault_buyback_token.amount == 0,
+            QantaraError::BuybackVaultNotEmpty
+        );
+
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.vault_buyback_token.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: config.to_account_info(),
+            },
+            &[&[
+                b"merchant",
+                config.owner.as_ref(),
+                &[config.bump],
+            ]],
+        ))?;
+
+        emit!(MerchantClosed {
+            owner: config.owner,
+        });
+
+        Ok(())
+    }
+
     /// Execute payment settlement
     /// 1. Receive payment (SOL or USDC)
     /// 2. Split: payout + buyback amounts
@@ -19,6 +19,17 @@ pub struct MerchantConfig {
     pub bump: u8,                       // 1
 }
 
+/// Record that a merchant moved to qantara-v2 (PDA: ["migrated", owner])
+/// Its existence keeps the v1 merchant frozen
+#[account]
+#[derive(InitSpace)]
+pub struct MigrationRecord {
+    pub owner: Pubkey,                  // 32
+    pub v2_merchant_id: u64,            // 8
+    pub migrated_at: i64,               // 8
+    pub bump: u8,                       // 1
+}
+
 /// Initialize merchant context
 #[derive(Accounts)]
 pub struct InitMerchant<'info> {
@@ -31,6 +42,14 @@ pub struct InitMerchant<'info> {
     )]
     pub merchant_config: Account<'info, MerchantConfig>,
 
+    /// CHECK: Migration record PDA (may be uninitialized); a migrated owner cannot re-create
+    /// a v1 merchant
+    #[account(
+        seeds = [b"migrated", owner.key().as_ref()],
+        bump
+    )]
+    pub migration_record: UncheckedAccount<'info>,
+
     #[account(mut)]
     pub owner: Signer<'info>,
 
@@ -48,9 +67,67 @@ pub struct UpdateMerchant<'info> {
     )]
     pub merchant_config: Account<'info, MerchantConfig>,
 
+    /// CHECK: Migration record PDA (may be uninitialized); blocks unfreezing once it exists
+    #[account(
+        seeds = [b"migrated", owner.key().as_ref()],
+        bump
+    )]
+    pub migration_record: UncheckedAccount<'info>,
+
     pub owner: Signer<'info>,
 }
 
+/// Mark merchant migrated context (called directly or via CPI from qantara-v2)
+#[derive(Accounts)]
+pub struct MarkMigrated<'info> {
+    #[account(
+        mut,
+        seeds = [b"merchant", owner.key().as_ref()],
+        bump = merchant_config.bump,
+        has_one = owner
+    )]
+    pub merchant_config: Account<'info, MerchantConfig>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + MigrationRecord::INIT_SPACE,
+        seeds = [b"migrated", owner.key().as_ref()],
+        bump
+    )]
+    pub migration_record: Account<'info, MigrationRecord>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Close merchant context
+#[derive(Accounts)]
+pub struct CloseMerchant<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"merchant", owner.key().as_ref()],
+        bump = merchant_config.bump,
+        has_one = owner
+    )]
+    pub merchant_config: Account<'info, MerchantConfig>,
+
+    #[account(
+        mut,
+        token::mint = merchant_config.buyback_mint,
+        token::authority = merchant_config
+    )]
+    pub vault_buyback_token: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
 /// Payment settlement context
 #[derive(Accounts)]
 pub struct Settle<'info> {
@@ -153,6 +230,17 @@ pub struct MerchantFrozen {
     pub frozen: bool,
 }
 
+#[event]
+pub struct MerchantMigrated {
+    pub owner: Pubkey,
+    pub v2_merchant_id: u64,
+}
+
+#[event]
+pub struct MerchantClosed {
+    pub owner: Pubkey,
+}
+
 #[event]
 pub struct PaymentSettled {
     pub merchant: Pubkey,
@@ -1,7 +1,7 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL, Transaction } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID, getAssociatedTokenAddress, createMint, createAccount, mintTo, createAssociatedTokenAccountInstruction, getAssociatedTokenAddressSync, ASSOCIATED_TOKEN_PROGRAM_ID, setAuthority, AuthorityType } from "@solana/spl-token";
+import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL, Transaction, Ed25519Program, SYSVAR_INSTRUCTIONS_PUBKEY, TransactionInstruction } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, getAssociatedTokenAddress, createMint, createAccount, mintTo, createAssociatedTokenAccountInstruction, getAssociatedTokenAddressSync, ASSOCIATED_TOKEN_PROGRAM_ID, setAuthority, AuthorityType, approve } from "@solana/spl-token";
 import { expect } from "chai";
 import { readFileSync } from "fs";
 import { join } from "path";
@@ -388,11 +388,115 @@ describe("Qantara V2 - End-to-End Tests", () => {
   // PDAs
   let protocolConfigPDA: PublicKey;
   let merchantRegistryPDA: PublicKey;
+  let jupiterRouterPDA: PublicKey;
   let merchantId: anchor.BN;
+  let merchantNonce: anchor.BN;
 
   // Constants
   const PROTOCOL_FEE_BPS = 100; // 1%
   const JUPITER_ROUTER = new PublicKey("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4"); // Jupiter v6 devnet
+  const MOCK_ORACLE = new PublicKey("AGYA1ewTyTPJ1ekV1gN8bmtTigk6r3SgFRHXr4WkcT3H"); // Pyth-layout mock
+  const QANTARA_V1 = new PublicKey("3oM4F5kVGXxid46LavUEjY3d3fREeyEm5dTKDX6ZRreU"); // v1 program
+
+  // merchant_id = sha256("qantara:merchant_id" || owner || nonce_le)[0..8] as u64 LE
+  const deriveMerchantId = (owner: PublicKey, nonce: anchor.BN): anchor.BN =>
+    new anchor.BN(
+      createHash("sha256")
+        .update(Buffer.concat([Buffer.from("qantara:merchant_id"), owner.toBuffer(), nonce.toArrayLike(Buffer, "le", 8)]))
+        .digest()
+        .slice(0, 8),
+      "le"
+    );
+
+  // Merchant's day bucket: seeds ["day_stats", merchant_id, day], day = UTC days since epoch
+  const currentDay = () => Math.floor(Date.now() / 1000 / 86400);
+  const getDayStatsPDA = (merchant: anchor.BN, day: number = currentDay()): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("day_stats"), merchant.toArrayLike(Buffer, "le", 8), new anchor.BN(day).toArrayLike(Buffer, "le", 4)],
+      programId
+    )[0];
+
+  // Protocol stats are sharded by merchant_id % PROTOCOL_STATS_SHARDS
+  const PROTOCOL_STATS_SHARDS = 16;
+  const getProtocolStatsPDA = (shard: number): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from("stats"), Buffer.from([shard])], programId)[0];
+  const getMerchantStatsShardPDA = (merchant: anchor.BN): PublicKey =>
+    getProtocolStatsPDA(merchant.modn(PROTOCOL_STATS_SHARDS));
+
+  // Accounts of a settlement of the test merchant paid by the buyer; each test passes its
+  // buyback vault and overrides only the accounts it exercises
+  const settleAccounts = (
+    vaultBuybackToken: PublicKey,
+    overrides: Record<string, PublicKey | null> = {}
+  ) => ({
+    protocolConfig: protocolConfigPDA,
+    merchantRegistry: merchantRegistryPDA,
+    protocolStats: getMerchantStatsShardPDA(merchantId),
+    payer: buyer.publicKey,
+    merchantDayStats: null,
+    merchantLimits: null,
+    protocolPayerEntry: null,
+    merchantPayerEntry: null,
+    instructionsSysvar: null,
+    sponsor: null,
+    authorization: null,
+    escrowUsdc: null,
+    installmentPlan: null,
+    groupInvoice: null,
+    donationSettings: null,
+    tipPolicy: null,
+    tipWallet: null,
+    tipWalletUsdc: null,
+    vaultSol: PublicKey.findProgramAddressSync([Buffer.from("vault"), Buffer.from("sol")], programId)[0],
+    vaultUsdc: PublicKey.findProgramAddressSync([Buffer.from("vault_usdc"), usdcMint.toBuffer()], programId)[0],
+    usdcMint,
+    vaultBuybackToken,
+    buybackMint,
+    protocolWallet: protocolWallet.publicKey,
+    protocolWalletUsdc: getAssociatedTokenAddressSync(usdcMint, protocolWallet.publicKey),
+    merchantPayoutWallet: merchantPayoutWallet.publicKey,
+    merchantPayoutUsdc: getAssociatedTokenAddressSync(usdcMint, merchantPayoutWallet.publicKey),
+    payerUsdcAccount: getAssociatedTokenAddressSync(usdcMint, buyer.publicKey),
+    merchantHandle: null,
+    reference: null,
+    receipt: null,
+    payerInputToken: null,
+    jupiterRouter: JUPITER_ROUTER,
+    swapRouter: jupiterRouterPDA,
+    payPriceFeed: null,
+    buybackPriceFeed: null,
+    settlementPriceFeed: null,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId,
+    ...overrides,
+  });
+
+  // Split and optional inputs of a settlement: full payout, no buyback, no extras unless
+  // the test overrides them
+  const settleArgs = (overrides: Record<string, unknown> = {}) => ({
+    minOut: new anchor.BN(0),
+    payoutBps: new anchor.BN(10000),
+    buybackBps: new anchor.BN(0),
+    burnOfBuybackBps: new anchor.BN(0),
+    swapData: Buffer.alloc(0),
+    payoutSwap: null,
+    orderReference: null,
+    memo: null,
+    tip: null,
+    ...overrides,
+  });
+
+  // Anchor instruction discriminator: sha256("global:<name>")[0..8]
+  const ixDiscriminator = (name: string): number[] =>
+    Array.from(createHash("sha256").update(`global:${name}`).digest().slice(0, 8));
+
+  // Jupiter v6 swap entrypoints allowed by the router registry
+  const JUPITER_SWAP_DISCRIMINATORS = [
+    "route",
+    "shared_accounts_route",
+    "exact_out_route",
+    "shared_accounts_exact_out_route",
+  ].map(ixDiscriminator);
 
   before(async () => {
     // Set environment variables if not already set
@@ -483,17 +587,9 @@ describe("Qantara V2 - End-to-End Tests", () => {
       9 // 9 decimals
     );
 
-    // Generate merchant_id (hash-based)
-    const hash = createHash("sha256")
-      .update(
-        Buffer.from(
-          merchantOwner.publicKey.toBase58() +
-          Date.now().toString() +
-          Math.random().toString()
-        )
-      )
-      .digest();
-    merchantId = new anchor.BN(hash.slice(0, 8), "le");
+    // Derive merchant_id from owner + nonce (verified on-chain)
+    merchantNonce = new anchor.BN(Date.now());
+    merchantId = deriveMerchantId(merchantOwner.publicKey, merchantNonce);
 
     // Derive PDAs
     [protocolConfigPDA] = PublicKey.findProgramAddressSync(
@@ -505,6 +601,12 @@ describe("Qantara V2 - End-to-End Tests", () => {
       [Buffer.from("merchant"), merchantId.toArrayLike(Buffer, "le", 8)],
       programId
     );
+
+    [jupiterRouterPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("router"), JUPITER_ROUTER.toBuffer()],
+      programId
+    );
+
   });
 
   describe("Protocol Initialization", () => {
@@ -544,6 +646,115 @@ describe("Qantara V2 - End-to-End Tests", () => {
         }
       }
     });
+
+    it("Registers Jupiter in the router registry", async () => {
+      try {
+        await program.methods
+          .addRouter(JUPITER_ROUTER, JUPITER_SWAP_DISCRIMINATORS)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            swapRouter: jupiterRouterPDA,
+            authority: protocolAuthority.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([protocolAuthority])
+          .rpc();
+      } catch (err) {
+        // Router might already be registered from a previous run
+        if (err.message && err.message.includes("already in use")) {
+          console.log("ℹ️  Jupiter router already registered");
+        } else {
+          throw err;
+        }
+      }
+
+      const router = await program.account.swapRouter.fetch(jupiterRouterPDA);
+      expect(router.programId.toString()).to.equal(JUPITER_ROUTER.toString());
+      expect(router.enabled).to.be.true;
+      expect(router.discriminators.length).to.equal(JUPITER_SWAP_DISCRIMINATORS.length);
+    });
+
+    it("Sets the protocol USDC mint", async () => {
+      await program.methods
+        .setUsdcMint(usdcMint)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          authority: protocolAuthority.publicKey,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+      const config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.usdcMint.toString()).to.equal(usdcMint.toString());
+    });
+
+    it("Initializes every protocol settlement stats shard", async () => {
+      for (let shard = 0; shard < PROTOCOL_STATS_SHARDS; shard++) {
+        try {
+          await program.methods
+            .initProtocolStats(shard)
+            .accounts({
+              protocolConfig: protocolConfigPDA,
+              protocolStats: getProtocolStatsPDA(shard),
+              authority: protocolAuthority.publicKey,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([protocolAuthority])
+            .rpc();
+          const stats = await program.account.protocolStats.fetch(getProtocolStatsPDA(shard));
+          expect(stats.shard).to.equal(shard);
+          expect(stats.paymentCount.toNumber()).to.equal(0);
+        } catch (err) {
+          // Stats might already exist from a previous run
+          if (err.message && err.message.includes("already in use")) {
+            console.log(`ℹ️  Protocol stats shard ${shard} already initialized`);
+          } else {
+            throw err;
+          }
+        }
+      }
+    });
+
+    it("Fails to initialize a protocol stats shard out of range", async () => {
+      try {
+        await program.methods
+          .initProtocolStats(PROTOCOL_STATS_SHARDS)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            protocolStats: getProtocolStatsPDA(PROTOCOL_STATS_SHARDS),
+            authority: protocolAuthority.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([protocolAuthority])
+          .rpc();
+        expect.fail("Should have failed with InvalidStatsShard");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("InvalidStatsShard") ||
+          errorMsg.includes("Protocol stats shard out of range")
+        ).to.be.true;
+      }
+    });
+
+    it("Fails to initialize protocol stats as a non-authority", async () => {
+      try {
+        await program.methods
+          .initProtocolStats(0)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            protocolStats: getProtocolStatsPDA(0),
+            authority: buyer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have failed: only the protocol authority may create stats");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("has_one") || errorMsg.includes("ConstraintHasOne")).to.be.true;
+      }
+    });
   });
 
   describe("Merchant Registration", () => {
@@ -551,6 +762,7 @@ describe("Qantara V2 - End-to-End Tests", () => {
       const tx = await program.methods
         .registerMerchant(
           merchantId,
+          merchantNonce,
           merchantPayoutWallet.publicKey,
           buybackMint
         )
@@ -571,6 +783,11 @@ describe("Qantara V2 - End-to-End Tests", () => {
       expect(merchant.payoutWallet.toString()).to.equal(merchantPayoutWallet.publicKey.toString());
       expect(merchant.buybackMint.toString()).to.equal(buybackMint.toString());
       expect(merchant.frozen).to.be.false;
+      expect(merchant.payoutBps).to.equal(10000);
+      expect(merchant.buybackBps).to.equal(0);
+      expect(merchant.burnOfBuybackBps).to.equal(0);
+      expect(merchant.paymentCount.toNumber()).to.equal(0);
+      expect(merchant.volumeSol.toNumber()).to.equal(0);
     });
 
     it("Fails to register with wrong merchant_id", async () => {
@@ -584,6 +801,7 @@ describe("Qantara V2 - End-to-End Tests", () => {
         await program.methods
           .registerMerchant(
             wrongMerchantId,
+            merchantNonce,
             merchantPayoutWallet.publicKey,
             buybackMint
           )
@@ -600,10 +818,37 @@ describe("Qantara V2 - End-to-End Tests", () => {
         expect(err.message).to.include("A seeds constraint was violated");
       }
     });
+
+    it("Fails to register a merchant_id derived from another owner (squatting)", async () => {
+      // Attacker reuses the victim's next ID with the correct PDA but signs as themselves
+      const victimNonce = merchantNonce.addn(100);
+      const victimId = deriveMerchantId(merchantOwner.publicKey, victimNonce);
+      const [victimPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("merchant"), victimId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+
+      try {
+        await program.methods
+          .registerMerchant(victimId, victimNonce, buyer.publicKey, buybackMint)
+          .accounts({
+            merchantRegistry: victimPDA,
+            owner: buyer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([buyer])
+          .rpc();
+
+        expect.fail("Should have failed with InvalidMerchantId");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("InvalidMerchantId")).to.be.true;
+        console.log("✅ merchant_id squatting rejected");
+      }
+    });
   });
 
   describe("Payment Settlement - Security Tests", () => {
-    let vaultSolPDA: PublicKey;
     let vaultUsdcPDA: PublicKey;
     let vaultBuybackToken: PublicKey;
     let buyerUsdcAccount: PublicKey;
@@ -612,10 +857,6 @@ describe("Qantara V2 - End-to-End Tests", () => {
 
     before(async () => {
       // Derive vault PDAs
-      [vaultSolPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("vault"), Buffer.from("sol")],
-        programId
-      );
 
       [vaultUsdcPDA] = PublicKey.findProgramAddressSync(
         [Buffer.from("vault_usdc"), usdcMint.toBuffer()],
@@ -796,29 +1037,11 @@ describe("Qantara V2 - End-to-End Tests", () => {
             merchantId,
             amount,
             { sol: {} },
-            new anchor.BN(0), // min_out
-            payoutBps,
-            buybackBps,
-            burnBps
+            settleArgs({ payoutBps, buybackBps, burnOfBuybackBps: burnBps })
           )
-          .accounts({
-            protocolConfig: protocolConfigPDA,
-            merchantRegistry: merchantRegistryPDA,
-            payer: buyer.publicKey,
-            vaultSol: vaultSolPDA,
-            vaultUsdc: vaultUsdcPDA,
-            usdcMint: usdcMint,
-            vaultBuybackToken: vaultBuybackToken,
-            buybackMint: buybackMint,
-            protocolWallet: protocolWallet.publicKey,
-            protocolWalletUsdc: protocolWalletUsdcAccount,
+          .accounts(settleAccounts(vaultBuybackToken, {
             merchantPayoutWallet: attackerWallet.publicKey, // WRONG WALLET - should fail
-            merchantPayoutUsdc: merchantPayoutUsdcAccount,
-            payerUsdcAccount: buyerUsdcAccount,
-            jupiterRouter: JUPITER_ROUTER,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
+          }))
           .signers([buyer])
           .rpc();
 
@@ -849,22 +1072,6 @@ describe("Qantara V2 - End-to-End Tests", () => {
         9
       );
 
-      // Create token account for wrong mint (required for Anchor deserialization)
-      const wrongMintVault = await createAccount(
-        provider.connection,
-        merchantOwner,
-        wrongMint,
-        merchantOwner.publicKey
-      );
-      await setAuthority(
-        provider.connection,
-        merchantOwner,
-        wrongMintVault,
-        merchantOwner.publicKey,
-        AuthorityType.AccountOwner,
-        merchantRegistryPDA
-      );
-
       const amount = new anchor.BN(1 * LAMPORTS_PER_SOL);
       const payoutBps = new anchor.BN(7000);
       const buybackBps = new anchor.BN(3000);
@@ -876,29 +1083,11 @@ describe("Qantara V2 - End-to-End Tests", () => {
             merchantId,
             amount,
             { sol: {} },
-            new anchor.BN(0),
-            payoutBps,
-            buybackBps,
-            burnBps
+            settleArgs({ payoutBps, buybackBps, burnOfBuybackBps: burnBps })
           )
-          .accounts({
-            protocolConfig: protocolConfigPDA,
-            merchantRegistry: merchantRegistryPDA,
-            payer: buyer.publicKey,
-            vaultSol: vaultSolPDA,
-            vaultUsdc: vaultUsdcPDA,
-            usdcMint: usdcMint,
-            vaultBuybackToken: wrongMintVault, // Token account for wrong mint
+          .accounts(settleAccounts(vaultBuybackToken, {
             buybackMint: wrongMint, // WRONG MINT - should fail
-            protocolWallet: protocolWallet.publicKey,
-            protocolWalletUsdc: protocolWalletUsdcAccount,
-            merchantPayoutWallet: merchantPayoutWallet.publicKey,
-            merchantPayoutUsdc: merchantPayoutUsdcAccount,
-            payerUsdcAccount: buyerUsdcAccount,
-            jupiterRouter: JUPITER_ROUTER,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
+          }))
           .signers([buyer])
           .rpc();
 
@@ -920,6 +1109,42 @@ describe("Qantara V2 - End-to-End Tests", () => {
       }
     });
 
+    it("Fails settlement with a buyback vault the merchant registry does not own", async () => {
+      // The conservation check measures these accounts, so they must be the protocol's vaults
+      const foreignVault = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantOwner.publicKey,
+        Keypair.generate()
+      );
+      try {
+        await program.methods
+          .settle(merchantId, new anchor.BN(LAMPORTS_PER_SOL / 100), { sol: {} }, settleArgs())
+          .accounts(settleAccounts(foreignVault))
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have failed with ConstraintTokenOwner");
+      } catch (err: any) {
+        expect(err?.message || err?.toString() || "").to.include("ConstraintTokenOwner");
+        console.log("✅ Foreign buyback vault rejected");
+      }
+    });
+
+    it("Fails settlement with a SOL vault other than the protocol vault", async () => {
+      try {
+        await program.methods
+          .settle(merchantId, new anchor.BN(LAMPORTS_PER_SOL / 100), { sol: {} }, settleArgs())
+          .accounts(settleAccounts(vaultBuybackToken, { vaultSol: Keypair.generate().publicKey }))
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have failed with ConstraintSeeds");
+      } catch (err: any) {
+        expect(err?.message || err?.toString() || "").to.include("ConstraintSeeds");
+        console.log("✅ Foreign SOL vault rejected");
+      }
+    });
+
     it("Fails settlement with invalid BPS (payout + buyback > 100%)", async () => {
       const amount = new anchor.BN(1 * LAMPORTS_PER_SOL);
       const payoutBps = new anchor.BN(7000);
@@ -932,29 +1157,9 @@ describe("Qantara V2 - End-to-End Tests", () => {
             merchantId,
             amount,
             { sol: {} },
-            new anchor.BN(0),
-            payoutBps,
-            buybackBps,
-            burnBps
+            settleArgs({ payoutBps, buybackBps, burnOfBuybackBps: burnBps })
           )
-          .accounts({
-            protocolConfig: protocolConfigPDA,
-            merchantRegistry: merchantRegistryPDA,
-            payer: buyer.publicKey,
-            vaultSol: vaultSolPDA,
-            vaultUsdc: vaultUsdcPDA,
-            usdcMint: usdcMint,
-            vaultBuybackToken: vaultBuybackToken,
-            buybackMint: buybackMint,
-            protocolWallet: protocolWallet.publicKey,
-            protocolWalletUsdc: protocolWalletUsdcAccount,
-            merchantPayoutWallet: merchantPayoutWallet.publicKey,
-            merchantPayoutUsdc: merchantPayoutUsdcAccount,
-            payerUsdcAccount: buyerUsdcAccount,
-            jupiterRouter: JUPITER_ROUTER,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
+          .accounts(settleAccounts(vaultBuybackToken))
           .signers([buyer])
           .rpc();
 
@@ -1003,29 +1208,9 @@ describe("Qantara V2 - End-to-End Tests", () => {
             merchantId,
             amount,
             { sol: {} },
-            new anchor.BN(0),
-            payoutBps,
-            buybackBps,
-            burnBps
+            settleArgs({ payoutBps, buybackBps, burnOfBuybackBps: burnBps })
           )
-          .accounts({
-            protocolConfig: protocolConfigPDA,
-            merchantRegistry: merchantRegistryPDA,
-            payer: buyer.publicKey,
-            vaultSol: vaultSolPDA,
-            vaultUsdc: vaultUsdcPDA,
-            usdcMint: usdcMint,
-            vaultBuybackToken: vaultBuybackToken,
-            buybackMint: buybackMint,
-            protocolWallet: protocolWallet.publicKey,
-            protocolWalletUsdc: protocolWalletUsdcAccount,
-            merchantPayoutWallet: merchantPayoutWallet.publicKey,
-            merchantPayoutUsdc: merchantPayoutUsdcAccount,
-            payerUsdcAccount: buyerUsdcAccount,
-            jupiterRouter: JUPITER_ROUTER,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
+          .accounts(settleAccounts(vaultBuybackToken))
           .signers([buyer])
           .rpc();
 
@@ -1083,29 +1268,9 @@ describe("Qantara V2 - End-to-End Tests", () => {
             merchantId,
             amount,
             { sol: {} },
-            new anchor.BN(0),
-            payoutBps,
-            buybackBps,
-            burnBps
+            settleArgs({ payoutBps, buybackBps, burnOfBuybackBps: burnBps })
           )
-          .accounts({
-            protocolConfig: protocolConfigPDA,
-            merchantRegistry: merchantRegistryPDA,
-            payer: buyer.publicKey,
-            vaultSol: vaultSolPDA,
-            vaultUsdc: vaultUsdcPDA,
-            usdcMint: usdcMint,
-            vaultBuybackToken: vaultBuybackToken,
-            buybackMint: buybackMint,
-            protocolWallet: protocolWallet.publicKey,
-            protocolWalletUsdc: protocolWalletUsdcAccount,
-            merchantPayoutWallet: merchantPayoutWallet.publicKey,
-            merchantPayoutUsdc: merchantPayoutUsdcAccount,
-            payerUsdcAccount: buyerUsdcAccount,
-            jupiterRouter: JUPITER_ROUTER,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
+          .accounts(settleAccounts(vaultBuybackToken))
           .signers([buyer])
           .rpc();
 
@@ -1138,6 +1303,2778 @@ describe("Qantara V2 - End-to-End Tests", () => {
     });
   });
 
+  describe("Router Registry", () => {
+    let vaultSolPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [vaultSolPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), Buffer.from("sol")],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantOwner.publicKey
+      );
+      await setAuthority(
+        provider.connection,
+        merchantOwner,
+        vaultBuybackToken,
+        merchantOwner.publicKey,
+        AuthorityType.AccountOwner,
+        merchantRegistryPDA
+      );
+    });
+
+    const settleWithRouter = (router: PublicKey, routerPDA: PublicKey, swapData: Buffer) =>
+      program.methods
+        .settle(
+          merchantId,
+          new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+          { sol: {} },
+          settleArgs({
+            minOut: new anchor.BN(1),
+            payoutBps: new anchor.BN(7000),
+            buybackBps: new anchor.BN(3000),
+            swapData,
+          })
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          jupiterRouter: router,
+          swapRouter: routerPDA,
+        }))
+        .remainingAccounts([
+          { pubkey: vaultSolPDA, isSigner: false, isWritable: true },
+        ])
+        .signers([buyer])
+        .rpc();
+
+    it("Fails settlement through an unregistered router program", async () => {
+      const unknownRouter = Keypair.generate().publicKey;
+      const [unknownRouterPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("router"), unknownRouter.toBuffer()],
+        programId
+      );
+
+      try {
+        await settleWithRouter(
+          unknownRouter,
+          unknownRouterPDA,
+          Buffer.from(JUPITER_SWAP_DISCRIMINATORS[0])
+        );
+        expect.fail("Should have failed: router is not in the registry");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("AccountNotInitialized") ||
+          errorMsg.includes("expected this account to be already initialized")
+        ).to.be.true;
+        console.log("✅ Unregistered router rejected");
+      }
+    });
+
+    it("Fails settlement with a non-swap router entrypoint", async () => {
+      try {
+        await settleWithRouter(
+          JUPITER_ROUTER,
+          jupiterRouterPDA,
+          Buffer.from(ixDiscriminator("set_token_ledger"))
+        );
+        expect.fail("Should have failed with SwapInstructionNotAllowed");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("SwapInstructionNotAllowed") ||
+          errorMsg.includes("not an allowlisted router entrypoint")
+        ).to.be.true;
+        console.log("✅ Non-swap entrypoint rejected");
+      }
+    });
+
+    it("Fails settlement through a disabled router", async () => {
+      await program.methods
+        .updateRouter(null, false)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          swapRouter: jupiterRouterPDA,
+          authority: protocolAuthority.publicKey,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+      try {
+        await settleWithRouter(
+          JUPITER_ROUTER,
+          jupiterRouterPDA,
+          Buffer.from(JUPITER_SWAP_DISCRIMINATORS[0])
+        );
+        expect.fail("Should have failed with RouterDisabled");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("RouterDisabled") ||
+          errorMsg.includes("Router is disabled")
+        ).to.be.true;
+        console.log("✅ Disabled router rejected");
+      } finally {
+        await program.methods
+          .updateRouter(null, true)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            swapRouter: jupiterRouterPDA,
+            authority: protocolAuthority.publicKey,
+          })
+          .signers([protocolAuthority])
+          .rpc();
+      }
+    });
+  });
+
+  describe("Oracle Pricing", () => {
+    const PRICE_ACCOUNT_LEN = 240 + 32; // Pyth layout, then the mock oracle's authority
+    const PRICE_EXPO = -8;
+    const solPriceFeed = Keypair.generate();
+    const usdcPriceFeed = Keypair.generate();
+    const buybackPriceFeed = Keypair.generate();
+    let vaultSolPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    // mock_oracle::set_price(price: i64, expo: i32, conf: u64, publish_time: i64)
+    const setPriceIx = (
+      feed: PublicKey,
+      price: number,
+      publishTime: number,
+      authority: PublicKey = protocolAuthority.publicKey
+    ) => {
+      const data = Buffer.alloc(8 + 8 + 4 + 8 + 8);
+      Buffer.from(ixDiscriminator("set_price")).copy(data, 0);
+      data.writeBigInt64LE(BigInt(price), 8);
+      data.writeInt32LE(PRICE_EXPO, 16);
+      data.writeBigUInt64LE(BigInt(0), 20);
+      data.writeBigInt64LE(BigInt(publishTime), 28);
+      return new anchor.web3.TransactionInstruction({
+        programId: MOCK_ORACLE,
+        keys: [
+          { pubkey: feed, isSigner: false, isWritable: true },
+          { pubkey: authority, isSigner: true, isWritable: false },
+        ],
+        data,
+      });
+    };
+
+    const setPrice = async (feed: PublicKey, price: number, publishTime: number) => {
+      await provider.sendAndConfirm(new Transaction().add(setPriceIx(feed, price, publishTime)), [protocolAuthority]);
+    };
+
+    const now = () => Math.floor(Date.now() / 1000);
+
+    before(async () => {
+      [vaultSolPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), Buffer.from("sol")],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantOwner.publicKey
+      );
+      await setAuthority(
+        provider.connection,
+        merchantOwner,
+        vaultBuybackToken,
+        merchantOwner.publicKey,
+        AuthorityType.AccountOwner,
+        merchantRegistryPDA
+      );
+
+      // Price accounts are owned by the mock oracle program; the first write claims them, so
+      // each is created and priced in one transaction
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(PRICE_ACCOUNT_LEN);
+      const initialPrices: [Keypair, number][] = [
+        [solPriceFeed, 150_00000000], // $150
+        [usdcPriceFeed, 1_00000000], // $1
+        [buybackPriceFeed, 1000000], // $0.01
+      ];
+      for (const [feed, price] of initialPrices) {
+        await provider.sendAndConfirm(
+          new Transaction().add(
+            SystemProgram.createAccount({
+              fromPubkey: protocolAuthority.publicKey,
+              newAccountPubkey: feed.publicKey,
+              lamports: rent,
+              space: PRICE_ACCOUNT_LEN,
+              programId: MOCK_ORACLE,
+            }),
+            setPriceIx(feed.publicKey, price, now())
+          ),
+          [protocolAuthority, feed]
+        );
+      }
+
+      await program.methods
+        .configureOracle(MOCK_ORACLE, solPriceFeed.publicKey, usdcPriceFeed.publicKey, 60)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          authority: protocolAuthority.publicKey,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+      await program.methods
+        .updateBuybackOracle(buybackPriceFeed.publicKey, 100) // 1% max slippage
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+    });
+
+    const settleWithMinOut = (minOut: anchor.BN) =>
+      program.methods
+        .settle(
+          merchantId,
+          new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+          { sol: {} },
+          settleArgs({
+            minOut,
+            payoutBps: new anchor.BN(7000),
+            buybackBps: new anchor.BN(3000),
+            swapData: Buffer.from(JUPITER_SWAP_DISCRIMINATORS[0]),
+          })
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          payPriceFeed: solPriceFeed.publicKey,
+          buybackPriceFeed: buybackPriceFeed.publicKey,
+        }))
+        .remainingAccounts([
+          { pubkey: vaultSolPDA, isSigner: false, isWritable: true },
+        ])
+        .signers([buyer])
+        .rpc();
+
+    it("Only the price account's authority may update a mock price", async () => {
+      const before = (await provider.connection.getAccountInfo(solPriceFeed.publicKey))!.data;
+      try {
+        await provider.sendAndConfirm(
+          new Transaction().add(setPriceIx(solPriceFeed.publicKey, 1, now(), buyer.publicKey)),
+          [buyer]
+        );
+        expect.fail("Should have failed with Unauthorized");
+      } catch (err: any) {
+        expect(err?.message || "").to.not.include("Should have failed");
+      }
+      const after = (await provider.connection.getAccountInfo(solPriceFeed.publicKey))!.data;
+      expect(after.readBigInt64LE(208)).to.equal(before.readBigInt64LE(208));
+      console.log("✅ Mock price writes limited to the account's authority");
+    });
+
+    it("Fails settlement when min_out implies more slippage than allowed", async () => {
+      // 0.01 SOL - 1% fee = 0.0099 SOL; 30% buyback = 0.00297 SOL ≈ $0.4455 ≈ 44.55 tokens
+      try {
+        await settleWithMinOut(new anchor.BN(1));
+        expect.fail("Should have failed with MinOutBelowOracleBound");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("MinOutBelowOracleBound") ||
+          errorMsg.includes("min_out implies more slippage")
+        ).to.be.true;
+        console.log("✅ Oracle slippage bound enforced");
+      }
+    });
+
+    it("Fails settlement with a stale oracle price", async () => {
+      await setPrice(buybackPriceFeed.publicKey, 1000000, now() - 3600);
+
+      try {
+        await settleWithMinOut(new anchor.BN(44_550_000_000));
+        expect.fail("Should have failed with OraclePriceStale");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("OraclePriceStale") ||
+          errorMsg.includes("Oracle price is stale")
+        ).to.be.true;
+        console.log("✅ Stale oracle price rejected");
+      } finally {
+        await setPrice(buybackPriceFeed.publicKey, 1000000, now());
+      }
+    });
+
+    it("Fails USD settlement when the conversion exceeds the payer's max_lamports", async () => {
+      // $10.00 at $150/SOL ≈ 0.0667 SOL, far above the 1000 lamport cap
+      try {
+        await program.methods
+          .settleUsd(
+            merchantId,
+            new anchor.BN(10_000_000), // usd_amount (6 decimals)
+            new anchor.BN(1000), // max_lamports
+            settleArgs()
+          )
+          .accounts(settleAccounts(vaultBuybackToken, {
+            payPriceFeed: solPriceFeed.publicKey,
+          }))
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have failed with UsdConversionExceedsMax");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("UsdConversionExceedsMax") ||
+          errorMsg.includes("more lamports than the payer allowed")
+        ).to.be.true;
+        console.log("✅ USD conversion bounded by max_lamports");
+      }
+    });
+
+    describe("Settlement Currency", () => {
+      const setSettlementMint = (mint: PublicKey) =>
+        program.methods
+          .updateSettlementMint(mint)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            merchantRegistry: merchantRegistryPDA,
+            owner: merchantOwner.publicKey,
+          })
+          .signers([merchantOwner])
+          .rpc();
+
+      const settlePayoutOnly = (payoutSwap: any) =>
+        program.methods
+          .settle(
+            merchantId,
+            new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+            { sol: {} },
+            settleArgs({ payoutSwap })
+          )
+          .accounts(settleAccounts(vaultBuybackToken, {
+            payPriceFeed: solPriceFeed.publicKey,
+            settlementPriceFeed: usdcPriceFeed.publicKey,
+          }))
+          .remainingAccounts(
+            payoutSwap ? [{ pubkey: vaultSolPDA, isSigner: false, isWritable: true }] : []
+          )
+          .signers([buyer])
+          .rpc();
+
+      before(async () => {
+        await setSettlementMint(usdcMint);
+      });
+
+      after(async () => {
+        await setSettlementMint(PublicKey.default);
+      });
+
+      it("Fails SOL settlement without a payout swap for a USDC-settled merchant", async () => {
+        try {
+          await settlePayoutOnly(null);
+          expect.fail("Should have failed with PayoutSwapMismatch");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(
+            errorMsg.includes("PayoutSwapMismatch") ||
+            errorMsg.includes("Payout swap must be supplied")
+          ).to.be.true;
+          console.log("✅ Missing payout conversion rejected");
+        }
+      });
+
+      it("Fails payout conversion when min_out implies more slippage than allowed", async () => {
+        // 0.01 SOL - 1% fee = 0.0099 SOL ≈ $1.485 ≈ 1,485,000 USDC base units
+        try {
+          await settlePayoutOnly({
+            swapData: Buffer.from(JUPITER_SWAP_DISCRIMINATORS[0]),
+            minOut: new anchor.BN(1),
+            accountCount: 1,
+          });
+          expect.fail("Should have failed with MinOutBelowOracleBound");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(
+            errorMsg.includes("MinOutBelowOracleBound") ||
+            errorMsg.includes("min_out implies more slippage")
+          ).to.be.true;
+          console.log("✅ Payout conversion bounded by oracle price");
+        }
+      });
+
+      it("Converts a SOL payment into a USDC payout through the router", async () => {
+        // The SPL Token program stands in for the router: its Transfer (tag 3 + amount) delivers
+        // the converted USDC from a market-maker account into vault_usdc, and the payout share
+        // stays in vault_sol as the route's input
+        const amount = 0.01 * LAMPORTS_PER_SOL;
+        const payout = amount - (amount * PROTOCOL_FEE_BPS) / 10000;
+        const converted = (payout * 150 * 1_000_000) / LAMPORTS_PER_SOL; // at $150 per SOL
+        const transferData = Buffer.alloc(9);
+        transferData.writeUInt8(3, 0);
+        transferData.writeBigUInt64LE(BigInt(converted), 1);
+        const [tokenRouterPDA] = PublicKey.findProgramAddressSync(
+          [Buffer.from("router"), TOKEN_PROGRAM_ID.toBuffer()],
+          programId
+        );
+        const vaultUsdc = settleAccounts(vaultBuybackToken).vaultUsdc as PublicKey;
+        const merchantPayoutUsdc = getAssociatedTokenAddressSync(usdcMint, merchantPayoutWallet.publicKey);
+        const marketMakerUsdc = await createAccount(
+          provider.connection,
+          buyer,
+          usdcMint,
+          buyer.publicKey,
+          Keypair.generate()
+        );
+        await mintTo(provider.connection, buyer, usdcMint, marketMakerUsdc, protocolAuthority, converted);
+
+        await program.methods
+          .addRouter(TOKEN_PROGRAM_ID, [Array.from(transferData.subarray(0, 8))])
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            swapRouter: tokenRouterPDA,
+            authority: protocolAuthority.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([protocolAuthority])
+          .rpc();
+
+        const balance = async (account: PublicKey) =>
+          Number((await provider.connection.getTokenAccountBalance(account)).value.amount);
+        const payoutBefore = await balance(merchantPayoutUsdc);
+        const vaultSolBefore = await provider.connection.getBalance(vaultSolPDA);
+
+        try {
+          await program.methods
+            .settle(
+              merchantId,
+              new anchor.BN(amount),
+              { sol: {} },
+              settleArgs({
+                payoutSwap: {
+                  swapData: transferData,
+                  minOut: new anchor.BN(converted),
+                  accountCount: 3,
+                },
+              })
+            )
+            .accounts(settleAccounts(vaultBuybackToken, {
+              payPriceFeed: solPriceFeed.publicKey,
+              settlementPriceFeed: usdcPriceFeed.publicKey,
+              jupiterRouter: TOKEN_PROGRAM_ID,
+              swapRouter: tokenRouterPDA,
+            }))
+            .remainingAccounts([
+              { pubkey: marketMakerUsdc, isSigner: false, isWritable: true },
+              { pubkey: vaultUsdc, isSigner: false, isWritable: true },
+              { pubkey: buyer.publicKey, isSigner: true, isWritable: false },
+            ])
+            .signers([buyer])
+            .rpc();
+
+          // The protocol fee is signed out of vault_sol; the converted USDC is signed out of
+          // vault_usdc to the merchant
+          expect((await balance(merchantPayoutUsdc)) - payoutBefore).to.equal(converted);
+          expect((await provider.connection.getBalance(vaultSolPDA)) - vaultSolBefore).to.equal(payout);
+          console.log("✅ SOL payment paid out in USDC");
+        } finally {
+          await program.methods
+            .removeRouter()
+            .accounts({
+              protocolConfig: protocolConfigPDA,
+              swapRouter: tokenRouterPDA,
+              authority: protocolAuthority.publicKey,
+            })
+            .signers([protocolAuthority])
+            .rpc();
+        }
+      });
+
+      it("Fails to set a settlement mint other than SOL or the protocol USDC", async () => {
+        try {
+          await setSettlementMint(buybackMint);
+          expect.fail("Should have failed with UnsupportedSettlementMint");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(
+            errorMsg.includes("UnsupportedSettlementMint") ||
+            errorMsg.includes("Settlement mint must be SOL")
+          ).to.be.true;
+          console.log("✅ Unsupported settlement mint rejected");
+        }
+      });
+
+      it("Fails settlement with a USDC mint other than the protocol's", async () => {
+        try {
+          await program.methods
+            .settle(
+              merchantId,
+              new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+              { sol: {} },
+              settleArgs()
+            )
+            .accounts(settleAccounts(vaultBuybackToken, {
+              usdcMint: buybackMint,
+            }))
+            .signers([buyer])
+            .rpc();
+          expect.fail("Should have failed with InvalidUsdcMint");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(errorMsg).to.include("InvalidUsdcMint");
+          console.log("✅ Settle pinned to the protocol USDC mint");
+        }
+      });
+    });
+  });
+
+  describe("Pay With Any Token", () => {
+    let vaultBuybackToken: PublicKey;
+    let altMint: PublicKey;
+    let buyerAltAccount: PublicKey;
+
+    before(async () => {
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+
+      // Token the buyer holds instead of SOL/USDC (stands in for BONK, JUP, ...)
+      altMint = await createMint(
+        provider.connection,
+        protocolAuthority,
+        protocolAuthority.publicKey,
+        null,
+        5
+      );
+      buyerAltAccount = await createAccount(provider.connection, buyer, altMint, buyer.publicKey);
+      await mintTo(provider.connection, buyer, altMint, buyerAltAccount, protocolAuthority, 1_000_000_000);
+    });
+
+    const settleWithInputSwap = (swapData: Buffer, payerInputToken: PublicKey | null) =>
+      program.methods
+        .settleWithSwap(
+          merchantId,
+          new anchor.BN(1_000_000), // 1 USDC invoice
+          {
+            swapData,
+            maxAmountIn: new anchor.BN(500_000_000),
+            accountCount: 1,
+          },
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          payerInputToken,
+        }))
+        .remainingAccounts([
+          { pubkey: buyerAltAccount, isSigner: false, isWritable: true },
+        ])
+        .signers([buyer])
+        .rpc();
+
+    it("Fails swap settlement without the payer's input token account", async () => {
+      try {
+        await settleWithInputSwap(Buffer.from(JUPITER_SWAP_DISCRIMINATORS[0]), null);
+        expect.fail("Should have failed with InvalidInputSwap");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("InvalidInputSwap") ||
+          errorMsg.includes("Input swap is missing accounts")
+        ).to.be.true;
+        console.log("✅ Missing input token account rejected");
+      }
+    });
+
+    it("Fails swap settlement with a non-swap router entrypoint", async () => {
+      try {
+        await settleWithInputSwap(Buffer.from(ixDiscriminator("set_token_ledger")), buyerAltAccount);
+        expect.fail("Should have failed with SwapInstructionNotAllowed");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("SwapInstructionNotAllowed") ||
+          errorMsg.includes("not an allowlisted router entrypoint")
+        ).to.be.true;
+        console.log("✅ Input swap entrypoint checked against the registry");
+      }
+    });
+
+    it("Refunds the payer when the input route over-delivers USDC", async () => {
+      // The SPL Token program stands in for the router: its Transfer (tag 3 + amount) moves
+      // 1.5 USDC from a second payer account into vault_usdc for a 1 USDC invoice
+      const delivered = 1_500_000;
+      const transferData = Buffer.alloc(9);
+      transferData.writeUInt8(3, 0);
+      transferData.writeBigUInt64LE(BigInt(delivered), 1);
+      const [tokenRouterPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("router"), TOKEN_PROGRAM_ID.toBuffer()],
+        programId
+      );
+      const vaultUsdc = settleAccounts(vaultBuybackToken).vaultUsdc as PublicKey;
+      const buyerUsdc = getAssociatedTokenAddressSync(usdcMint, buyer.publicKey);
+      const buyerRouteUsdc = await createAccount(
+        provider.connection,
+        buyer,
+        usdcMint,
+        buyer.publicKey,
+        Keypair.generate()
+      );
+      await mintTo(provider.connection, buyer, usdcMint, buyerRouteUsdc, protocolAuthority, delivered);
+
+      await program.methods
+        .addRouter(TOKEN_PROGRAM_ID, [Array.from(transferData.subarray(0, 8))])
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          swapRouter: tokenRouterPDA,
+          authority: protocolAuthority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+      const balance = async (account: PublicKey) =>
+        Number((await provider.connection.getTokenAccountBalance(account)).value.amount);
+      const buyerBefore = await balance(buyerUsdc);
+      const vaultBefore = await balance(vaultUsdc);
+
+      try {
+        await program.methods
+          .settleWithSwap(
+            merchantId,
+            new anchor.BN(1_000_000), // 1 USDC invoice
+            {
+              swapData: transferData,
+              maxAmountIn: new anchor.BN(delivered),
+              accountCount: 3,
+            },
+            settleArgs()
+          )
+          .accounts(settleAccounts(vaultBuybackToken, {
+            payerInputToken: buyerRouteUsdc,
+            jupiterRouter: TOKEN_PROGRAM_ID,
+            swapRouter: tokenRouterPDA,
+          }))
+          .remainingAccounts([
+            { pubkey: buyerRouteUsdc, isSigner: false, isWritable: true },
+            { pubkey: vaultUsdc, isSigner: false, isWritable: true },
+            { pubkey: buyer.publicKey, isSigner: true, isWritable: false },
+          ])
+          .signers([buyer])
+          .rpc();
+
+        // The 0.5 USDC surplus is signed out of vault_usdc back to the payer, and the
+        // invoice leaves the vault as protocol fee and merchant payout
+        expect(await balance(buyerRouteUsdc)).to.equal(0);
+        expect((await balance(buyerUsdc)) - buyerBefore).to.equal(delivered - 1_000_000);
+        expect(await balance(vaultUsdc)).to.equal(vaultBefore);
+        console.log("✅ Over-delivered USDC refunded to the payer");
+      } finally {
+        await program.methods
+          .removeRouter()
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            swapRouter: tokenRouterPDA,
+            authority: protocolAuthority.publicKey,
+          })
+          .signers([protocolAuthority])
+          .rpc();
+      }
+    });
+  });
+
+  describe("Merchant Closure", () => {
+    let closingMerchantId: anchor.BN;
+    let closingMerchantPDA: PublicKey;
+
+    // Buyback vault owned by the given merchant registry PDA
+    const createBuybackVault = async (registry: PublicKey) => {
+      return createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        registry,
+        Keypair.generate()
+      );
+    };
+
+    // Per-merchant PDA keyed by ["<seed>", merchant_id]
+    const closingPDA = (seed: string) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(seed), closingMerchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      )[0];
+
+    const closeMerchant = (vault: PublicKey) =>
+      program.methods
+        .closeMerchant()
+        .accounts({
+          merchantRegistry: closingMerchantPDA,
+          vaultBuybackToken: vault,
+          merchantMetadata: closingPDA("metadata"),
+          merchantLimits: closingPDA("limits"),
+          tipPolicy: closingPDA("tips"),
+          donationSettings: closingPDA("donations"),
+          owner: merchantOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    before(async () => {
+      const closingNonce = merchantNonce.addn(1);
+      closingMerchantId = deriveMerchantId(merchantOwner.publicKey, closingNonce);
+      [closingMerchantPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("merchant"), closingMerchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      await program.methods
+        .registerMerchant(closingMerchantId, closingNonce, merchantPayoutWallet.publicKey, buybackMint)
+        .accounts({
+          merchantRegistry: closingMerchantPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+    });
+
+    it("Fails to close while the buyback vault holds tokens", async () => {
+      const vault = await createBuybackVault(closingMerchantPDA);
+      await mintTo(provider.connection, merchantOwner, buybackMint, vault, merchantOwner, 1_000_000_000);
+
+      try {
+        await closeMerchant(vault);
+        expect.fail("Should have failed with BuybackVaultNotEmpty");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("BuybackVaultNotEmpty") ||
+          errorMsg.includes("Buyback vault must be empty")
+        ).to.be.true;
+        console.log("✅ Close refused with buyback balance outstanding");
+      }
+    });
+
+    it("Fails to close while a handle resolves to the merchant", async () => {
+      const handle = `closing-${Date.now().toString(36)}`;
+      const [handlePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("handle"), Buffer.from(handle)],
+        programId
+      );
+      await program.methods
+        .claimHandle(handle)
+        .accounts({
+          merchantHandle: handlePDA,
+          merchantRegistry: closingMerchantPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      try {
+        await closeMerchant(await createBuybackVault(closingMerchantPDA));
+        expect.fail("Should have failed with HandlesOutstanding");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("HandlesOutstanding") ||
+          errorMsg.includes("still has claimed handles")
+        ).to.be.true;
+        console.log("✅ Close refused while a handle points at the merchant");
+      } finally {
+        await program.methods
+          .releaseHandle()
+          .accounts({
+            merchantHandle: handlePDA,
+            merchantRegistry: closingMerchantPDA,
+            owner: merchantOwner.publicKey,
+          })
+          .signers([merchantOwner])
+          .rpc();
+      }
+    });
+
+    it("Closes the merchant registry and returns rent to the owner", async () => {
+      await program.methods
+        .initMerchantMetadata({
+          name: "Closing Shop",
+          uri: "https://closing.example/logo.png",
+          website: "https://closing.example",
+          supportContact: "support@closing.example",
+          categoryCode: 5814,
+          termsHash: Array.from(createHash("sha256").update("closing terms").digest()),
+        })
+        .accounts({
+          merchantMetadata: closingPDA("metadata"),
+          merchantRegistry: closingMerchantPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+      const vault = await createBuybackVault(closingMerchantPDA);
+      const balanceBefore = await provider.connection.getBalance(merchantOwner.publicKey);
+
+      await closeMerchant(vault);
+
+      expect(await provider.connection.getAccountInfo(closingMerchantPDA)).to.be.null;
+      expect(await provider.connection.getAccountInfo(vault)).to.be.null;
+      // Metadata is closed with the registry instead of being orphaned
+      expect(await provider.connection.getAccountInfo(closingPDA("metadata"))).to.be.null;
+      expect(await provider.connection.getBalance(merchantOwner.publicKey)).to.be.greaterThan(balanceBefore);
+      console.log("✅ Merchant closed and rent reclaimed");
+    });
+  });
+
+  describe("Migration from V1", () => {
+    const v1Owner = Keypair.generate();
+    let v1MerchantConfig: PublicKey;
+    let v1MigrationRecord: PublicKey;
+    let migratedMerchantId: anchor.BN;
+    let migratedMerchantPDA: PublicKey;
+
+    before(async () => {
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: merchantOwner.publicKey,
+            toPubkey: v1Owner.publicKey,
+            lamports: 0.05 * LAMPORTS_PER_SOL,
+          })
+        ),
+        [merchantOwner]
+      );
+
+      [v1MerchantConfig] = PublicKey.findProgramAddressSync(
+        [Buffer.from("merchant"), v1Owner.publicKey.toBuffer()],
+        QANTARA_V1
+      );
+      [v1MigrationRecord] = PublicKey.findProgramAddressSync(
+        [Buffer.from("migrated"), v1Owner.publicKey.toBuffer()],
+        QANTARA_V1
+      );
+      migratedMerchantId = deriveMerchantId(v1Owner.publicKey, new anchor.BN(0));
+      [migratedMerchantPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("merchant"), migratedMerchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+
+      // v1 init_merchant(InitMerchantArgs), encoded by hand (v1 IDL is not loaded here)
+      const data = Buffer.alloc(8 + 32 + 1 + 1 + 32 + 2 * 4 + 32);
+      let offset = 0;
+      Buffer.from(ixDiscriminator("init_merchant")).copy(data, offset); offset += 8;
+      merchantPayoutWallet.publicKey.toBuffer().copy(data, offset); offset += 32;
+      data.writeUInt8(1, offset++); // allow_sol
+      data.writeUInt8(1, offset++); // allow_usdc
+      buybackMint.toBuffer().copy(data, offset); offset += 32;
+      data.writeUInt16LE(7000, offset); offset += 2; // payout_bps
+      data.writeUInt16LE(3000, offset); offset += 2; // buyback_bps
+      data.writeUInt16LE(5000, offset); offset += 2; // burn_of_buyback_bps
+      data.writeUInt16LE(100, offset); offset += 2; // slippage_bps_max
+      JUPITER_ROUTER.toBuffer().copy(data, offset);
+
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          new anchor.web3.TransactionInstruction({
+            programId: QANTARA_V1,
+            keys: [
+              { pubkey: v1MerchantConfig, isSigner: false, isWritable: true },
+              { pubkey: v1MigrationRecord, isSigner: false, isWritable: false },
+              { pubkey: v1Owner.publicKey, isSigner: true, isWritable: true },
+              { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            ],
+            data,
+          })
+        ),
+        [v1Owner]
+      );
+    });
+
+    it("Migrates a v1 merchant and freezes it in v1", async () => {
+      await program.methods
+        .migrateFromV1(migratedMerchantId, new anchor.BN(0))
+        .accounts({
+          v1MerchantConfig,
+          v1MigrationRecord,
+          merchantRegistry: migratedMerchantPDA,
+          owner: v1Owner.publicKey,
+          qantaraProgram: QANTARA_V1,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([v1Owner])
+        .rpc();
+
+      const merchant = await program.account.merchantRegistry.fetch(migratedMerchantPDA);
+      expect(merchant.owner.toString()).to.equal(v1Owner.publicKey.toString());
+      expect(merchant.payoutWallet.toString()).to.equal(merchantPayoutWallet.publicKey.toString());
+      expect(merchant.buybackMint.toString()).to.equal(buybackMint.toString());
+      expect(merchant.payoutBps).to.equal(7000);
+      expect(merchant.buybackBps).to.equal(3000);
+      expect(merchant.burnOfBuybackBps).to.equal(5000);
+      expect(merchant.frozen).to.be.false;
+
+      // v1 MerchantConfig.frozen sits after owner, payout_wallet, allow_*, buyback_mint, 4 bps fields, router
+      const v1Data = (await provider.connection.getAccountInfo(v1MerchantConfig))!.data;
+      expect(v1Data[8 + 32 + 32 + 1 + 1 + 32 + 2 * 4 + 32]).to.equal(1);
+      expect(await provider.connection.getAccountInfo(v1MigrationRecord)).to.not.be.null;
+      console.log("✅ v1 merchant migrated and frozen");
+    });
+
+    it("Fails to migrate the same v1 merchant twice", async () => {
+      const otherId = deriveMerchantId(v1Owner.publicKey, new anchor.BN(1));
+      const [otherPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("merchant"), otherId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+
+      try {
+        await program.methods
+          .migrateFromV1(otherId, new anchor.BN(1))
+          .accounts({
+            v1MerchantConfig,
+            v1MigrationRecord,
+            merchantRegistry: otherPDA,
+            owner: v1Owner.publicKey,
+            qantaraProgram: QANTARA_V1,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([v1Owner])
+          .rpc();
+        expect.fail("Should have failed: migration record already exists");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("already in use")).to.be.true;
+        console.log("✅ Second migration rejected");
+      }
+    });
+  });
+
+  describe("Merchant Handles", () => {
+    const HANDLE = `acme-${Date.now().toString(36)}`;
+    let handlePDA: PublicKey;
+    let otherMerchantId: anchor.BN;
+    let otherMerchantPDA: PublicKey;
+
+    const getHandlePDA = (handle: string) =>
+      PublicKey.findProgramAddressSync([Buffer.from("handle"), Buffer.from(handle)], programId)[0];
+
+    const claimHandle = (handle: string, registry: PublicKey) =>
+      program.methods
+        .claimHandle(handle)
+        .accounts({
+          merchantHandle: getHandlePDA(handle),
+          merchantRegistry: registry,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    before(async () => {
+      handlePDA = getHandlePDA(HANDLE);
+
+      const otherNonce = merchantNonce.addn(2);
+      otherMerchantId = deriveMerchantId(merchantOwner.publicKey, otherNonce);
+      [otherMerchantPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("merchant"), otherMerchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      await program.methods
+        .registerMerchant(otherMerchantId, otherNonce, merchantPayoutWallet.publicKey, buybackMint)
+        .accounts({
+          merchantRegistry: otherMerchantPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+    });
+
+    it("Claims a handle for the merchant", async () => {
+      await claimHandle(HANDLE, merchantRegistryPDA);
+
+      const merchantHandle = await program.account.merchantHandle.fetch(handlePDA);
+      expect(merchantHandle.handle).to.equal(HANDLE);
+      expect(merchantHandle.merchantId.toString()).to.equal(merchantId.toString());
+      expect(merchantHandle.owner.toBase58()).to.equal(merchantOwner.publicKey.toBase58());
+      console.log("✅ Handle claimed:", HANDLE);
+    });
+
+    it("Fails to claim a handle that is already taken", async () => {
+      try {
+        await claimHandle(HANDLE, otherMerchantPDA);
+        expect.fail("Should have failed: handle already claimed");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include("already in use");
+        console.log("✅ Duplicate handle rejected");
+      }
+    });
+
+    it("Fails to claim a handle that is not normalized", async () => {
+      for (const handle of ["Acme-Coffee", "-acme", "ac", "acme_coffee"]) {
+        try {
+          await claimHandle(handle, merchantRegistryPDA);
+          expect.fail(`Should have failed with InvalidHandle for "${handle}"`);
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(errorMsg.includes("InvalidHandle") || errorMsg.includes("Handle must be")).to.be.true;
+        }
+      }
+      console.log("✅ Non-normalized handles rejected");
+    });
+
+    it("Transfers the handle to another merchant", async () => {
+      await program.methods
+        .transferHandle()
+        .accounts({
+          merchantHandle: handlePDA,
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+          newMerchantRegistry: otherMerchantPDA,
+          newOwner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      const merchantHandle = await program.account.merchantHandle.fetch(handlePDA);
+      expect(merchantHandle.merchantId.toString()).to.equal(otherMerchantId.toString());
+      const other = await program.account.merchantRegistry.fetch(otherMerchantPDA);
+      expect(other.claimedHandles).to.equal(1);
+      console.log("✅ Handle now resolves to merchant", otherMerchantId.toString());
+    });
+
+    it("Fails settlement when the handle resolves to a different merchant", async () => {
+      const vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+
+      try {
+        await program.methods
+          .settle(
+            merchantId,
+            new anchor.BN(LAMPORTS_PER_SOL / 100),
+            { sol: {} },
+            settleArgs()
+          )
+          .accounts(settleAccounts(vaultBuybackToken, {
+            merchantHandle: handlePDA, // now points at otherMerchantId
+          }))
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have failed with MerchantHandleMismatch");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("MerchantHandleMismatch") ||
+          errorMsg.includes("does not resolve to this merchant")
+        ).to.be.true;
+        console.log("✅ Stale handle cannot redirect a settlement");
+      }
+    });
+
+    it("Releases the handle so it can be claimed again", async () => {
+      await program.methods
+        .releaseHandle()
+        .accounts({
+          merchantHandle: handlePDA,
+          merchantRegistry: otherMerchantPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+      expect(await provider.connection.getAccountInfo(handlePDA)).to.be.null;
+
+      await claimHandle(HANDLE, merchantRegistryPDA);
+      const merchantHandle = await program.account.merchantHandle.fetch(handlePDA);
+      expect(merchantHandle.merchantId.toString()).to.equal(merchantId.toString());
+      console.log("✅ Released handle reclaimed");
+    });
+  });
+
+  describe("Merchant Metadata", () => {
+    let metadataPDA: PublicKey;
+
+    const metadataArgs = (overrides: Record<string, any> = {}) => ({
+      name: "Acme Coffee",
+      uri: "https://acme.example/logo.png",
+      website: "https://acme.example",
+      supportContact: "support@acme.example",
+      categoryCode: 5814, // Fast food restaurants
+      termsHash: Array.from(createHash("sha256").update("acme terms v1").digest()),
+      ...overrides,
+    });
+
+    before(() => {
+      [metadataPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("metadata"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+    });
+
+    const updateMetadata = (args: ReturnType<typeof metadataArgs>, owner: Keypair = merchantOwner) =>
+      program.methods
+        .updateMerchantMetadata(args)
+        .accounts({
+          merchantMetadata: metadataPDA,
+          merchantRegistry: merchantRegistryPDA,
+          owner: owner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+
+    it("Rejects metadata that fails validation", async () => {
+      const invalid = [
+        metadataArgs({ name: "" }),
+        metadataArgs({ name: " Acme" }),
+        metadataArgs({ name: "x".repeat(65) }),
+        metadataArgs({ uri: "http://acme.example/logo.png" }),
+        metadataArgs({ supportContact: "a".repeat(101) }),
+        metadataArgs({ categoryCode: 10000 }),
+      ];
+      for (const args of invalid) {
+        try {
+          await program.methods
+            .initMerchantMetadata(args)
+            .accounts({
+              merchantMetadata: metadataPDA,
+              merchantRegistry: merchantRegistryPDA,
+              owner: merchantOwner.publicKey,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([merchantOwner])
+            .rpc();
+          expect.fail("Should have rejected invalid metadata");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(errorMsg).to.match(/InvalidMetadataName|InvalidMetadataField|InvalidMetadataUri|InvalidCategoryCode/);
+        }
+      }
+      console.log("✅ Invalid metadata rejected");
+    });
+
+    it("Publishes merchant metadata", async () => {
+      const args = metadataArgs();
+      await program.methods
+        .initMerchantMetadata(args)
+        .accounts({
+          merchantMetadata: metadataPDA,
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      const metadata = await program.account.merchantMetadata.fetch(metadataPDA);
+      expect(metadata.name).to.equal("Acme Coffee");
+      expect(metadata.categoryCode).to.equal(5814);
+      expect(metadata.merchantId.toString()).to.equal(merchantId.toString());
+      console.log("✅ Metadata published");
+    });
+
+    it("Resizes the account when metadata grows or shrinks", async () => {
+      const sizeOf = async () => (await provider.connection.getAccountInfo(metadataPDA))!.data.length;
+      const initialSize = await sizeOf();
+
+      await updateMetadata(metadataArgs({ uri: `ipfs://${"a".repeat(52)}` }));
+      expect(await sizeOf()).to.equal(initialSize + 30);
+
+      await updateMetadata(metadataArgs({ website: "", supportContact: "" }));
+      expect(await sizeOf()).to.be.lessThan(initialSize);
+
+      const metadata = await program.account.merchantMetadata.fetch(metadataPDA);
+      expect(metadata.website).to.equal("");
+      console.log("✅ Metadata account resized with its contents");
+    });
+
+    it("Fails to update metadata as a non-owner", async () => {
+      try {
+        await updateMetadata(metadataArgs({ name: "Not Acme" }), buyer);
+        expect.fail("Should have failed: only the merchant owner may update metadata");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("has_one") || errorMsg.includes("ConstraintHasOne")).to.be.true;
+        console.log("✅ Non-owner metadata update rejected");
+      }
+    });
+  });
+
+  describe("Order Reference and Memo", () => {
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const settleWithReference = (
+      orderReference: number[] | null,
+      memo: string | null,
+      reference: PublicKey | null,
+      receipt: PublicKey | null = null
+    ) =>
+      program.methods
+        .settle(
+          merchantId,
+          new anchor.BN(LAMPORTS_PER_SOL / 100),
+          { sol: {} },
+          settleArgs({ orderReference, memo })
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          reference,
+          receipt,
+        }))
+        .signers([buyer])
+        .rpc();
+
+    it("Fails settlement when the reference account differs from the order reference", async () => {
+      // Solana Pay reference: a fresh public key added read-only to the transaction
+      const orderReference = Keypair.generate().publicKey;
+      for (const [ref, account] of [
+        [Array.from(orderReference.toBytes()), Keypair.generate().publicKey],
+        [null, orderReference],
+      ] as const) {
+        try {
+          await settleWithReference(ref as number[] | null, null, account);
+          expect.fail("Should have failed with OrderReferenceMismatch");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(
+            errorMsg.includes("OrderReferenceMismatch") ||
+            errorMsg.includes("does not match the order reference")
+          ).to.be.true;
+        }
+      }
+      console.log("✅ Reference account must match the order reference");
+    });
+
+    it("Fails settlement with a memo over 64 bytes", async () => {
+      try {
+        await settleWithReference(null, "x".repeat(65), null);
+        expect.fail("Should have failed with MemoTooLong");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("MemoTooLong") || errorMsg.includes("Memo exceeds")).to.be.true;
+        console.log("✅ Oversized memo rejected");
+      }
+    });
+
+    it("Fails to create a receipt without an order reference or at the wrong address", async () => {
+      const orderReference = Keypair.generate().publicKey;
+      const receiptPDA = (ref: PublicKey) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from("receipt"), merchantId.toArrayLike(Buffer, "le", 8), ref.toBuffer()],
+          programId
+        )[0];
+
+      for (const [ref, receipt] of [
+        [null, receiptPDA(orderReference)],
+        [Array.from(orderReference.toBytes()), receiptPDA(Keypair.generate().publicKey)],
+      ] as const) {
+        try {
+          await settleWithReference(ref as number[] | null, null, null, receipt);
+          expect.fail("Should have failed with InvalidReceiptAccount");
+        } catch (err: any) {
+          const errorMsg = err?.message || err?.toString() || "";
+          expect(
+            errorMsg.includes("InvalidReceiptAccount") ||
+            errorMsg.includes("A receipt requires an order reference")
+          ).to.be.true;
+        }
+      }
+      console.log("✅ Receipt must be the PDA of the order reference");
+    });
+  });
+
+  describe("Daily Stats", () => {
+    const openDayStats = (day: number) =>
+      program.methods
+        .openDayStats(merchantId, day)
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          merchantDayStats: getDayStatsPDA(merchantId, day),
+          payer: protocolAuthority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+    it("Opens tomorrow's bucket ahead of settlement", async () => {
+      const day = currentDay() + 1;
+      await openDayStats(day);
+
+      const stats = await program.account.merchantDayStats.fetch(getDayStatsPDA(merchantId, day));
+      expect(stats.day).to.equal(day);
+      expect(stats.merchantId.toString()).to.equal(merchantId.toString());
+      expect(stats.paymentCount.toNumber()).to.equal(0);
+      expect(stats.rentPayer.toBase58()).to.equal(protocolAuthority.publicKey.toBase58());
+      console.log("✅ Day bucket opened by the crank");
+    });
+
+    it("Fails to open a bucket for a past day", async () => {
+      try {
+        await openDayStats(currentDay() - 1);
+        expect.fail("Should have failed with InvalidDayStatsDay");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("InvalidDayStatsDay") || errorMsg.includes("past day")).to.be.true;
+        console.log("✅ Past-day bucket rejected");
+      }
+    });
+
+    it("Fails to close a bucket within the retention period", async () => {
+      const day = currentDay() + 1;
+      try {
+        await program.methods
+          .closeDayStats()
+          .accounts({
+            merchantDayStats: getDayStatsPDA(merchantId, day),
+            rentPayer: protocolAuthority.publicKey,
+          })
+          .rpc();
+        expect.fail("Should have failed with InvalidDayStatsDay");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg.includes("InvalidDayStatsDay") || errorMsg.includes("retention period")).to.be.true;
+        console.log("✅ Recent bucket cannot be closed");
+      }
+    });
+
+    it("Fails settlement with another day's bucket", async () => {
+      const vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+
+      try {
+        await program.methods
+          .settle(
+            merchantId,
+            new anchor.BN(LAMPORTS_PER_SOL / 100),
+            { sol: {} },
+            settleArgs()
+          )
+          .accounts(settleAccounts(vaultBuybackToken, {
+            merchantDayStats: getDayStatsPDA(merchantId, currentDay() + 1), // tomorrow's bucket
+          }))
+          .signers([buyer])
+          .rpc();
+        expect.fail("Should have failed with InvalidDayStatsAccount");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(
+          errorMsg.includes("InvalidDayStatsAccount") ||
+          errorMsg.includes("current UTC day")
+        ).to.be.true;
+        console.log("✅ Settlement must record into today's bucket");
+      }
+    });
+
+    it("Records a settlement into today's pre-opened bucket", async () => {
+      const day = currentDay();
+      try {
+        await openDayStats(day);
+      } catch (err: any) {
+        // Bucket might already exist from an earlier run today
+        if (!(err.message && err.message.includes("already in use"))) {
+          throw err;
+        }
+      }
+      const vaultBuybackToken = await createAccount(
+        provider.connection,
+        merchantOwner,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+      const before = await program.account.merchantDayStats.fetch(getDayStatsPDA(merchantId, day));
+      const amount = 1_000_000; // 1 USDC
+
+      await program.methods
+        .settle(
+          merchantId,
+          new anchor.BN(amount),
+          { usdc: {} },
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          merchantDayStats: getDayStatsPDA(merchantId, day),
+        }))
+        .signers([buyer])
+        .rpc();
+
+      const after = await program.account.merchantDayStats.fetch(getDayStatsPDA(merchantId, day));
+      expect(after.paymentCount.toNumber()).to.equal(before.paymentCount.toNumber() + 1);
+      expect(after.volumeUsdc.toNumber()).to.equal(before.volumeUsdc.toNumber() + amount);
+      expect(after.rentPayer.toBase58()).to.equal(protocolAuthority.publicKey.toBase58());
+      console.log("✅ Settlement recorded into the crank-funded bucket");
+    });
+  });
+
+  describe("Payment Limits", () => {
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    const solLimits = (minAmount: number, maxAmount: number, dailyCap: number) => ({
+      minAmount: new anchor.BN(minAmount),
+      maxAmount: new anchor.BN(maxAmount),
+      dailyCap: new anchor.BN(dailyCap),
+    });
+    const noLimits = solLimits(0, 0, 0);
+
+    before(async () => {
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const initLimits = (authority: Keypair) =>
+      program.methods
+        .initMerchantLimits()
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          merchantRegistry: merchantRegistryPDA,
+          merchantLimits: limitsPDA,
+          authority: authority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([authority])
+        .rpc();
+
+    const updateLimits = (sol: ReturnType<typeof solLimits>) =>
+      program.methods
+        .updateMerchantLimits(sol, noLimits)
+        .accounts({
+          merchantLimits: limitsPDA,
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    const settleSol = (lamports: number, merchantLimits: PublicKey | null) =>
+      program.methods
+        .settle(
+          merchantId,
+          new anchor.BN(lamports),
+          { sol: {} },
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          merchantLimits,
+        }))
+        .signers([buyer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to create limits as neither owner nor protocol authority", async () => {
+      await expectError(initLimits(buyer), "Unauthorized");
+      console.log("✅ Only the owner or protocol authority may enable limits");
+    });
+
+    it("Creates limits and requires them in settlement", async () => {
+      await initLimits(merchantOwner);
+
+      const merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.limitsEnabled).to.be.true;
+
+      await expectError(settleSol(LAMPORTS_PER_SOL / 100, null), "MerchantLimitsRequired");
+      console.log("✅ Limits account cannot be omitted once enabled");
+    });
+
+    it("Fails to set a minimum above the maximum", async () => {
+      await expectError(updateLimits(solLimits(2 * LAMPORTS_PER_SOL, LAMPORTS_PER_SOL, 0)), "InvalidLimits");
+      console.log("✅ Inconsistent limits rejected");
+    });
+
+    it("Enforces the merchant's min and max payment amounts", async () => {
+      await updateLimits(solLimits(LAMPORTS_PER_SOL / 10, LAMPORTS_PER_SOL / 2, 0));
+
+      await expectError(settleSol(LAMPORTS_PER_SOL / 100, limitsPDA), "PaymentBelowMinimum");
+      await expectError(settleSol(LAMPORTS_PER_SOL, limitsPDA), "PaymentAboveMaximum");
+      console.log("✅ Payment amount bounds enforced");
+    });
+
+    it("Enforces a protocol daily cap the merchant cannot raise", async () => {
+      await program.methods
+        .setProtocolLimits(solLimits(0, 0, LAMPORTS_PER_SOL / 10), noLimits)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          merchantLimits: limitsPDA,
+          authority: protocolAuthority.publicKey,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+      // Merchant lifts its own limits; the protocol ceiling still applies
+      await updateLimits(solLimits(0, 0, 100 * LAMPORTS_PER_SOL));
+      await expectError(settleSol(LAMPORTS_PER_SOL / 5, limitsPDA), "DailyCapExceeded");
+
+      const limits = await program.account.merchantLimits.fetch(limitsPDA);
+      expect(limits.protocolSol.dailyCap.toNumber()).to.equal(LAMPORTS_PER_SOL / 10);
+      console.log("✅ Protocol daily cap enforced over merchant limits");
+    });
+  });
+
+  describe("Payer Lists", () => {
+    let protocolEntryPDA: PublicKey;
+    let merchantEntryPDA: PublicKey;
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [protocolEntryPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payer_list"), buyer.publicKey.toBuffer()],
+        programId
+      );
+      [merchantEntryPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payer_list"), merchantId.toArrayLike(Buffer, "le", 8), buyer.publicKey.toBuffer()],
+        programId
+      );
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    // The protocol denylist is global: never leave the test buyer on it
+    after(async () => {
+      const entry = await provider.connection.getAccountInfo(protocolEntryPDA);
+      if (entry) {
+        await removeProtocolEntry();
+      }
+    });
+
+    const removeProtocolEntry = () =>
+      program.methods
+        .removeProtocolDenylistEntry()
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          payerListEntry: protocolEntryPDA,
+          authority: protocolAuthority.publicKey,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+    const addMerchantEntry = (kind: object) =>
+      program.methods
+        .addPayerListEntry(buyer.publicKey, kind)
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          payerListEntry: merchantEntryPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    const removeMerchantEntry = () =>
+      program.methods
+        .removePayerListEntry()
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          payerListEntry: merchantEntryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    const setAllowlist = (enabled: boolean) =>
+      program.methods
+        .setPayerAllowlist(enabled)
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    const settleSol = (protocolPayerEntry: PublicKey | null, merchantPayerEntry: PublicKey | null) =>
+      program.methods
+        .settle(
+          merchantId,
+          new anchor.BN(LAMPORTS_PER_SOL / 100),
+          { sol: {} },
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          merchantLimits: limitsPDA,
+          protocolPayerEntry,
+          merchantPayerEntry,
+        }))
+        .signers([buyer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to add a protocol denylist entry as non-authority", async () => {
+      await expectError(
+        program.methods
+          .addProtocolDenylistEntry(buyer.publicKey)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            payerListEntry: protocolEntryPDA,
+            authority: merchantOwner.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchantOwner])
+          .rpc(),
+        "ConstraintHasOne"
+      );
+      console.log("✅ Only the protocol authority may edit the protocol denylist");
+    });
+
+    it("Blocks a payer on the protocol denylist", async () => {
+      await program.methods
+        .addProtocolDenylistEntry(buyer.publicKey)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          payerListEntry: protocolEntryPDA,
+          authority: protocolAuthority.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([protocolAuthority])
+        .rpc();
+
+      const entry = await program.account.payerListEntry.fetch(protocolEntryPDA);
+      expect(entry.merchantId).to.be.null;
+      expect(entry.kind).to.deep.equal({ deny: {} });
+
+      await expectError(settleSol(null, null), "PayerListEntryRequired");
+      await expectError(settleSol(protocolEntryPDA, null), "PayerDenied");
+
+      await removeProtocolEntry();
+      const config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.deniedPayers).to.equal(0);
+      console.log("✅ Protocol denylist enforced and entry removed");
+    });
+
+    it("Blocks a payer on the merchant denylist", async () => {
+      await addMerchantEntry({ deny: {} });
+      let merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.deniedPayers).to.equal(1);
+
+      await expectError(settleSol(null, merchantEntryPDA), "PayerDenied");
+
+      await removeMerchantEntry();
+      merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.deniedPayers).to.equal(0);
+      console.log("✅ Merchant denylist enforced and entry removed");
+    });
+
+    it("Blocks a delegate paying from a denylisted owner's USDC account", async () => {
+      const delegate = Keypair.generate();
+      const buyerUsdc = getAssociatedTokenAddressSync(usdcMint, buyer.publicKey);
+      const [delegateEntryPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("payer_list"), merchantId.toArrayLike(Buffer, "le", 8), delegate.publicKey.toBuffer()],
+        programId
+      );
+      await approve(provider.connection, protocolAuthority, buyerUsdc, delegate.publicKey, buyer, 1_000_000);
+      await addMerchantEntry({ deny: {} });
+
+      try {
+        await expectError(
+          program.methods
+            .settle(
+              merchantId,
+              new anchor.BN(1_000_000),
+              { usdc: {} },
+              settleArgs()
+            )
+            .accounts(settleAccounts(vaultBuybackToken, {
+              payer: delegate.publicKey,
+              payerUsdcAccount: buyerUsdc,
+              merchantLimits: limitsPDA,
+              merchantPayerEntry: delegateEntryPDA,
+            }))
+            .signers([delegate])
+            .rpc(),
+          "PayerNotTokenOwner"
+        );
+      } finally {
+        await removeMerchantEntry();
+      }
+      console.log("✅ Delegates cannot pay around the owner's denylist entry");
+    });
+
+    it("Admits only allowlisted payers when the allowlist is on", async () => {
+      await setAllowlist(true);
+      await expectError(settleSol(null, null), "PayerListEntryRequired");
+      await expectError(settleSol(null, merchantEntryPDA), "PayerNotAllowlisted");
+
+      await addMerchantEntry({ allow: {} });
+      const entry = await program.account.payerListEntry.fetch(merchantEntryPDA);
+      expect(entry.merchantId.toString()).to.equal(merchantId.toString());
+      expect(entry.kind).to.deep.equal({ allow: {} });
+
+      await removeMerchantEntry();
+      await setAllowlist(false);
+      const merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.allowlistEnabled).to.be.false;
+      console.log("✅ Allowlist enforced");
+    });
+  });
+
+  describe("Payment Quotes", () => {
+    const quoteSigner = Keypair.generate();
+    const amount = new anchor.BN(LAMPORTS_PER_SOL / 100);
+    const NATIVE_MINT = new PublicKey("So11111111111111111111111111111111111111112");
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    after(async () => {
+      await setQuoteSigner(null);
+    });
+
+    const setQuoteSigner = (signer: PublicKey | null) =>
+      program.methods
+        .setQuoteSigner(signer)
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    // Signed message: "qantara-quote-v1" || borsh(PaymentQuote)
+    const quoteInstruction = (
+      signer: Keypair,
+      quote: { amount: anchor.BN; mint: PublicKey; orderReference: Buffer; expiresAt: number }
+    ) =>
+      Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: signer.secretKey,
+        message: Buffer.concat([
+          Buffer.from("qantara-quote-v1"),
+          merchantId.toArrayLike(Buffer, "le", 8),
+          quote.amount.toArrayLike(Buffer, "le", 8),
+          quote.mint.toBuffer(),
+          quote.orderReference,
+          new anchor.BN(quote.expiresAt).toArrayLike(Buffer, "le", 8),
+        ]),
+      });
+
+    const settleSol = (
+      orderReference: Buffer,
+      instructionsSysvar: PublicKey | null,
+      sponsor: null,
+      authorization: null,
+      escrowUsdc: null,
+      preInstructions: TransactionInstruction[] = []
+    ) =>
+      program.methods
+        .settle(
+          merchantId,
+          amount,
+          { sol: {} },
+          settleArgs({ orderReference: Array.from(orderReference) })
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          merchantLimits: limitsPDA,
+          instructionsSysvar,
+        }))
+        .preInstructions(preInstructions)
+        .signers([buyer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    const inOneHour = () => Math.floor(Date.now() / 1000) + 3600;
+
+    it("Sets a quote signer", async () => {
+      await setQuoteSigner(quoteSigner.publicKey);
+
+      const merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.quoteSigner.toString()).to.equal(quoteSigner.publicKey.toString());
+      console.log("✅ Quote signer set");
+    });
+
+    it("Requires a quote once a signer is set", async () => {
+      const orderReference = Keypair.generate().publicKey.toBuffer();
+
+      await expectError(settleSol(orderReference, null), "QuoteRequired");
+      await expectError(settleSol(orderReference, SYSVAR_INSTRUCTIONS_PUBKEY), "InvalidQuoteSignature");
+      console.log("✅ Settlement without a signed quote rejected");
+    });
+
+    it("Fails with a quote signed by another key", async () => {
+      const orderReference = Keypair.generate().publicKey.toBuffer();
+      const forged = quoteInstruction(Keypair.generate(), {
+        amount,
+        mint: NATIVE_MINT,
+        orderReference,
+        expiresAt: inOneHour(),
+      });
+
+      await expectError(settleSol(orderReference, SYSVAR_INSTRUCTIONS_PUBKEY, [forged]), "InvalidQuoteSignature");
+      console.log("✅ Forged quote rejected");
+    });
+
+    it("Fails when the payment does not match the quote", async () => {
+      const orderReference = Keypair.generate().publicKey.toBuffer();
+      const underpaid = quoteInstruction(quoteSigner, {
+        amount: amount.muln(2),
+        mint: NATIVE_MINT,
+        orderReference,
+        expiresAt: inOneHour(),
+      });
+      const otherOrder = quoteInstruction(quoteSigner, {
+        amount,
+        mint: NATIVE_MINT,
+        orderReference: Keypair.generate().publicKey.toBuffer(),
+        expiresAt: inOneHour(),
+      });
+
+      await expectError(settleSol(orderReference, SYSVAR_INSTRUCTIONS_PUBKEY, [underpaid]), "QuoteMismatch");
+      await expectError(settleSol(orderReference, SYSVAR_INSTRUCTIONS_PUBKEY, [otherOrder]), "QuoteMismatch");
+      console.log("✅ Underpayment against a quote rejected");
+    });
+
+    it("Fails with an expired quote", async () => {
+      const orderReference = Keypair.generate().publicKey.toBuffer();
+      const expired = quoteInstruction(quoteSigner, {
+        amount,
+        mint: NATIVE_MINT,
+        orderReference,
+        expiresAt: Math.floor(Date.now() / 1000) - 60,
+      });
+
+      await expectError(settleSol(orderReference, SYSVAR_INSTRUCTIONS_PUBKEY, [expired]), "QuoteExpired");
+      console.log("✅ Expired quote rejected");
+    });
+  });
+
+  describe("Sponsored Payments", () => {
+    const customer = Keypair.generate(); // Holds USDC but no SOL
+    let customerUsdc: PublicKey;
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+      customerUsdc = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        usdcMint,
+        customer.publicKey,
+        Keypair.generate()
+      );
+    });
+
+    const settleSponsored = (sponsor: Keypair | null) =>
+      program.methods
+        .settleSponsored(
+          merchantId,
+          new anchor.BN(1_000_000), // 1 USDC
+          customer.publicKey, // beneficiary
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          payer: customer.publicKey,
+          merchantLimits: limitsPDA,
+          sponsor: sponsor ? sponsor.publicKey : null,
+          payerUsdcAccount: customerUsdc,
+        }))
+        .signers(sponsor ? [customer, sponsor] : [customer])
+        .rpc();
+
+    it("Fails without a sponsor", async () => {
+      try {
+        await settleSponsored(null);
+        expect.fail("Should have failed with SponsorRequired");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include("SponsorRequired");
+      }
+      console.log("✅ Sponsored settlement requires a sponsor");
+    });
+
+    it("Lets a payer without SOL reach the USDC transfer", async () => {
+      // Fees come from the provider wallet (relayer), rent from the sponsor; the customer
+      // has no SOL, so the only failure left is its empty USDC account
+      const balance = await provider.connection.getBalance(customer.publicKey);
+      expect(balance).to.equal(0);
+
+      try {
+        await settleSponsored(protocolAuthority);
+        expect.fail("Should have failed on the empty USDC account");
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.not.include("SponsorRequired");
+        expect(errorMsg).to.match(/insufficient funds|0x1\b/i);
+      }
+      console.log("✅ Customer without SOL only needs USDC");
+    });
+  });
+
+  describe("Authorizations", () => {
+    const authorizationId = Keypair.generate().publicKey.toBuffer();
+    const amount = new anchor.BN(LAMPORTS_PER_SOL / 100);
+    let authorizationPDA: PublicKey;
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [authorizationPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("authorization"), merchantId.toArrayLike(Buffer, "le", 8), authorizationId],
+        programId
+      );
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const authorizeSol = (id: Buffer, expiresAt: number) => {
+      const [authorization] = PublicKey.findProgramAddressSync(
+        [Buffer.from("authorization"), merchantId.toArrayLike(Buffer, "le", 8), id],
+        programId
+      );
+      return program.methods
+        .authorize(Array.from(id), amount, { sol: {} }, new anchor.BN(expiresAt))
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          merchantRegistry: merchantRegistryPDA,
+          authorization,
+          escrowUsdc: null,
+          usdcMint: null,
+          payerUsdcAccount: null,
+          protocolPayerEntry: null,
+          merchantPayerEntry: null,
+          payer: buyer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+    };
+
+    const voidAuthorization = (authority: Keypair) =>
+      program.methods
+        .voidAuthorization()
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          authorization: authorizationPDA,
+          payer: buyer.publicKey,
+          escrowUsdc: null,
+          payerUsdcAccount: null,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([authority])
+        .rpc();
+
+    const capture = (signer: Keypair, captureAmount: anchor.BN) =>
+      program.methods
+        .capture(
+          merchantId,
+          captureAmount,
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          payer: signer.publicKey,
+          merchantLimits: limitsPDA,
+          authorization: authorizationPDA,
+        }))
+        .signers([signer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to authorize with an expiry in the past", async () => {
+      await expectError(
+        authorizeSol(Keypair.generate().publicKey.toBuffer(), Math.floor(Date.now() / 1000) - 60),
+        "InvalidAuthorizationExpiry"
+      );
+      console.log("✅ Expired authorization rejected");
+    });
+
+    it("Holds the payer's SOL in the authorization", async () => {
+      const before = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      await authorizeSol(authorizationId, Math.floor(Date.now() / 1000) + 3600);
+
+      const authorization = await program.account.authorization.fetch(authorizationPDA);
+      expect(authorization.amount.toString()).to.equal(amount.toString());
+      expect(authorization.payer.toString()).to.equal(buyer.publicKey.toString());
+      expect(authorization.captured).to.be.false;
+
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(
+        (await provider.connection.getAccountInfo(authorizationPDA))!.data.length
+      );
+      const lamports = await provider.connection.getBalance(authorizationPDA);
+      expect(lamports).to.equal(rent + amount.toNumber());
+
+      const after = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(after.openAuthorizations).to.equal(before.openAuthorizations + 1);
+      console.log("✅ Funds held in escrow");
+    });
+
+    it("Fails to capture as anyone but the merchant owner", async () => {
+      await expectError(capture(buyer, amount), "Unauthorized");
+      console.log("✅ Only the merchant can capture");
+    });
+
+    it("Fails to capture more than was authorized", async () => {
+      await expectError(capture(merchantOwner, amount.addn(1)), "CaptureExceedsAuthorization");
+      console.log("✅ Over-capture rejected");
+    });
+
+    it("Fails when the payer voids an active authorization", async () => {
+      await expectError(voidAuthorization(buyer), "AuthorizationActive");
+      console.log("✅ Payer cannot void before expiry");
+    });
+
+    it("Merchant voids and the payer is refunded", async () => {
+      const payerBefore = await provider.connection.getBalance(buyer.publicKey);
+      const held = await provider.connection.getBalance(authorizationPDA);
+
+      await voidAuthorization(merchantOwner);
+
+      const payerAfter = await provider.connection.getBalance(buyer.publicKey);
+      expect(payerAfter - payerBefore).to.equal(held);
+      expect(await provider.connection.getAccountInfo(authorizationPDA)).to.be.null;
+
+      const merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.openAuthorizations).to.equal(0);
+      console.log("✅ Void refunded the hold and rent");
+    });
+  });
+
+  describe("Installment Plans", () => {
+    const planId = Keypair.generate().publicKey.toBuffer();
+    const overduePlanId = Keypair.generate().publicKey.toBuffer();
+    const installmentAmount = new anchor.BN(LAMPORTS_PER_SOL / 100);
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    const getPlanPDA = (id: Buffer) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("installments"), merchantId.toArrayLike(Buffer, "le", 8), id],
+        programId
+      )[0];
+
+    before(async () => {
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const createPlan = (id: Buffer, installmentCount: number, firstDueAt: number, gracePeriodSecs: number) =>
+      program.methods
+        .createInstallmentPlan(Array.from(id), buyer.publicKey, {
+          payToken: { sol: {} },
+          installmentAmount,
+          installmentCount,
+          firstDueAt: new anchor.BN(firstDueAt),
+          intervalSecs: new anchor.BN(30 * 86400),
+          gracePeriodSecs: new anchor.BN(gracePeriodSecs),
+        })
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          installmentPlan: getPlanPDA(id),
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    const markDefault = (id: Buffer) =>
+      program.methods
+        .markInstallmentDefault()
+        .accounts({ installmentPlan: getPlanPDA(id) })
+        .rpc();
+
+    const settleInstallment = (signer: Keypair) =>
+      program.methods
+        .settleInstallment(
+          merchantId,
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          payer: signer.publicKey,
+          merchantLimits: limitsPDA,
+          installmentPlan: getPlanPDA(planId),
+          payerUsdcAccount: getAssociatedTokenAddressSync(usdcMint, signer.publicKey),
+        }))
+        .signers([signer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to create a plan with a single installment", async () => {
+      await expectError(
+        createPlan(Keypair.generate().publicKey.toBuffer(), 1, Math.floor(Date.now() / 1000), 0),
+        "InvalidInstallmentTerms"
+      );
+      console.log("✅ Single-installment plan rejected");
+    });
+
+    it("Creates an active plan for the buyer", async () => {
+      const { openInstallmentPlans } = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      await createPlan(planId, 3, Math.floor(Date.now() / 1000) + 86400, 7 * 86400);
+
+      const plan = await program.account.installmentPlan.fetch(getPlanPDA(planId));
+      expect(plan.payer.toString()).to.equal(buyer.publicKey.toString());
+      expect(plan.terms.installmentCount).to.equal(3);
+      expect(plan.paidCount).to.equal(0);
+      expect(plan.status).to.deep.equal({ active: {} });
+      const merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.openInstallmentPlans).to.equal(openInstallmentPlans + 1);
+      console.log("✅ Installment plan created");
+    });
+
+    it("Fails to pay an installment from another wallet", async () => {
+      await expectError(settleInstallment(merchantOwner), "InstallmentPayerMismatch");
+      console.log("✅ Only the plan's payer can pay");
+    });
+
+    it("Fails to default a plan that is not overdue", async () => {
+      await expectError(markDefault(planId), "InstallmentNotOverdue");
+      console.log("✅ Plan within schedule cannot default");
+    });
+
+    it("Defaults a plan past its grace period", async () => {
+      await createPlan(overduePlanId, 2, Math.floor(Date.now() / 1000) - 3600, 60);
+      await markDefault(overduePlanId);
+
+      const plan = await program.account.installmentPlan.fetch(getPlanPDA(overduePlanId));
+      expect(plan.status).to.deep.equal({ defaulted: {} });
+
+      await expectError(markDefault(overduePlanId), "InstallmentPlanDefaulted");
+      console.log("✅ Overdue plan defaulted once");
+    });
+
+    it("Merchant closes a defaulted plan", async () => {
+      const { openInstallmentPlans } = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      await program.methods
+        .closeInstallmentPlan()
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          installmentPlan: getPlanPDA(overduePlanId),
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(getPlanPDA(overduePlanId))).to.be.null;
+      const merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.openInstallmentPlans).to.equal(openInstallmentPlans - 1);
+      console.log("✅ Plan closed and rent returned");
+    });
+  });
+
+  describe("Group Invoices", () => {
+    const invoiceId = Keypair.generate().publicKey.toBuffer();
+    const target = new anchor.BN(LAMPORTS_PER_SOL / 50);
+    const share = new anchor.BN(LAMPORTS_PER_SOL / 100);
+    let invoicePDA: PublicKey;
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    const getContributionPDA = (contributor: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("contribution"), invoicePDA.toBuffer(), contributor.toBuffer()],
+        programId
+      )[0];
+
+    before(async () => {
+      [invoicePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("group_invoice"), merchantId.toArrayLike(Buffer, "le", 8), invoiceId],
+        programId
+      );
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const createInvoice = (id: Buffer, targetAmount: anchor.BN, expiresAt: number) => {
+      const [groupInvoice] = PublicKey.findProgramAddressSync(
+        [Buffer.from("group_invoice"), merchantId.toArrayLike(Buffer, "le", 8), id],
+        programId
+      );
+      return program.methods
+        .createGroupInvoice(Array.from(id), { sol: {} }, targetAmount, new anchor.BN(expiresAt))
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          merchantRegistry: merchantRegistryPDA,
+          groupInvoice,
+          escrowUsdc: null,
+          usdcMint: null,
+          owner: merchantOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+    };
+
+    const contribute = (contributor: Keypair, amount: anchor.BN) =>
+      program.methods
+        .contribute(amount)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          merchantRegistry: merchantRegistryPDA,
+          groupInvoice: invoicePDA,
+          contribution: getContributionPDA(contributor.publicKey),
+          escrowUsdc: null,
+          contributorUsdcAccount: null,
+          protocolPayerEntry: null,
+          merchantPayerEntry: null,
+          contributor: contributor.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([contributor])
+        .rpc();
+
+    const settleGroupInvoice = (signer: Keypair) =>
+      program.methods
+        .settleGroupInvoice(
+          merchantId,
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          payer: signer.publicKey,
+          merchantLimits: limitsPDA,
+          groupInvoice: invoicePDA,
+          payerUsdcAccount: getAssociatedTokenAddressSync(usdcMint, signer.publicKey),
+        }))
+        .signers([signer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to create an invoice with a zero target", async () => {
+      await expectError(
+        createInvoice(
+          Keypair.generate().publicKey.toBuffer(),
+          new anchor.BN(0),
+          Math.floor(Date.now() / 1000) + 3600
+        ),
+        "InvalidGroupInvoiceTerms"
+      );
+      console.log("✅ Zero-target invoice rejected");
+    });
+
+    it("Fails to create a USDC invoice with a mint other than the protocol's", async () => {
+      const id = Keypair.generate().publicKey.toBuffer();
+      const [groupInvoice] = PublicKey.findProgramAddressSync(
+        [Buffer.from("group_invoice"), merchantId.toArrayLike(Buffer, "le", 8), id],
+        programId
+      );
+      const [escrowUsdc] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), groupInvoice.toBuffer()],
+        programId
+      );
+      await expectError(
+        program.methods
+          .createGroupInvoice(
+            Array.from(id),
+            { usdc: {} },
+            new anchor.BN(1_000_000),
+            new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+          )
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            merchantRegistry: merchantRegistryPDA,
+            groupInvoice,
+            escrowUsdc,
+            usdcMint: buybackMint,
+            owner: merchantOwner.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([merchantOwner])
+          .rpc(),
+        "InvalidUsdcMint"
+      );
+      console.log("✅ Group invoice escrow pinned to the protocol USDC mint");
+    });
+
+    it("Creates an open group invoice", async () => {
+      const before = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      await createInvoice(invoiceId, target, Math.floor(Date.now() / 1000) + 3600);
+
+      const invoice = await program.account.groupInvoice.fetch(invoicePDA);
+      expect(invoice.targetAmount.toString()).to.equal(target.toString());
+      expect(invoice.fundedAmount.toNumber()).to.equal(0);
+      expect(invoice.settled).to.be.false;
+
+      const after = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(after.openGroupInvoices).to.equal(before.openGroupInvoices + 1);
+      console.log("✅ Group invoice created");
+    });
+
+    it("Records repeat contributions from one payer in a single record", async () => {
+      const half = share.divn(2);
+      await contribute(buyer, half);
+      await contribute(buyer, share.sub(half));
+
+      const contribution = await program.account.contribution.fetch(
+        getContributionPDA(buyer.publicKey)
+      );
+      expect(contribution.amount.toString()).to.equal(share.toString());
+
+      const invoice = await program.account.groupInvoice.fetch(invoicePDA);
+      expect(invoice.fundedAmount.toString()).to.equal(share.toString());
+      expect(invoice.contributorCount).to.equal(1);
+      console.log("✅ Contributions accumulated");
+    });
+
+    it("Fails to contribute more than the unfunded remainder", async () => {
+      await expectError(contribute(protocolAuthority, share.addn(1)), "InvalidContributionAmount");
+      console.log("✅ Overfunding rejected");
+    });
+
+    it("Second payer funds the rest", async () => {
+      await contribute(protocolAuthority, share);
+
+      const invoice = await program.account.groupInvoice.fetch(invoicePDA);
+      expect(invoice.fundedAmount.toString()).to.equal(target.toString());
+      expect(invoice.contributorCount).to.equal(2);
+      console.log("✅ Invoice fully funded");
+    });
+
+    it("Fails to reclaim a contribution while the invoice is open", async () => {
+      await expectError(
+        program.methods
+          .reclaimContribution()
+          .accounts({
+            groupInvoice: invoicePDA,
+            contribution: getContributionPDA(buyer.publicKey),
+            contributor: buyer.publicKey,
+            escrowUsdc: null,
+            contributorUsdcAccount: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc(),
+        "GroupInvoiceOpen"
+      );
+      console.log("✅ Funds locked until settled or expired");
+    });
+
+    it("Fails to settle as anyone but the merchant owner", async () => {
+      await expectError(settleGroupInvoice(buyer), "Unauthorized");
+      console.log("✅ Only the merchant can settle");
+    });
+
+    it("Fails to close an invoice with contributions outstanding", async () => {
+      await expectError(
+        program.methods
+          .closeGroupInvoice()
+          .accounts({
+            merchantRegistry: merchantRegistryPDA,
+            groupInvoice: invoicePDA,
+            escrowUsdc: null,
+            owner: merchantOwner.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([merchantOwner])
+          .rpc(),
+        "ContributionsOutstanding"
+      );
+      console.log("✅ Invoice outlives its contribution records");
+    });
+  });
+
+  describe("Donations", () => {
+    const minimum = new anchor.BN(LAMPORTS_PER_SOL / 100);
+    const noTerms = {
+      minAmount: new anchor.BN(0),
+      suggested: [0, 0, 0, 0].map((v) => new anchor.BN(v)),
+    };
+    let donationSettingsPDA: PublicKey;
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [donationSettingsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("donations"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const updateSettings = (enabled: boolean, sol: typeof noTerms) =>
+      program.methods
+        .updateDonationSettings(enabled, sol, noTerms)
+        .accounts({
+          donationSettings: donationSettingsPDA,
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+    const setVerified = (authority: Keypair, verified: boolean) =>
+      program.methods
+        .setVerifiedNonprofit(verified)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          merchantRegistry: merchantRegistryPDA,
+          authority: authority.publicKey,
+        })
+        .signers([authority])
+        .rpc();
+
+    const donateSol = (
+      amount: anchor.BN,
+      message: string | null,
+      donationSettings: PublicKey | null = donationSettingsPDA
+    ) =>
+      program.methods
+        .donate(
+          merchantId,
+          amount,
+          { sol: {} },
+          message,
+          settleArgs()
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          merchantLimits: limitsPDA,
+          donationSettings,
+        }))
+        .signers([buyer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to donate without donation settings", async () => {
+      await expectError(donateSol(minimum, null, null), "DonationSettingsRequired");
+      console.log("✅ Donation mode required");
+    });
+
+    it("Fails to donate while donations are disabled", async () => {
+      await program.methods
+        .initDonationSettings()
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          donationSettings: donationSettingsPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      await expectError(donateSol(minimum, null), "DonationsDisabled");
+      console.log("✅ Disabled donation mode rejects donations");
+    });
+
+    it("Fails to suggest a tier below the minimum", async () => {
+      await expectError(
+        updateSettings(true, {
+          minAmount: minimum,
+          suggested: [minimum.subn(1), minimum, minimum.muln(5), new anchor.BN(0)],
+        }),
+        "InvalidDonationSettings"
+      );
+      console.log("✅ Tiers below the minimum rejected");
+    });
+
+    it("Enables donations with a minimum and suggested tiers", async () => {
+      await updateSettings(true, {
+        minAmount: minimum,
+        suggested: [minimum, minimum.muln(5), minimum.muln(10), new anchor.BN(0)],
+      });
+
+      const settings = await program.account.donationSettings.fetch(donationSettingsPDA);
+      expect(settings.enabled).to.be.true;
+      expect(settings.sol.minAmount.toString()).to.equal(minimum.toString());
+      expect(settings.sol.suggested[2].toString()).to.equal(minimum.muln(10).toString());
+      console.log("✅ Donation mode enabled");
+    });
+
+    it("Fails to donate below the minimum", async () => {
+      await expectError(donateSol(minimum.subn(1), null), "DonationBelowMinimum");
+      console.log("✅ Small donation rejected");
+    });
+
+    it("Fails to donate with an oversized message", async () => {
+      await expectError(donateSol(minimum, "x".repeat(281)), "DonationMessageTooLong");
+      console.log("✅ Message length enforced");
+    });
+
+    it("Only the protocol authority marks verified nonprofits", async () => {
+      await expectError(setVerified(merchantOwner, true), "ConstraintHasOne");
+
+      await setVerified(protocolAuthority, true);
+      let merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.verifiedNonprofit).to.be.true;
+
+      await setVerified(protocolAuthority, false);
+      merchant = await program.account.merchantRegistry.fetch(merchantRegistryPDA);
+      expect(merchant.verifiedNonprofit).to.be.false;
+      console.log("✅ Nonprofit verification is admin-only");
+    });
+  });
+
+  describe("Tips", () => {
+    const staffPool = Keypair.generate();
+    const amount = new anchor.BN(LAMPORTS_PER_SOL / 100);
+    const tip = new anchor.BN(LAMPORTS_PER_SOL / 500);
+    let tipPolicyPDA: PublicKey;
+    let limitsPDA: PublicKey;
+    let vaultBuybackToken: PublicKey;
+
+    before(async () => {
+      [tipPolicyPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tips"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      [limitsPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("limits"), merchantId.toArrayLike(Buffer, "le", 8)],
+        programId
+      );
+      vaultBuybackToken = await createAccount(
+        provider.connection,
+        protocolAuthority,
+        buybackMint,
+        merchantRegistryPDA,
+        Keypair.generate()
+      );
+    });
+
+    const settleWithTip = (tipPolicy: PublicKey | null, tipWallet: PublicKey | null) =>
+      program.methods
+        .settle(
+          merchantId,
+          amount,
+          { sol: {} },
+          settleArgs({ tip })
+        )
+        .accounts(settleAccounts(vaultBuybackToken, {
+          merchantLimits: limitsPDA,
+          tipPolicy,
+          tipWallet,
+        }))
+        .signers([buyer])
+        .rpc();
+
+    const expectError = async (promise: Promise<unknown>, code: string) => {
+      try {
+        await promise;
+        expect.fail(`Should have failed with ${code}`);
+      } catch (err: any) {
+        const errorMsg = err?.message || err?.toString() || "";
+        expect(errorMsg).to.include(code);
+      }
+    };
+
+    it("Fails to tip a merchant without a tip policy", async () => {
+      await expectError(settleWithTip(null, staffPool.publicKey), "TipPolicyRequired");
+      console.log("✅ Tips require a tip policy");
+    });
+
+    it("Merchant sets a tip policy for the staff pool", async () => {
+      await program.methods
+        .initTipPolicy(staffPool.publicKey, false)
+        .accounts({
+          merchantRegistry: merchantRegistryPDA,
+          tipPolicy: tipPolicyPDA,
+          owner: merchantOwner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      const policy = await program.account.tipPolicy.fetch(tipPolicyPDA);
+      expect(policy.tipWallet.toString()).to.equal(staffPool.publicKey.toString());
+      expect(policy.feeOnTips).to.be.false;
+      console.log("✅ Tip policy created");
+    });
+
+    it("Fails to send a tip anywhere but the tip wallet", async () => {
+      await expectError(
+        settleWithTip(tipPolicyPDA, Keypair.generate().publicKey),
+        "InvalidTipWallet"
+      );
+      console.log("✅ Tip wallet enforced");
+    });
+
+    it("Fails to tip outside a plain settle", async () => {
+      await expectError(
+        program.methods
+          .settleUsd(
+            merchantId,
+            new anchor.BN(10_000_000), // usd_amount (6 decimals)
+            amount, // max_lamports
+            settleArgs({ tip })
+          )
+          .accounts(settleAccounts(vaultBuybackToken, {
+            merchantLimits: limitsPDA,
+            tipPolicy: tipPolicyPDA,
+            tipWallet: staffPool.publicKey,
+          }))
+          .signers([buyer])
+          .rpc(),
+        "UnsupportedSettleArg"
+      );
+      console.log("✅ Tips only ride on settle");
+    });
+
+    it("Merchant applies the protocol fee to tips", async () => {
+      await program.methods
+        .updateTipPolicy(staffPool.publicKey, true)
+        .accounts({
+          tipPolicy: tipPolicyPDA,
+          merchantRegistry: merchantRegistryPDA,
+          owner: merchantOwner.publicKey,
+        })
+        .signers([merchantOwner])
+        .rpc();
+
+      const policy = await program.account.tipPolicy.fetch(tipPolicyPDA);
+      expect(policy.feeOnTips).to.be.true;
+      console.log("✅ Tip fee policy updated");
+    });
+  });
+
   describe("Protocol Fee Enforcement", () => {
     it("Calculates and transfers protocol fee correctly", async () => {
       // This test verifies that protocol fee is calculated and transferred